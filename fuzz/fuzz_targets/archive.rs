@@ -0,0 +1,15 @@
+#![no_main]
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::ArchiveReader;
+use bin_serialization_rs_fuzz::Sample;
+
+fuzz_target!(|data: &[u8]| {
+    let mut archive = match ArchiveReader::new(Cursor::new(data)) {
+        Ok(archive) => archive,
+        Err(_) => return
+    };
+    for index in 0..archive.entries().len() {
+        let _ = archive.get_at::<Sample>(index);
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::{
+    BinaryReaderBigEndian, BinaryReaderLittleEndian, SerializationReflector, SizePolicy, ReflectorOptions
+};
+use bin_serialization_rs_fuzz::Sample;
+
+// Each node is a child count followed by that many u16 pointers to further nodes
+fn read_tree(reader: &mut BinaryReaderLittleEndian<Cursor<&[u8]>>) -> std::io::Result<()> {
+    reader.reflect_pointer_with(SizePolicy::U16, |r| {
+        let mut children = 0u8;
+        r.reflect_u8(&mut children)?;
+        for _ in 0..children {
+            read_tree(r)?;
+        }
+        Ok(())
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut cursor = Cursor::new(data);
+    let mut reader = BinaryReaderLittleEndian::new(&mut cursor);
+    let _ = read_tree(&mut reader);
+
+    let mut cursor = Cursor::new(data);
+    let mut reader = BinaryReaderBigEndian::with_options(&mut cursor, ReflectorOptions::canonical());
+    let _ = reader.reflect_pointer(&mut Sample::default(), SizePolicy::U32);
+
+    let mut cursor = Cursor::new(data);
+    let mut reader = BinaryReaderLittleEndian::new(&mut cursor);
+    let _ = reader.reflect_pointer(&mut Sample::default(), SizePolicy::U64);
+});
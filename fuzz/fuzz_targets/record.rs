@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::{Endianness, RecordReader};
+use bin_serialization_rs_fuzz::Sample;
+
+fuzz_target!(|data: &[u8]| {
+    for record in RecordReader::<_, Sample>::new(data, Endianness::LittleEndian) {
+        let _ = record;
+    }
+    for record in RecordReader::<_, Sample>::with_sync_marker(data, Endianness::BigEndian, b"\xA5\x5A") {
+        let _ = record;
+    }
+});
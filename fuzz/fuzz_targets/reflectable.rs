@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::{Reflectable, Endianness, ReflectorOptions, Versioned};
+use bin_serialization_rs_fuzz::Sample;
+
+fuzz_target!(|data: &[u8]| {
+    let canonical = ReflectorOptions::canonical();
+    for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+        if let Ok(mut sample) = Sample::deserialize(&mut &data[..], endianness) {
+            let _ = sample.serialize(&mut Vec::new(), endianness);
+        }
+        if let Ok(mut versioned) = Versioned::<Sample>::deserialize(&mut &data[..], endianness) {
+            let _ = versioned.serialize(&mut Vec::new(), endianness);
+        }
+        // Anything the canonical reader accepts must re-encode to exactly the bytes it consumed
+        if let Ok(mut sample) = Sample::deserialize_ext(&mut &data[..], endianness, canonical) {
+            let mut encoded = Vec::new();
+            sample.serialize_ext(&mut encoded, endianness, canonical).unwrap();
+            assert!(data.starts_with(&encoded));
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+use std::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::{Reflectable, Endianness, Schema, DynamicReader, DynamicWriter};
+
+// The schema and the data it describes both come from the input, one after the other
+fuzz_target!(|data: &[u8]| {
+    let mut stream = Cursor::new(data);
+    let schema = match Schema::deserialize(&mut stream, Endianness::LittleEndian) {
+        Ok(schema) => schema,
+        Err(_) => return
+    };
+    if let Ok(value) = DynamicReader::new(&mut stream, &schema, Default::default()).read() {
+        let _ = DynamicWriter::new(&mut Vec::new(), &schema, Default::default()).write(&value);
+    }
+});
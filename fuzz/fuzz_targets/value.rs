@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bin_serialization_rs::{Reflectable, TaggedReflectable, Endianness, Value, Patch, apply_patch};
+use bin_serialization_rs_fuzz::Sample;
+
+fuzz_target!(|data: &[u8]| {
+    for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+        if let Ok(mut value) = Value::deserialize(&mut &data[..], endianness) {
+            let _ = value.serialize(&mut Vec::new(), endianness);
+        }
+        if let Ok(patch) = Patch::deserialize(&mut &data[..], endianness) {
+            let _ = apply_patch(&mut Sample::default(), &patch);
+        }
+    }
+});
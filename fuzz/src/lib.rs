@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashSet};
+use bin_serialization_rs::{
+    Reflectable, VersionedReflectable, SerializationReflector, SizePolicy, CharEncoding, BitOrder,
+    ChecksumAlgorithm, CompressionCodec, Value
+};
+
+// Touches every reader path a derived type can reach, so one target covers the whole surface
+#[derive(Default, Clone, Debug)]
+pub struct Sample {
+    pub id: u64,
+    pub small: i8,
+    pub guid: u128,
+    pub offset: i128,
+    pub half: f32,
+    pub ratio: f32,
+    pub precise: f64,
+    pub letter: char,
+    pub wide_letter: char,
+    pub name: String,
+    pub short_name: String,
+    pub label: String,
+    pub title: String,
+    pub code: String,
+    pub flags: Vec<bool>,
+    pub packed: Vec<bool>,
+    pub samples: Vec<i16>,
+    pub words: Vec<u32>,
+    pub initials: Vec<char>,
+    pub children: Vec<Sample>,
+    pub shape: Value,
+    pub tags: HashSet<u32>,
+    pub lookup: BTreeMap<u16, Sample>,
+    pub revision: u32
+}
+
+impl Reflectable for Sample {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_magic(b"FZ")?;
+        reflector.reflect_u64(&mut self.id)?;
+        reflector.reflect_i8(&mut self.small)?;
+        reflector.reflect_u128(&mut self.guid)?;
+        reflector.reflect_i128(&mut self.offset)?;
+        reflector.reflect_f16(&mut self.half)?;
+        reflector.reflect_f32(&mut self.ratio)?;
+        reflector.reflect_f64(&mut self.precise)?;
+        reflector.reflect_char(&mut self.letter, CharEncoding::Utf8)?;
+        reflector.reflect_char(&mut self.wide_letter, CharEncoding::Utf32)?;
+        reflector.reflect_string(&mut self.name)?;
+        reflector.reflect_string_ext(&mut self.short_name, SizePolicy::U8)?;
+        reflector.reflect_cp866_string(&mut self.label)?;
+        reflector.reflect_cp866_zstring(&mut self.title)?;
+        reflector.reflect_cp866_zstring_ext(8, &mut self.code)?;
+        reflector.reflect_bool_array(&mut self.flags)?;
+        reflector.reflect_packed_bool_array_ext(&mut self.packed, BitOrder::MsbFirst, SizePolicy::U16)?;
+        reflector.align(4)?;
+        reflector.reflect_reserved(2, 0)?;
+        reflector.reflect_padding(1)?;
+        reflector.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| r.reflect_i16_array(&mut self.samples))?;
+        reflector.reflect_u32_array_ext(&mut self.words, SizePolicy::U16)?;
+        reflector.reflect_char_array(&mut self.initials, CharEncoding::Utf8)?;
+        reflector.reflect_compressed(CompressionCodec::Stored, |r| r.reflect_array_of_composites(&mut self.children))?;
+        reflector.reflect_tagged_composite_framed(&mut self.shape)?;
+        reflector.reflect_set(&mut self.tags)?;
+        reflector.reflect_map(&mut self.lookup)?;
+        reflector.reflect_since(2, &mut self.revision, 0)
+    }
+}
+
+impl VersionedReflectable for Sample {
+    const LATEST_VERSION: u32 = 2;
+
+    fn reflect_versioned<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        _version: u32,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        self.reflect(reflector)
+    }
+}
@@ -0,0 +1,251 @@
+//! A container for many `Reflectable` values in one seekable file.
+//!
+//! Layout, all integers little-endian:
+//!
+//! ```text
+//! header   "BSAR" magic, u32 format version (currently 1)
+//! payloads entry payloads back to back, each one a little-endian serialized value
+//! index    entry count as in `reflect_array_of_composites`, then for every entry:
+//!          name (`reflect_string`), u64 payload offset, u64 payload length, u32 CRC32 of the payload
+//! footer   u64 offset of the index, "BSAR" magic
+//! ```
+//!
+//! Offsets are counted from the start of the header. The reader only touches the header,
+//! the footer, the index and the payloads it is asked for.
+
+use std::collections::HashMap;
+use std::io::{Read, Write, Seek, SeekFrom};
+use crate::{
+    Reflectable, SerializationReflector, Endianness, ReflectError,
+    BinaryWriterLittleEndian, BinaryReaderLittleEndian, check, crc32
+};
+
+const ARCHIVE_MAGIC: &[u8] = b"BSAR";
+const ARCHIVE_VERSION: u32 = 1;
+const FOOTER_LENGTH: u64 = 12;
+
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+    pub checksum: u32
+}
+
+impl Reflectable for ArchiveEntry {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_string(&mut self.name)?;
+        reflector.reflect_u64(&mut self.offset)?;
+        reflector.reflect_u64(&mut self.length)?;
+        reflector.reflect_u32(&mut self.checksum)
+    }
+}
+
+pub struct ArchiveWriter<TStream: Write> {
+    stream: TStream,
+    position: u64,
+    entries: Vec<ArchiveEntry>,
+    names: HashMap<String, usize>
+}
+
+impl<TStream: Write> ArchiveWriter<TStream> {
+    pub fn new(mut stream: TStream) -> std::io::Result<Self> {
+        let mut version = ARCHIVE_VERSION;
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream);
+        serializer.reflect_magic(ARCHIVE_MAGIC)?;
+        serializer.reflect_u32(&mut version)?;
+        let position = serializer.position();
+        Ok(Self { stream, position, entries: Vec::new(), names: HashMap::new() })
+    }
+
+    pub fn add<T: Reflectable>(&mut self, name: &str, data: &mut T) -> std::io::Result<usize> {
+        check(
+            name.is_empty() || !self.names.contains_key(name),
+            "archive already contains an entry with this name"
+        )?;
+        let mut payload = Vec::new();
+        data.serialize(&mut payload, Endianness::LittleEndian)?;
+        self.stream.write_all(&payload)?;
+        self.entries.push(ArchiveEntry {
+            name: name.to_string(),
+            offset: self.position,
+            length: payload.len() as u64,
+            checksum: crc32(&payload)
+        });
+        self.position += payload.len() as u64;
+        let index = self.entries.len() - 1;
+        if !name.is_empty() {
+            self.names.entry(name.to_string()).or_insert(index);
+        }
+        Ok(index)
+    }
+
+    pub fn push<T: Reflectable>(&mut self, data: &mut T) -> std::io::Result<usize> {
+        self.add("", data)
+    }
+
+    pub fn finish(mut self) -> std::io::Result<TStream> {
+        let mut index_offset = self.position;
+        let mut serializer = BinaryWriterLittleEndian::new(&mut self.stream);
+        serializer.reflect_array_of_composites(&mut self.entries)?;
+        serializer.reflect_u64(&mut index_offset)?;
+        serializer.reflect_magic(ARCHIVE_MAGIC)?;
+        self.stream.flush()?;
+        Ok(self.stream)
+    }
+}
+
+pub struct ArchiveReader<TStream: Read + Seek> {
+    stream: TStream,
+    start: u64,
+    entries: Vec<ArchiveEntry>,
+    names: HashMap<String, usize>
+}
+
+impl<TStream: Read + Seek> ArchiveReader<TStream> {
+    pub fn new(mut stream: TStream) -> std::io::Result<Self> {
+        let start = stream.stream_position()?;
+        let end = stream.seek(SeekFrom::End(0))?;
+        check(end.saturating_sub(start) >= 8 + FOOTER_LENGTH, "archive is too short")?;
+
+        stream.seek(SeekFrom::Start(start))?;
+        let mut version = 0u32;
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        deserializer.reflect_magic(ARCHIVE_MAGIC)?;
+        deserializer.reflect_u32(&mut version)?;
+        check(version != 0, "archive version 0 does not exist")?;
+        if version > ARCHIVE_VERSION {
+            return Err(ReflectError::UnsupportedVersion { version, latest: ARCHIVE_VERSION }.into());
+        }
+
+        stream.seek(SeekFrom::End(-(FOOTER_LENGTH as i64)))?;
+        let mut index_offset = 0u64;
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        deserializer.reflect_u64(&mut index_offset)?;
+        deserializer.reflect_magic(ARCHIVE_MAGIC)?;
+        let index_end = end - start - FOOTER_LENGTH;
+        check(index_offset >= 8 && index_offset <= index_end, "archive index offset is out of range")?;
+
+        stream.seek(SeekFrom::Start(start + index_offset))?;
+        let mut index = (&mut stream).take(index_end - index_offset);
+        let mut entries: Vec<ArchiveEntry> = Vec::new();
+        BinaryReaderLittleEndian::new(&mut index)
+            .reflect_array_of_composites(&mut entries)?;
+        for entry in entries.iter() {
+            check(
+                entry.offset >= 8 &&
+                    entry.offset <= index_offset &&
+                    entry.length <= index_offset - entry.offset,
+                "archive entry is out of range"
+            )?;
+        }
+        let mut names = HashMap::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate().filter(|(_, entry)| !entry.name.is_empty()) {
+            names.entry(entry.name.clone()).or_insert(index);
+        }
+        Ok(Self { stream, start, entries, names })
+    }
+
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn read_raw(&mut self, index: usize) -> std::io::Result<Vec<u8>> {
+        let entry = self.entries.get(index)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, "no such archive entry"))?;
+        let mut payload = vec![0u8; entry.length as usize];
+        self.stream.seek(SeekFrom::Start(self.start + entry.offset))?;
+        self.stream.read_exact(&mut payload)?;
+        check(crc32(&payload) == entry.checksum, "archive entry checksum mismatch")?;
+        Ok(payload)
+    }
+
+    pub fn get_at<T: Reflectable>(&mut self, index: usize) -> std::io::Result<T> {
+        let payload = self.read_raw(index)?;
+        T::deserialize(&mut &payload[..], Endianness::LittleEndian)
+    }
+
+    pub fn get<T: Reflectable>(&mut self, name: &str) -> std::io::Result<T> {
+        let index = self.find(name)
+            .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, "no such archive entry"))?;
+        self.get_at(index)
+    }
+
+    pub fn into_inner(self) -> TStream {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::{ArchiveWriter, ArchiveReader, Reflectable, SerializationReflector};
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct Sprite {
+        name: String,
+        frames: Vec<u16>
+    }
+
+    impl Reflectable for Sprite {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string(&mut self.name)?;
+            reflector.reflect_u16_array(&mut self.frames)
+        }
+    }
+
+    fn build_archive() -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut hero = Sprite { name: "hero".to_string(), frames: vec![1, 2, 3] };
+        let mut orc = Sprite { name: "orc".to_string(), frames: vec![9] };
+        let mut palette = 51u32;
+        assert_eq!(writer.add("sprites/hero", &mut hero).unwrap(), 0);
+        assert_eq!(writer.add("sprites/orc", &mut orc).unwrap(), 1);
+        assert_eq!(writer.push(&mut palette).unwrap(), 2);
+        assert!(writer.add("sprites/orc", &mut orc).is_err());
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let bytes = build_archive();
+        assert_eq!(&bytes[..8], &[b'B', b'S', b'A', b'R', 1, 0, 0, 0]);
+        assert_eq!(&bytes[bytes.len() - 4..], b"BSAR");
+
+        let mut reader = ArchiveReader::new(Cursor::new(&bytes[..])).unwrap();
+        let names: Vec<&str> = reader.entries().iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["sprites/hero", "sprites/orc", ""]);
+        let orc: Sprite = reader.get("sprites/orc").unwrap();
+        assert_eq!(orc, Sprite { name: "orc".to_string(), frames: vec![9] });
+        assert_eq!(reader.get_at::<u32>(2).unwrap(), 51);
+        assert!(reader.get::<Sprite>("sprites/elf").is_err());
+        assert_eq!(reader.find(""), None);
+    }
+
+    #[test]
+    fn test_corrupted_archive() {
+        let mut bytes = build_archive();
+        bytes[9] ^= 0xFF;
+        let mut reader = ArchiveReader::new(Cursor::new(&bytes[..])).unwrap();
+        assert!(reader.get::<Sprite>("sprites/hero").is_err());
+        assert!(reader.get::<Sprite>("sprites/orc").is_ok());
+
+        let length = bytes.len();
+        bytes[length - 12] = 0xF0;
+        assert!(ArchiveReader::new(Cursor::new(&bytes[..])).is_err());
+
+        let mut bytes = build_archive();
+        bytes[4] = 0;
+        assert!(ArchiveReader::new(Cursor::new(&bytes[..])).is_err());
+    }
+}
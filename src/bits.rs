@@ -0,0 +1,163 @@
+use crate::{SerializationReflector, BitOrder, bit_mask, check};
+
+/// Packs bits into bytes on top of another reflector. A writer holds back its partial byte until
+/// `align_to_byte` or `finish`; dropping it flushes that byte too, but only `finish` reports write errors.
+#[must_use = "pending bits are only written once the bit reflector is finished or dropped"]
+pub struct BitReflector<'a, R: SerializationReflector> {
+    reflector: &'a mut R,
+    bit_order: BitOrder,
+    current: u8,
+    bit_count: usize
+}
+
+impl<'a, R: SerializationReflector> BitReflector<'a, R> {
+    pub fn new(reflector: &'a mut R, bit_order: BitOrder) -> Self {
+        Self {
+            reflector,
+            bit_order,
+            current: 0,
+            bit_count: 0
+        }
+    }
+
+    pub fn reflect_bit(&mut self, data: &mut bool) -> std::io::Result<()> {
+        let mask = bit_mask(self.bit_count, self.bit_order);
+        if self.reflector.is_reading() {
+            if self.bit_count == 0 {
+                self.reflector.reflect_u8(&mut self.current)?;
+            }
+            *data = self.current & mask != 0;
+        } else if *data {
+            self.current |= mask;
+        }
+        self.bit_count += 1;
+        if self.bit_count == 8 {
+            self.align_to_byte()?;
+        }
+        Ok(())
+    }
+
+    pub fn reflect_bits(&mut self, bit_count: u32, data: &mut u32) -> std::io::Result<()> {
+        check(bit_count <= 32, "bit field is wider than 32 bits")?;
+        check(
+            self.reflector.is_reading() || bit_count == 32 || *data >> bit_count == 0,
+            "value does not fit into bit field"
+        )?;
+        let mut value = 0u32;
+        for i in 0..bit_count {
+            let shift = match self.bit_order {
+                BitOrder::MsbFirst => bit_count - 1 - i,
+                BitOrder::LsbFirst => i
+            };
+            let mut bit = (*data >> shift) & 1 != 0;
+            self.reflect_bit(&mut bit)?;
+            if bit {
+                value |= 1 << shift;
+            }
+        }
+        *data = value;
+        Ok(())
+    }
+
+    pub fn reflect_bool_array(&mut self, data: &mut [bool]) -> std::io::Result<()> {
+        for bit in data.iter_mut() {
+            self.reflect_bit(bit)?;
+        }
+        Ok(())
+    }
+
+    pub fn align_to_byte(&mut self) -> std::io::Result<()> {
+        if self.bit_count != 0 && !self.reflector.is_reading() {
+            self.reflector.reflect_u8(&mut self.current)?;
+        }
+        self.current = 0;
+        self.bit_count = 0;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.align_to_byte()
+    }
+}
+
+impl<'a, R: SerializationReflector> Drop for BitReflector<'a, R> {
+    fn drop(&mut self) {
+        let _ = self.align_to_byte();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BitReflector, BitOrder, SerializationReflector, BinaryWriterLittleEndian, BinaryReaderLittleEndian};
+
+    #[test]
+    fn test_msb_first_fields() {
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        let mut bits = BitReflector::new(&mut serializer, BitOrder::MsbFirst);
+        bits.reflect_bits(3, &mut 0b101).unwrap();
+        bits.reflect_bits(5, &mut 0b00011).unwrap();
+        bits.reflect_bits(8, &mut 0).unwrap();
+        bits.reflect_bits(2, &mut 0b11).unwrap();
+        bits.finish().unwrap();
+        assert_eq!(stream_vec, vec![0b1010_0011, 0, 0b1100_0000]);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut bits = BitReflector::new(&mut deserializer, BitOrder::MsbFirst);
+        let (mut kind, mut level, mut reserved, mut tail) = (0, 0, 0, 0);
+        bits.reflect_bits(3, &mut kind).unwrap();
+        bits.reflect_bits(5, &mut level).unwrap();
+        bits.reflect_bits(8, &mut reserved).unwrap();
+        bits.reflect_bits(2, &mut tail).unwrap();
+        bits.finish().unwrap();
+        assert_eq!((kind, level, reserved, tail), (0b101, 0b00011, 0, 0b11));
+    }
+
+    #[test]
+    fn test_lsb_first_and_packed_bools() {
+        let mut flags = vec![true, false, false, true, true, false, false, false, false, true];
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        let mut bits = BitReflector::new(&mut serializer, BitOrder::LsbFirst);
+        bits.reflect_bits(4, &mut 0b0110).unwrap();
+        bits.align_to_byte().unwrap();
+        bits.reflect_bits(12, &mut 0xABC).unwrap();
+        bits.finish().unwrap();
+        serializer.reflect_packed_bool_array(&mut flags, BitOrder::LsbFirst).unwrap();
+        assert_eq!(stream_vec, vec![0b0110, 0xBC, 0x0A, 1, 10, 0b0001_1001, 0b10]);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut bits = BitReflector::new(&mut deserializer, BitOrder::LsbFirst);
+        let (mut low, mut wide) = (0, 0);
+        bits.reflect_bits(4, &mut low).unwrap();
+        bits.align_to_byte().unwrap();
+        bits.reflect_bits(12, &mut wide).unwrap();
+        bits.finish().unwrap();
+        let mut flags1 = Vec::new();
+        deserializer.reflect_packed_bool_array(&mut flags1, BitOrder::LsbFirst).unwrap();
+        assert_eq!((low, wide), (0b0110, 0xABC));
+        assert_eq!(flags, flags1);
+    }
+
+    #[test]
+    fn test_value_too_wide() {
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        let mut bits = BitReflector::new(&mut serializer, BitOrder::MsbFirst);
+        assert!(bits.reflect_bits(3, &mut 8).is_err());
+    }
+
+    #[test]
+    fn test_dropped_writer_flushes_pending_bits() {
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        {
+            let mut bits = BitReflector::new(&mut serializer, BitOrder::MsbFirst);
+            bits.reflect_bits(3, &mut 0b101).unwrap();
+        }
+        serializer.reflect_u8(&mut 7).unwrap();
+        assert_eq!(stream_vec, vec![0b1010_0000, 7]);
+    }
+}
@@ -0,0 +1,310 @@
+use std::hash::Hasher;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Crc32 {
+    state: u32
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self { state: 0xFFFFFFFF }
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.state = CRC32_TABLE[((self.state ^ *byte as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Adler32 {
+    a: u32,
+    b: u32
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self { a: 1, b: 0 }
+    }
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(5552) {
+            for byte in chunk {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= 65521;
+            self.b %= 65521;
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+const XXH_PRIME64_1: u64 = 0x9E3779B185EBCA87;
+const XXH_PRIME64_2: u64 = 0xC2B2AE3D27D4EB4F;
+const XXH_PRIME64_3: u64 = 0x165667B19E3779F9;
+const XXH_PRIME64_4: u64 = 0x85EBCA77C2B2AE63;
+const XXH_PRIME64_5: u64 = 0x27D4EB2F165667C5;
+
+fn xxh64_round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(accumulator: u64, value: u64) -> u64 {
+    (accumulator ^ xxh64_round(0, value))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    let mut lane = [0u8; 8];
+    lane.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(lane)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    let mut lane = [0u8; 4];
+    lane.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(lane)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct XxHash64 {
+    seed: u64,
+    accumulators: [u64; 4],
+    buffer: [u8; 32],
+    buffered: usize,
+    total_length: u64
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl XxHash64 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            accumulators: [
+                seed.wrapping_add(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_2),
+                seed.wrapping_add(XXH_PRIME64_2),
+                seed,
+                seed.wrapping_sub(XXH_PRIME64_1)
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            total_length: 0
+        }
+    }
+
+    fn consume_stripe(&mut self, stripe: &[u8]) {
+        for (i, accumulator) in self.accumulators.iter_mut().enumerate() {
+            *accumulator = xxh64_round(*accumulator, read_u64_le(&stripe[i * 8..]));
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_length += data.len() as u64;
+        if self.buffered > 0 {
+            let taken = data.len().min(32 - self.buffered);
+            self.buffer[self.buffered..self.buffered + taken].copy_from_slice(&data[..taken]);
+            self.buffered += taken;
+            data = &data[taken..];
+            if self.buffered < 32 {
+                return;
+            }
+            let stripe = self.buffer;
+            self.consume_stripe(&stripe);
+            self.buffered = 0;
+        }
+        while data.len() >= 32 {
+            self.consume_stripe(&data[..32]);
+            data = &data[32..];
+        }
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.buffered = data.len();
+    }
+
+    pub fn finish(&self) -> u64 {
+        let [v1, v2, v3, v4] = self.accumulators;
+        let mut hash = if self.total_length >= 32 {
+            let mut hash = v1.rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            for accumulator in self.accumulators.iter() {
+                hash = xxh64_merge_round(hash, *accumulator);
+            }
+            hash
+        } else {
+            self.seed.wrapping_add(XXH_PRIME64_5)
+        };
+        hash = hash.wrapping_add(self.total_length);
+
+        let mut tail = &self.buffer[..self.buffered];
+        while tail.len() >= 8 {
+            hash ^= xxh64_round(0, read_u64_le(tail));
+            hash = hash.rotate_left(27).wrapping_mul(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_4);
+            tail = &tail[8..];
+        }
+        if tail.len() >= 4 {
+            hash ^= (read_u32_le(tail) as u64).wrapping_mul(XXH_PRIME64_1);
+            hash = hash.rotate_left(23).wrapping_mul(XXH_PRIME64_2).wrapping_add(XXH_PRIME64_3);
+            tail = &tail[4..];
+        }
+        for byte in tail {
+            hash ^= (*byte as u64).wrapping_mul(XXH_PRIME64_5);
+            hash = hash.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(XXH_PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(XXH_PRIME64_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+impl Hasher for XxHash64 {
+    fn finish(&self) -> u64 {
+        XxHash64::finish(self)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Adler32,
+    XxHash64
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Checksum {
+    Crc32(Crc32),
+    Adler32(Adler32),
+    XxHash64(XxHash64)
+}
+
+impl Checksum {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => Checksum::Crc32(Crc32::new()),
+            ChecksumAlgorithm::Adler32 => Checksum::Adler32(Adler32::new()),
+            ChecksumAlgorithm::XxHash64 => Checksum::XxHash64(XxHash64::new())
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Crc32(crc) => crc.update(data),
+            Checksum::Adler32(adler) => adler.update(data),
+            Checksum::XxHash64(xxhash) => xxhash.update(data)
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        match self {
+            Checksum::Crc32(crc) => crc.finish() as u64,
+            Checksum::Adler32(adler) => adler.finish() as u64,
+            Checksum::XxHash64(xxhash) => xxhash.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{crc32, Adler32, XxHash64};
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_adler32_check_value() {
+        let mut adler = Adler32::new();
+        adler.update(b"Wiki");
+        adler.update(b"pedia");
+        assert_eq!(adler.finish(), 0x11E60398);
+    }
+
+    #[test]
+    fn test_xxhash64_check_values() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let expected = [
+            (0, 0xEF46DB3751D8E999),
+            (3, 0xE5C7BB4533BC65DD),
+            (31, 0xC346D2B59B4D8EE1),
+            (100, 0x6AC1E58032166597)
+        ];
+        for (length, hash) in expected.iter() {
+            let mut one_shot = XxHash64::new();
+            one_shot.update(&data[..*length]);
+            assert_eq!(one_shot.finish(), *hash);
+
+            let mut streamed = XxHash64::new();
+            for chunk in data[..*length].chunks(7) {
+                streamed.update(chunk);
+            }
+            assert_eq!(streamed.finish(), *hash);
+        }
+    }
+}
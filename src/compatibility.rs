@@ -0,0 +1,492 @@
+use crate::{Endianness, Layout, Schema, SizeEncoding, SizePolicy, Variant, ReflectorOptions};
+
+/// How a change affects data exchange: `BackwardCompatible` means the new layout still reads everything
+/// the old one wrote, while files written by the new layout may not be readable by the old one.
+/// `ForwardCompatible` is the reverse: the old layout reads what the new one writes, but not the other way round.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Compatibility {
+    Compatible,
+    BackwardCompatible,
+    ForwardCompatible,
+    Breaking
+}
+
+impl Compatibility {
+    // A backward-only change next to a forward-only one leaves neither direction readable
+    pub fn of(changes: &[SchemaChange]) -> Self {
+        changes.iter()
+            .map(|change| change.compatibility)
+            .fold(Compatibility::Compatible, |total, compatibility| match (total, compatibility) {
+                (Compatibility::Compatible, other) | (other, Compatibility::Compatible) => other,
+                (total, compatibility) if total == compatibility => total,
+                _ => Compatibility::Breaking
+            })
+    }
+}
+
+impl std::fmt::Display for Compatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compatibility::Compatible => write!(f, "compatible"),
+            Compatibility::BackwardCompatible => write!(f, "backward-compatible only"),
+            Compatibility::ForwardCompatible => write!(f, "forward-compatible only"),
+            Compatibility::Breaking => write!(f, "breaking")
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum LayoutChange {
+    Endianness { old: Endianness, new: Endianness },
+    Layout { old: Layout, new: Layout },
+    Renamed { old: String, new: String },
+    FieldAdded(Layout),
+    FieldRemoved(Layout),
+    SizeEncoding { old: SizeEncoding, new: SizeEncoding },
+    TagPolicy { old: SizePolicy, new: SizePolicy },
+    VariantAdded(u64),
+    VariantRemoved(u64),
+    VariantRenumbered { old: u64, new: u64 }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SchemaChange {
+    pub path: String,
+    pub change: LayoutChange,
+    pub compatibility: Compatibility
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.change {
+            LayoutChange::Endianness { old, new } => write!(f, "endianness {:?} -> {:?}", old, new),
+            LayoutChange::Layout { old, new } => write!(f, "{} -> {}", describe(old), describe(new)),
+            LayoutChange::Renamed { old, new } => write!(f, "renamed {} -> {}", old, new),
+            LayoutChange::FieldAdded(layout) => write!(f, "added {}", describe(layout)),
+            LayoutChange::FieldRemoved(layout) => write!(f, "removed {}", describe(layout)),
+            LayoutChange::SizeEncoding { old, new } =>
+                write!(f, "{} -> {}", describe_size(*old), describe_size(*new)),
+            LayoutChange::TagPolicy { old, new } => write!(f, "tag {:?} -> {:?}", old, new),
+            LayoutChange::VariantAdded(tag) => write!(f, "added variant #{}", tag),
+            LayoutChange::VariantRemoved(tag) => write!(f, "removed variant #{}", tag),
+            LayoutChange::VariantRenumbered { old, new } => write!(f, "variant #{} renumbered to #{}", old, new)
+        }?;
+        write!(f, " ({})", self.compatibility)
+    }
+}
+
+/// Size prefixes carry their own width tag, so a changed `SizePolicy` on an array or string stays readable
+/// both ways for lenient readers; the tag width of a `TaggedReflectable` does not and is reported as breaking.
+pub fn check_compatibility(old: &Schema, new: &Schema) -> Vec<SchemaChange> {
+    check_compatibility_ext(old, new, ReflectorOptions::default())
+}
+
+/// Readers with `strict_size_tags` or `canonical` set only accept the size tag their own layout would write,
+/// so for them a changed size encoding is breaking as well; the same goes for reserved bytes that changed value
+/// under `strict_reserved`, and for fields appended to a frame, which canonical readers refuse to skip.
+pub fn check_compatibility_ext(old: &Schema, new: &Schema, options: ReflectorOptions) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    let mut path = "$".to_string();
+    if old.endianness != new.endianness {
+        push(
+            &mut changes,
+            &path,
+            LayoutChange::Endianness { old: old.endianness, new: new.endianness },
+            Compatibility::Breaking
+        );
+    }
+    compare(&mut path, &old.root, &new.root, &mut changes);
+    let strict_sizes = options.strict_size_tags || options.canonical;
+    let strict_reserved = options.strict_reserved || options.canonical;
+    for change in changes.iter_mut() {
+        let strict = match change.change {
+            LayoutChange::SizeEncoding { .. } => strict_sizes,
+            LayoutChange::Layout { old: Layout::Reserved { .. }, .. } => strict_reserved,
+            LayoutChange::FieldAdded(_) => options.canonical,
+            _ => false
+        };
+        if strict {
+            change.compatibility = Compatibility::Breaking;
+        }
+    }
+    changes
+}
+
+fn push(changes: &mut Vec<SchemaChange>, path: &str, change: LayoutChange, compatibility: Compatibility) {
+    changes.push(SchemaChange { path: path.to_string(), change, compatibility });
+}
+
+fn compare(path: &mut String, old: &Layout, new: &Layout, changes: &mut Vec<SchemaChange>) {
+    match (old, new) {
+        (Layout::Struct { name: old_name, fields: old_fields }, Layout::Struct { name: new_name, fields: new_fields }) => {
+            compare_names(path, old_name, new_name, changes);
+            compare_fields(path, old_fields, new_fields, Compatibility::Breaking, changes);
+        },
+        (
+            Layout::Tagged { name: old_name, tag: old_tag, variants: old_variants },
+            Layout::Tagged { name: new_name, tag: new_tag, variants: new_variants }
+        ) => {
+            compare_names(path, old_name, new_name, changes);
+            if old_tag != new_tag {
+                push(changes, path, LayoutChange::TagPolicy { old: *old_tag, new: *new_tag }, Compatibility::Breaking);
+            }
+            compare_variants(path, old_variants, new_variants, changes);
+        },
+        (Layout::Array { size: old_size, element: old_element }, Layout::Array { size: new_size, element: new_element }) => {
+            compare_sizes(path, *old_size, *new_size, changes);
+            let length = path.len();
+            path.push_str("[]");
+            compare(path, old_element, new_element, changes);
+            path.truncate(length);
+        },
+        (Layout::PackedBools { size: old_size, order: old_order }, Layout::PackedBools { size: new_size, order: new_order }) => {
+            compare_sizes(path, *old_size, *new_size, changes);
+            if old_order != new_order {
+                push(changes, path, LayoutChange::Layout { old: old.clone(), new: new.clone() }, Compatibility::Breaking);
+            }
+        },
+        (Layout::String(old_size), Layout::String(new_size)) => compare_sizes(path, *old_size, *new_size, changes),
+        (Layout::Reserved { length: old_length, .. }, Layout::Reserved { length: new_length, .. })
+            if old_length == new_length && old != new =>
+            push(changes, path, LayoutChange::Layout { old: old.clone(), new: new.clone() }, Compatibility::Compatible),
+        // Old readers skip whatever is left of a frame, so fields appended to one only trip up new readers
+        (Layout::Frame(old_fields), Layout::Frame(new_fields)) =>
+            compare_fields(path, old_fields, new_fields, Compatibility::ForwardCompatible, changes),
+        (Layout::Encrypted(old_fields), Layout::Encrypted(new_fields)) =>
+            compare_fields(path, old_fields, new_fields, Compatibility::Breaking, changes),
+        (
+            Layout::Checksummed { algorithm: old_algorithm, fields: old_fields },
+            Layout::Checksummed { algorithm: new_algorithm, fields: new_fields }
+        ) if old_algorithm == new_algorithm =>
+            compare_fields(path, old_fields, new_fields, Compatibility::Breaking, changes),
+        (
+            Layout::Compressed { codec: old_codec, fields: old_fields },
+            Layout::Compressed { codec: new_codec, fields: new_fields }
+        ) if old_codec == new_codec => compare_fields(path, old_fields, new_fields, Compatibility::Breaking, changes),
+        (Layout::Recursive(_), Layout::Recursive(_)) => {},
+        _ if old != new =>
+            push(changes, path, LayoutChange::Layout { old: old.clone(), new: new.clone() }, Compatibility::Breaking),
+        _ => {}
+    }
+}
+
+fn compare_names(path: &str, old: &str, new: &str, changes: &mut Vec<SchemaChange>) {
+    if old != new {
+        push(
+            changes,
+            path,
+            LayoutChange::Renamed { old: old.to_string(), new: new.to_string() },
+            Compatibility::Compatible
+        );
+    }
+}
+
+fn compare_sizes(path: &str, old: SizeEncoding, new: SizeEncoding, changes: &mut Vec<SchemaChange>) {
+    if old != new {
+        push(changes, path, LayoutChange::SizeEncoding { old, new }, Compatibility::Compatible);
+    }
+}
+
+/// Fields are matched on their longest common run of identical layouts, and the fields left between two
+/// matches are compared pairwise, so that a single removed field is not reported as every later field changing.
+/// Fields added after the last old one are classified as `appended`.
+fn compare_fields(
+    path: &mut String,
+    old: &[Layout],
+    new: &[Layout],
+    appended: Compatibility,
+    changes: &mut Vec<SchemaChange>
+) {
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let (mut old_start, mut new_start) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            compare_unmatched(path, old, old_start..i, new, new_start..j, Compatibility::Breaking, changes);
+            i += 1;
+            j += 1;
+            old_start = i;
+            new_start = j;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    compare_unmatched(path, old, old_start..old.len(), new, new_start..new.len(), appended, changes);
+}
+
+fn compare_unmatched(
+    path: &mut String,
+    old: &[Layout],
+    old_range: std::ops::Range<usize>,
+    new: &[Layout],
+    new_range: std::ops::Range<usize>,
+    added: Compatibility,
+    changes: &mut Vec<SchemaChange>
+) {
+    let paired = old_range.len().min(new_range.len());
+    for (i, j) in old_range.clone().zip(new_range.clone()) {
+        let length = path.len();
+        path.push_str(&format!(".{}", i));
+        compare(path, &old[i], &new[j], changes);
+        path.truncate(length);
+    }
+    for i in old_range.skip(paired) {
+        push(changes, &format!("{}.{}", path, i), LayoutChange::FieldRemoved(old[i].clone()), Compatibility::Breaking);
+    }
+    for j in new_range.skip(paired) {
+        push(changes, &format!("{}.{}", path, j), LayoutChange::FieldAdded(new[j].clone()), added);
+    }
+}
+
+fn compare_variants(path: &mut String, old: &[Variant], new: &[Variant], changes: &mut Vec<SchemaChange>) {
+    let is_new_tag = |tag: u64| old.iter().all(|variant| variant.tag != tag);
+    let mut renumbered = Vec::new();
+    for old_variant in old {
+        match new.iter().find(|variant| variant.tag == old_variant.tag) {
+            Some(new_variant) => {
+                let length = path.len();
+                path.push_str(&format!(".#{}", old_variant.tag));
+                compare_fields(path, &old_variant.fields, &new_variant.fields, Compatibility::Breaking, changes);
+                path.truncate(length);
+            },
+            None => match new.iter().find(|variant|
+                is_new_tag(variant.tag) && !renumbered.contains(&variant.tag) && variant.fields == old_variant.fields
+            ) {
+                Some(new_variant) => {
+                    renumbered.push(new_variant.tag);
+                    push(
+                        changes,
+                        path,
+                        LayoutChange::VariantRenumbered { old: old_variant.tag, new: new_variant.tag },
+                        Compatibility::Breaking
+                    );
+                },
+                None => push(changes, path, LayoutChange::VariantRemoved(old_variant.tag), Compatibility::Breaking)
+            }
+        }
+    }
+    for new_variant in new {
+        if is_new_tag(new_variant.tag) && !renumbered.contains(&new_variant.tag) {
+            push(changes, path, LayoutChange::VariantAdded(new_variant.tag), Compatibility::BackwardCompatible);
+        }
+    }
+}
+
+fn describe_size(size: SizeEncoding) -> String {
+    match size {
+        SizeEncoding::Tagged => "tagged size".to_string(),
+        SizeEncoding::Fixed(size_policy) => format!("{:?} size", size_policy)
+    }
+}
+
+fn describe(layout: &Layout) -> String {
+    match layout {
+        Layout::U8 => "u8".to_string(),
+        Layout::U16 => "u16".to_string(),
+        Layout::U32 => "u32".to_string(),
+        Layout::U64 => "u64".to_string(),
+        Layout::U128 => "u128".to_string(),
+        Layout::I8 => "i8".to_string(),
+        Layout::I16 => "i16".to_string(),
+        Layout::I32 => "i32".to_string(),
+        Layout::I64 => "i64".to_string(),
+        Layout::I128 => "i128".to_string(),
+        Layout::F16 => "f16".to_string(),
+        Layout::F32 => "f32".to_string(),
+        Layout::F64 => "f64".to_string(),
+        Layout::Bool => "bool".to_string(),
+        Layout::Char(encoding) => format!("{:?} char", encoding),
+        Layout::String(size) => format!("string with {}", describe_size(*size)),
+        Layout::Cp866String => "cp866 string".to_string(),
+        Layout::Cp866ZString => "cp866 zstring".to_string(),
+        Layout::Cp866FixedZString(length) => format!("cp866 zstring of {} bytes", length),
+        Layout::Array { element, .. } => format!("array of {}", describe(element)),
+        Layout::PackedBools { order, .. } => format!("packed bools {:?}", order),
+        Layout::Struct { name, .. } => format!("struct {}", name),
+        Layout::Tagged { name, .. } => format!("tagged {}", name),
+        Layout::Recursive(name) => name.clone(),
+        Layout::Magic(magic) => format!("magic {:?}", String::from_utf8_lossy(magic)),
+        Layout::Reserved { length, byte } => format!("{} reserved bytes of {:#04x}", length, byte),
+        Layout::Align(alignment) => format!("align {}", alignment),
+        Layout::Frame(_) => "frame".to_string(),
+        Layout::Checksummed { algorithm, .. } => format!("{:?} checksummed section", algorithm),
+        Layout::Compressed { codec, .. } => format!("{:?} compressed section", codec),
+        Layout::Encrypted(_) => "encrypted section".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Endianness, Layout, Schema, SizeEncoding, SizePolicy, Variant, CharEncoding, Compatibility,
+        LayoutChange, SchemaChange, ReflectorOptions, check_compatibility, check_compatibility_ext
+    };
+
+    fn message(fields: Vec<Layout>, variants: Vec<Variant>) -> Layout {
+        Layout::Struct {
+            name: "Message".to_string(),
+            fields: vec![
+                Layout::Magic(b"MSG0".to_vec()),
+                Layout::Struct { name: "Header".to_string(), fields },
+                Layout::Array {
+                    size: SizeEncoding::Tagged,
+                    element: Box::new(Layout::Tagged { name: "Body".to_string(), tag: SizePolicy::U8, variants })
+                }
+            ]
+        }
+    }
+
+    fn change(path: &str, change: LayoutChange, compatibility: Compatibility) -> SchemaChange {
+        SchemaChange { path: path.to_string(), change, compatibility }
+    }
+
+    #[test]
+    fn test_identical_schemas_are_compatible() {
+        let schema = Schema::extract::<Schema>(Endianness::LittleEndian).unwrap();
+        let changes = check_compatibility(&schema, &schema);
+        assert!(changes.is_empty());
+        assert_eq!(Compatibility::of(&changes), Compatibility::Compatible);
+    }
+
+    #[test]
+    fn test_schema_changes_are_classified() {
+        let old = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(
+                vec![Layout::U16, Layout::String(SizeEncoding::Tagged), Layout::U32, Layout::Char(CharEncoding::Utf8)],
+                vec![
+                    Variant { tag: 0, fields: vec![] },
+                    Variant { tag: 1, fields: vec![Layout::I32] },
+                    Variant { tag: 2, fields: vec![Layout::F32, Layout::F32] },
+                    Variant { tag: 3, fields: vec![Layout::Cp866String] }
+                ]
+            )
+        };
+        let new = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(
+                vec![
+                    Layout::U16,
+                    Layout::String(SizeEncoding::Fixed(SizePolicy::U32)),
+                    Layout::Char(CharEncoding::Utf32)
+                ],
+                vec![
+                    Variant { tag: 0, fields: vec![] },
+                    Variant { tag: 1, fields: vec![Layout::I64] },
+                    Variant { tag: 4, fields: vec![Layout::F32, Layout::F32] },
+                    Variant { tag: 5, fields: vec![Layout::Bool] }
+                ]
+            )
+        };
+
+        let changes = check_compatibility(&old, &new);
+        assert_eq!(changes, vec![
+            change(
+                "$.1.1",
+                LayoutChange::SizeEncoding { old: SizeEncoding::Tagged, new: SizeEncoding::Fixed(SizePolicy::U32) },
+                Compatibility::Compatible
+            ),
+            change(
+                "$.1.2",
+                LayoutChange::Layout { old: Layout::U32, new: Layout::Char(CharEncoding::Utf32) },
+                Compatibility::Breaking
+            ),
+            change("$.1.3", LayoutChange::FieldRemoved(Layout::Char(CharEncoding::Utf8)), Compatibility::Breaking),
+            change(
+                "$.2[].#1.0",
+                LayoutChange::Layout { old: Layout::I32, new: Layout::I64 },
+                Compatibility::Breaking
+            ),
+            change("$.2[]", LayoutChange::VariantRenumbered { old: 2, new: 4 }, Compatibility::Breaking),
+            change("$.2[]", LayoutChange::VariantRemoved(3), Compatibility::Breaking),
+            change("$.2[]", LayoutChange::VariantAdded(5), Compatibility::BackwardCompatible)
+        ]);
+        assert_eq!(Compatibility::of(&changes), Compatibility::Breaking);
+        assert_eq!(changes[2].to_string(), "$.1.3: removed Utf8 char (breaking)");
+        assert_eq!(changes[6].to_string(), "$.2[]: added variant #5 (backward-compatible only)");
+
+        let mut extended = old.clone();
+        if let Layout::Struct { fields, .. } = &mut extended.root {
+            if let Layout::Array { element, .. } = &mut fields[2] {
+                if let Layout::Tagged { variants, .. } = element.as_mut() {
+                    variants.push(Variant { tag: 9, fields: vec![Layout::U8] });
+                }
+            }
+        }
+        assert_eq!(Compatibility::of(&check_compatibility(&old, &extended)), Compatibility::BackwardCompatible);
+
+        let framed = |fields: Vec<Layout>| Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(vec![Layout::Frame(fields)], vec![])
+        };
+        let old_frame = framed(vec![Layout::U16, Layout::U8]);
+        let new_frame = framed(vec![Layout::U16, Layout::U8, Layout::U32]);
+        let changes = check_compatibility(&old_frame, &new_frame);
+        assert_eq!(changes, vec![
+            change("$.1.0.2", LayoutChange::FieldAdded(Layout::U32), Compatibility::ForwardCompatible)
+        ]);
+        assert_eq!(changes[0].to_string(), "$.1.0.2: added u32 (forward-compatible only)");
+        let inserted = framed(vec![Layout::U32, Layout::U16, Layout::U8]);
+        assert_eq!(Compatibility::of(&check_compatibility(&old_frame, &inserted)), Compatibility::Breaking);
+        let canonical = check_compatibility_ext(&old_frame, &new_frame, ReflectorOptions::canonical());
+        assert_eq!(Compatibility::of(&canonical), Compatibility::Breaking);
+
+        let mut both = check_compatibility(&old, &extended);
+        both.extend(check_compatibility(&old_frame, &new_frame));
+        assert_eq!(Compatibility::of(&both), Compatibility::Breaking);
+
+        let swapped = Schema { endianness: Endianness::BigEndian, root: old.root.clone() };
+        assert_eq!(check_compatibility(&old, &swapped), vec![change(
+            "$",
+            LayoutChange::Endianness { old: Endianness::LittleEndian, new: Endianness::BigEndian },
+            Compatibility::Breaking
+        )]);
+    }
+
+    #[test]
+    fn test_strict_readers_reject_encoding_changes() {
+        let old = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(vec![Layout::String(SizeEncoding::Tagged)], vec![])
+        };
+        let new = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(vec![Layout::String(SizeEncoding::Fixed(SizePolicy::U16))], vec![])
+        };
+        assert_eq!(Compatibility::of(&check_compatibility(&old, &new)), Compatibility::Compatible);
+
+        let strict = ReflectorOptions { strict_size_tags: true, ..Default::default() };
+        for &options in &[strict, ReflectorOptions::canonical()] {
+            assert_eq!(check_compatibility_ext(&old, &new, options), vec![change(
+                "$.1.0",
+                LayoutChange::SizeEncoding { old: SizeEncoding::Tagged, new: SizeEncoding::Fixed(SizePolicy::U16) },
+                Compatibility::Breaking
+            )]);
+        }
+
+        let old = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(vec![Layout::Reserved { length: 2, byte: 0 }], vec![])
+        };
+        let new = Schema {
+            endianness: Endianness::LittleEndian,
+            root: message(vec![Layout::Reserved { length: 2, byte: 0xFF }], vec![])
+        };
+        assert_eq!(Compatibility::of(&check_compatibility_ext(&old, &new, strict)), Compatibility::Compatible);
+        let strict_reserved = ReflectorOptions { strict_reserved: true, ..Default::default() };
+        assert_eq!(Compatibility::of(&check_compatibility_ext(&old, &new, strict_reserved)), Compatibility::Breaking);
+    }
+}
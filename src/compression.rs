@@ -0,0 +1,155 @@
+use std::io::Read;
+#[cfg(any(feature = "deflate", feature = "lz4"))]
+use std::io::Write;
+use crate::ReflectError;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CompressionCodec {
+    Stored,
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+    #[cfg(feature = "lz4")]
+    Lz4
+}
+
+pub(crate) fn codec_id(codec: CompressionCodec) -> u8 {
+    match codec {
+        CompressionCodec::Stored => 0,
+        #[cfg(feature = "deflate")]
+        CompressionCodec::Deflate => 1,
+        #[cfg(feature = "zstd")]
+        CompressionCodec::Zstd => 2,
+        #[cfg(feature = "lz4")]
+        CompressionCodec::Lz4 => 3
+    }
+}
+
+pub(crate) fn codec_from_id(id: u8) -> Option<CompressionCodec> {
+    match id {
+        0 => Some(CompressionCodec::Stored),
+        #[cfg(feature = "deflate")]
+        1 => Some(CompressionCodec::Deflate),
+        #[cfg(feature = "zstd")]
+        2 => Some(CompressionCodec::Zstd),
+        #[cfg(feature = "lz4")]
+        3 => Some(CompressionCodec::Lz4),
+        _ => None
+    }
+}
+
+pub(crate) fn compress(codec: CompressionCodec, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match codec {
+        CompressionCodec::Stored => Ok(data.to_vec()),
+        #[cfg(feature = "deflate")]
+        CompressionCodec::Deflate => {
+            let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        },
+        #[cfg(feature = "zstd")]
+        CompressionCodec::Zstd => zstd::stream::encode_all(data, 0),
+        #[cfg(feature = "lz4")]
+        CompressionCodec::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(data)?;
+            encoder.finish().map_err(std::io::Error::other)
+        }
+    }
+}
+
+fn read_limited<TStream: Read>(stream: TStream, limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    stream.take(limit.saturating_add(1)).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(ReflectError::DecompressedSizeExceeded { limit }.into());
+    }
+    Ok(data)
+}
+
+pub(crate) fn decompress(codec: CompressionCodec, data: &[u8], limit: u64) -> std::io::Result<Vec<u8>> {
+    match codec {
+        CompressionCodec::Stored => read_limited(data, limit),
+        #[cfg(feature = "deflate")]
+        CompressionCodec::Deflate => read_limited(flate2::read::DeflateDecoder::new(data), limit),
+        #[cfg(feature = "zstd")]
+        CompressionCodec::Zstd => read_limited(zstd::stream::read::Decoder::new(data)?, limit),
+        #[cfg(feature = "lz4")]
+        CompressionCodec::Lz4 => read_limited(lz4_flex::frame::FrameDecoder::new(data), limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CompressionCodec, ReflectorOptions, ReflectError, SerializationReflector,
+        BinaryWriterLittleEndian, BinaryReaderLittleEndian
+    };
+
+    fn codecs() -> Vec<CompressionCodec> {
+        vec![
+            CompressionCodec::Stored,
+            #[cfg(feature = "deflate")]
+            CompressionCodec::Deflate,
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd,
+            #[cfg(feature = "lz4")]
+            CompressionCodec::Lz4
+        ]
+    }
+
+    #[test]
+    fn test_compressed_sections() {
+        for codec in codecs() {
+            let mut tiles = vec![0u16; 4096];
+            tiles[100] = 7;
+            let mut trailer = 0xABu8;
+            let mut stream_vec = Vec::new();
+            let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+            serializer.reflect_compressed(codec, |w| w.reflect_u16_array(&mut tiles)).unwrap();
+            serializer.reflect_u8(&mut trailer).unwrap();
+            if codec != CompressionCodec::Stored {
+                assert!(stream_vec.len() < 1024);
+            }
+
+            let mut stream = &stream_vec[..];
+            let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+            let mut tiles1 = Vec::new();
+            let mut trailer1 = 0;
+            deserializer.reflect_compressed(codec, |r| r.reflect_u16_array(&mut tiles1)).unwrap();
+            deserializer.reflect_u8(&mut trailer1).unwrap();
+            assert_eq!(tiles, tiles1);
+            assert_eq!(trailer, trailer1);
+
+            let options = ReflectorOptions { max_decompressed_size: 1024, ..Default::default() };
+            let mut stream = &stream_vec[..];
+            let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, options);
+            let error = deserializer.reflect_compressed(codec, |r| r.reflect_u16_array(&mut tiles1)).unwrap_err();
+            assert_eq!(
+                ReflectError::from_io_error(&error),
+                Some(&ReflectError::DecompressedSizeExceeded { limit: 1024 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_canonical_sections_are_stored() {
+        for codec in codecs() {
+            let mut tiles = vec![1u16, 2, 3];
+            let mut stream_vec = Vec::new();
+            let mut serializer = BinaryWriterLittleEndian::with_options(&mut stream_vec, ReflectorOptions::canonical());
+            let written = serializer.reflect_compressed(codec, |w| w.reflect_u16_array(&mut tiles));
+            assert_eq!(written.is_ok(), codec == CompressionCodec::Stored);
+
+            let mut stream_vec = Vec::new();
+            let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+            serializer.reflect_compressed(codec, |w| w.reflect_u16_array(&mut tiles)).unwrap();
+            let mut stream = &stream_vec[..];
+            let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, ReflectorOptions::canonical());
+            let mut tiles1 = Vec::new();
+            let read = deserializer.reflect_compressed(codec, |r| r.reflect_u16_array(&mut tiles1));
+            assert_eq!(read.is_ok(), codec == CompressionCodec::Stored);
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{
+    SerializationReflector, Reflectable, TaggedReflectable, ReflectableMap, ReflectableSet,
+    ChecksumAlgorithm, CompressionCodec, SizePolicy, CharEncoding, BitOrder, Value,
+    reflect_size, reflect_size_ext, reflect_digest, reflect_packed_bools, cp866_size
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change {
+    Value { old: Value, new: Value },
+    Length { old: usize, new: usize },
+    Variant { old: u64, new: u64 }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Difference {
+    pub path: String,
+    pub change: Change
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.change {
+            Change::Value { old, new } => write!(f, "{}: {} -> {}", self.path, old, new),
+            Change::Length { old, new } => write!(f, "{}: length {} -> {}", self.path, old, new),
+            Change::Variant { old, new } => write!(f, "{}: variant {} -> {}", self.path, old, new)
+        }
+    }
+}
+
+pub fn diff<T: Reflectable>(old: &T, new: &T) -> std::io::Result<Vec<Difference>> {
+    let old = DiffReflector::record(&mut old.clone())?;
+    let new = DiffReflector::record(&mut new.clone())?;
+    let mut differences = Vec::new();
+    compare(&mut "$".to_string(), &old, &new, &mut differences);
+    Ok(differences)
+}
+
+fn compare(path: &mut String, old: &Value, new: &Value, differences: &mut Vec<Difference>) {
+    match (old, new) {
+        (Value::Struct(old_fields), Value::Struct(new_fields)) =>
+            compare_items(path, old_fields, new_fields, differences, |i| format!(".{}", i)),
+        (Value::Array(old_items), Value::Array(new_items)) =>
+            compare_items(path, old_items, new_items, differences, |i| format!("[{}]", i)),
+        (Value::Tagged(old_tag, _), Value::Tagged(new_tag, _)) if old_tag != new_tag =>
+            differences.push(Difference {
+                path: path.clone(),
+                change: Change::Variant { old: *old_tag, new: *new_tag }
+            }),
+        (Value::Tagged(_, old_body), Value::Tagged(_, new_body)) =>
+            compare(path, old_body, new_body, differences),
+        _ if !old.same_as(new) =>
+            differences.push(Difference {
+                path: path.clone(),
+                change: Change::Value { old: old.clone(), new: new.clone() }
+            }),
+        _ => {}
+    }
+}
+
+fn compare_items<F: Fn(usize) -> String>(
+    path: &mut String,
+    old: &[Value],
+    new: &[Value],
+    differences: &mut Vec<Difference>,
+    segment: F
+) {
+    if old.len() != new.len() {
+        differences.push(Difference {
+            path: path.clone(),
+            change: Change::Length { old: old.len(), new: new.len() }
+        });
+    }
+    for (i, (old, new)) in old.iter().zip(new.iter()).enumerate() {
+        let length = path.len();
+        path.push_str(&segment(i));
+        compare(path, old, new, differences);
+        path.truncate(length);
+    }
+}
+
+macro_rules! record_primitives {
+    ($($reflect:ident, $primitive:ty, $variant:ident;)*) => {$(
+        fn $reflect(&mut self, data: &mut $primitive) -> std::io::Result<()> {
+            self.position += std::mem::size_of::<$primitive>() as u64;
+            self.push(Value::$variant(*data));
+            Ok(())
+        }
+    )*};
+}
+
+pub struct DiffReflector {
+    position: u64,
+    version: u32,
+    muted: usize,
+    groups: Vec<Vec<Value>>
+}
+
+impl DiffReflector {
+    pub fn new() -> Self {
+        Self {
+            position: 0,
+            version: u32::MAX,
+            muted: 0,
+            groups: vec![Vec::new()]
+        }
+    }
+
+    pub fn record<T: Reflectable>(data: &mut T) -> std::io::Result<Value> {
+        let mut reflector = Self::new();
+        data.reflect(&mut reflector)?;
+        Ok(reflector.into_value())
+    }
+
+    pub fn into_value(mut self) -> Value {
+        Value::Struct(self.groups.pop().unwrap_or_default())
+    }
+
+    fn push(&mut self, value: Value) {
+        if self.muted == 0 {
+            self.groups.last_mut().unwrap().push(value);
+        }
+    }
+
+    fn group<F>(&mut self, body: F) -> std::io::Result<Vec<Value>>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.groups.push(Vec::new());
+        let result = body(self);
+        let values = self.groups.pop().unwrap();
+        result.map(|_| values)
+    }
+
+    fn muted<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.muted += 1;
+        let result = body(self);
+        self.muted -= 1;
+        result
+    }
+
+    fn record_size(&mut self, size: usize, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        let mut size = size;
+        self.muted(|r| match size_policy {
+            Some(size_policy) => reflect_size_ext(r, &mut size, size_policy),
+            None => reflect_size(r, &mut size)
+        })
+    }
+
+    fn record_array<T, F>(
+        &mut self,
+        data: &mut [T],
+        size_policy: Option<SizePolicy>,
+        mut element: F
+    ) -> std::io::Result<()>
+        where F: FnMut(&mut Self, &mut T) -> std::io::Result<()>
+    {
+        self.record_size(data.len(), size_policy)?;
+        let mut items = Vec::with_capacity(data.len());
+        for item in data.iter_mut() {
+            let mut values = self.group(|r| element(r, item))?;
+            items.push(if values.len() == 1 { values.pop().unwrap() } else { Value::Struct(values) });
+        }
+        self.push(Value::Array(items));
+        Ok(())
+    }
+
+    fn record_string(&mut self, string: &str, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        self.record_size(string.len(), size_policy)?;
+        self.position += string.len() as u64;
+        self.push(Value::String(string.to_string()));
+        Ok(())
+    }
+
+    fn record_packed_bools(
+        &mut self,
+        data: &mut [bool],
+        bit_order: BitOrder,
+        size_policy: Option<SizePolicy>
+    ) -> std::io::Result<()> {
+        self.record_size(data.len(), size_policy)?;
+        self.muted(|r| reflect_packed_bools(r, data, bit_order))?;
+        self.push(Value::Array(data.iter().map(|bit| Value::U8(*bit as u8)).collect()));
+        Ok(())
+    }
+
+    fn record_entry<K: Reflectable, V: Reflectable>(&mut self, entry: &mut (K, V)) -> std::io::Result<()> {
+        let fields = self.group(|r| {
+            r.reflect_composite(&mut entry.0)?;
+            r.reflect_composite(&mut entry.1)
+        })?;
+        self.push(Value::Struct(fields));
+        Ok(())
+    }
+
+    fn record_section<F>(&mut self, overhead: u64, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let outer_position = self.position;
+        self.position = 0;
+        let result = body(self);
+        let length = self.position;
+        self.position = outer_position + overhead;
+        result?;
+        self.record_size(length as usize, None)?;
+        self.position += length;
+        Ok(())
+    }
+}
+
+impl Default for DiffReflector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerializationReflector for DiffReflector {
+    fn is_reading(&self) -> bool {
+        false
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.record_section(0, body)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)?;
+        self.muted(|r| reflect_digest(r, algorithm, 0))
+    }
+
+    // The compressed length is unknown here, so the section is measured as if it was stored.
+    fn reflect_compressed<F>(&mut self, _codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.record_section(0, body)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.record_section((crate::encryption::NONCE_SIZE + crate::encryption::TAG_SIZE) as u64, body)
+    }
+
+    with_primitives!(record_primitives);
+
+    fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.position += cp866_size(string);
+        self.push(Value::String(string.clone()));
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.position += cp866_size(string);
+        self.push(Value::String(string.clone()));
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        self.position += length as u64;
+        self.push(Value::String(string.clone()));
+        Ok(())
+    }
+
+    delegate_arrays!(record_array);
+
+    fn reflect_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.record_string(string, None)
+    }
+
+    fn reflect_string_ext(&mut self, string: &mut String, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.record_string(string, Some(size_policy))
+    }
+
+    fn reflect_composite<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        let fields = self.group(|r| composite.reflect(r))?;
+        self.push(Value::Struct(fields));
+        Ok(())
+    }
+
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_composite(composite))
+    }
+
+    fn reflect_tagged_composite<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        let tag = composite.get_tag();
+        let mut fields = self.group(|r| composite.reflect(r))?;
+        if !fields.is_empty() {
+            fields.remove(0);
+        }
+        self.push(Value::Tagged(tag, Box::new(Value::Struct(fields))));
+        Ok(())
+    }
+
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_tagged_composite(composite))
+    }
+
+    fn reflect_char(&mut self, data: &mut char, encoding: CharEncoding) -> std::io::Result<()> {
+        self.position += match encoding {
+            CharEncoding::Utf32 => 4,
+            CharEncoding::Utf8 => data.len_utf8() as u64
+        };
+        self.push(Value::U32(*data as u32));
+        Ok(())
+    }
+
+    fn reflect_char_array(&mut self, data: &mut Vec<char>, encoding: CharEncoding) -> std::io::Result<()> {
+        self.record_array(data, None, |r, item| r.reflect_char(item, encoding))
+    }
+
+    fn reflect_char_array_ext(
+        &mut self,
+        data: &mut Vec<char>,
+        encoding: CharEncoding,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(data, Some(size_policy), |r, item| r.reflect_char(item, encoding))
+    }
+
+    fn reflect_packed_bool_array(&mut self, data: &mut Vec<bool>, bit_order: BitOrder) -> std::io::Result<()> {
+        self.record_packed_bools(data, bit_order, None)
+    }
+
+    fn reflect_packed_bool_array_ext(
+        &mut self,
+        data: &mut Vec<bool>,
+        bit_order: BitOrder,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_packed_bools(data, bit_order, Some(size_policy))
+    }
+
+    fn reflect_array_of_composites<R: Reflectable>(&mut self, data: &mut Vec<R>) -> std::io::Result<()> {
+        self.record_array(data, None, |r, item| r.reflect_composite(item))
+    }
+
+    fn reflect_array_of_composites_ext<R: Reflectable>(
+        &mut self,
+        data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(data, Some(size_policy), |r, item| r.reflect_composite(item))
+    }
+
+    fn reflect_array_of_tagged_composites<R: TaggedReflectable>(&mut self, data: &mut Vec<R>) -> std::io::Result<()> {
+        self.record_array(data, None, |r, item| r.reflect_tagged_composite(item))
+    }
+
+    fn reflect_array_of_tagged_composites_ext<R: TaggedReflectable>(
+        &mut self,
+        data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(data, Some(size_policy), |r, item| r.reflect_tagged_composite(item))
+    }
+
+    fn reflect_map<M: ReflectableMap>(&mut self, map: &mut M) -> std::io::Result<()> {
+        let mut entries = map.sorted_entries();
+        self.record_array(&mut entries, None, |r, item| r.record_entry(item))
+    }
+
+    fn reflect_map_ext<M: ReflectableMap>(&mut self, map: &mut M, size_policy: SizePolicy) -> std::io::Result<()> {
+        let mut entries = map.sorted_entries();
+        self.record_array(&mut entries, Some(size_policy), |r, item| r.record_entry(item))
+    }
+
+    fn reflect_set<S: ReflectableSet>(&mut self, set: &mut S) -> std::io::Result<()> {
+        let mut items = set.sorted_items();
+        self.record_array(&mut items, None, |r, item| r.reflect_composite(item))
+    }
+
+    fn reflect_set_ext<S: ReflectableSet>(&mut self, set: &mut S, size_policy: SizePolicy) -> std::io::Result<()> {
+        let mut items = set.sorted_items();
+        self.record_array(&mut items, Some(size_policy), |r, item| r.reflect_composite(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{
+        Reflectable, TaggedReflectable, SerializationReflector, SizePolicy, Endianness, Value, Change, Difference, DiffReflector, diff
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    enum State {
+        #[default]
+        Idle,
+        Walking { speed: f32 },
+        Dead { cause: String }
+    }
+
+    impl TaggedReflectable for State {
+        fn get_size_policy() -> SizePolicy {
+            SizePolicy::U8
+        }
+
+        fn get_tag(&self) -> u64 {
+            match self {
+                State::Idle => 0,
+                State::Walking { .. } => 1,
+                State::Dead { .. } => 2
+            }
+        }
+
+        fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            tag: u64,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            if self.get_tag() != tag {
+                *self = match tag {
+                    1 => State::Walking { speed: 0.0 },
+                    2 => State::Dead { cause: String::new() },
+                    _ => State::Idle
+                };
+            }
+            match self {
+                State::Idle => Ok(()),
+                State::Walking { speed } => reflector.reflect_f32(speed),
+                State::Dead { cause } => reflector.reflect_string(cause)
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Item {
+        id: u32,
+        count: u16
+    }
+
+    impl Reflectable for Item {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u32(&mut self.id)?;
+            reflector.reflect_u16(&mut self.count)
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Save {
+        name: String,
+        level: u32,
+        inventory: Vec<Item>,
+        states: Vec<State>,
+        flags: BTreeMap<u8, bool>
+    }
+
+    impl Reflectable for Save {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string(&mut self.name)?;
+            reflector.reflect_u32(&mut self.level)?;
+            reflector.reflect_array_of_composites(&mut self.inventory)?;
+            reflector.reflect_array_of_tagged_composites(&mut self.states)?;
+            reflector.reflect_map(&mut self.flags)
+        }
+    }
+
+    fn save() -> Save {
+        Save {
+            name: "hero".to_string(),
+            level: 3,
+            inventory: vec![Item { id: 1, count: 5 }, Item { id: 2, count: 1 }],
+            states: vec![State::Idle, State::Walking { speed: 1.5 }],
+            flags: vec![(1, true), (2, false)].into_iter().collect()
+        }
+    }
+
+    #[test]
+    fn test_equal_values_have_no_differences() {
+        assert_eq!(diff(&save(), &save()).unwrap(), vec![]);
+
+        let mut bytes = Vec::new();
+        save().serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+        let mut reflector = DiffReflector::new();
+        save().reflect(&mut reflector).unwrap();
+        assert_eq!(reflector.position(), bytes.len() as u64);
+    }
+
+    #[test]
+    fn test_differences_carry_paths_and_values() {
+        let old = save();
+        let mut new = save();
+        new.level = 4;
+        new.inventory[1].count = 9;
+        new.inventory.push(Item { id: 3, count: 1 });
+        new.states[0] = State::Dead { cause: "lava".to_string() };
+        new.states[1] = State::Walking { speed: 2.0 };
+        new.flags.insert(2, true);
+
+        let differences = diff(&old, &new).unwrap();
+        assert_eq!(differences, vec![
+            Difference { path: "$.1".to_string(), change: Change::Value { old: Value::U32(3), new: Value::U32(4) } },
+            Difference { path: "$.2".to_string(), change: Change::Length { old: 2, new: 3 } },
+            Difference { path: "$.2[1].1".to_string(), change: Change::Value { old: Value::U16(1), new: Value::U16(9) } },
+            Difference { path: "$.3[0]".to_string(), change: Change::Variant { old: 0, new: 2 } },
+            Difference { path: "$.3[1].0".to_string(), change: Change::Value { old: Value::F32(1.5), new: Value::F32(2.0) } },
+            Difference { path: "$.4[1].1.0".to_string(), change: Change::Value { old: Value::U8(0), new: Value::U8(1) } }
+        ]);
+        let printed: Vec<String> = differences.iter().map(|d| d.to_string()).collect();
+        assert_eq!(printed, vec![
+            "$.1: 3u32 -> 4u32",
+            "$.2: length 2 -> 3",
+            "$.2[1].1: 1u16 -> 9u16",
+            "$.3[0]: variant 0 -> 2",
+            "$.3[1].0: 1.5f32 -> 2.0f32",
+            "$.4[1].1.0: 0u8 -> 1u8"
+        ]);
+    }
+}
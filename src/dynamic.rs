@@ -0,0 +1,588 @@
+use std::io::{Read, Write};
+use crate::{
+    SerializationReflector, ReflectorOptions, SizePolicy, Endianness, Schema, Layout, SizeEncoding, Value,
+    BinaryReaderBigEndian, BinaryReaderLittleEndian, BinaryWriterBigEndian, BinaryWriterLittleEndian,
+    ReflectError, reflect_size, reflect_size_ext, reflect_packed_bools, check
+};
+
+pub struct DynamicReader<'a, TStream: Read> {
+    stream: &'a mut TStream,
+    schema: &'a Schema,
+    options: ReflectorOptions
+}
+
+impl<'a, TStream: Read> DynamicReader<'a, TStream> {
+    pub fn new(stream: &'a mut TStream, schema: &'a Schema, options: ReflectorOptions) -> Self {
+        Self { stream, schema, options }
+    }
+
+    pub fn read(&mut self) -> std::io::Result<Value> {
+        let mut value = Value::default();
+        match self.schema.endianness {
+            Endianness::BigEndian => {
+                let mut reflector = BinaryReaderBigEndian::with_options(self.stream, self.options);
+                reflect_value(&mut reflector, &self.schema.root, &mut value, &mut Vec::new())
+            },
+            Endianness::LittleEndian => {
+                let mut reflector = BinaryReaderLittleEndian::with_options(self.stream, self.options);
+                reflect_value(&mut reflector, &self.schema.root, &mut value, &mut Vec::new())
+            }
+        }?;
+        Ok(value)
+    }
+}
+
+pub struct DynamicWriter<'a, TStream: Write> {
+    stream: &'a mut TStream,
+    schema: &'a Schema,
+    options: ReflectorOptions
+}
+
+impl<'a, TStream: Write> DynamicWriter<'a, TStream> {
+    pub fn new(stream: &'a mut TStream, schema: &'a Schema, options: ReflectorOptions) -> Self {
+        Self { stream, schema, options }
+    }
+
+    pub fn write(&mut self, value: &Value) -> std::io::Result<()> {
+        let mut value = value.clone();
+        match self.schema.endianness {
+            Endianness::BigEndian => {
+                let mut reflector = BinaryWriterBigEndian::with_options(self.stream, self.options);
+                reflect_value(&mut reflector, &self.schema.root, &mut value, &mut Vec::new())
+            },
+            Endianness::LittleEndian => {
+                let mut reflector = BinaryWriterLittleEndian::with_options(self.stream, self.options);
+                reflect_value(&mut reflector, &self.schema.root, &mut value, &mut Vec::new())
+            }
+        }
+    }
+}
+
+fn mismatch() -> std::io::Error {
+    std::io::Error::other("value does not match the schema")
+}
+
+macro_rules! reflect_primitive {
+    ($r:expr, $value:expr, $variant:ident, $reflect:ident) => {{
+        let mut data = match $value {
+            Value::$variant(data) => *data,
+            _ if $r.is_reading() => Default::default(),
+            _ => return Err(mismatch())
+        };
+        $r.$reflect(&mut data)?;
+        *$value = Value::$variant(data);
+        Ok(())
+    }};
+}
+
+fn reflect_value<'a, R: SerializationReflector>(
+    r: &mut R,
+    layout: &'a Layout,
+    value: &mut Value,
+    ancestors: &mut Vec<&'a Layout>
+) -> std::io::Result<()> {
+    let limit = r.options().max_depth;
+    if ancestors.len() >= limit {
+        return Err(ReflectError::DepthLimitExceeded { limit }.into());
+    }
+    ancestors.push(layout);
+    let result = reflect_layout(r, layout, value, ancestors);
+    ancestors.pop();
+    result
+}
+
+fn reflect_layout<'a, R: SerializationReflector>(
+    r: &mut R,
+    layout: &'a Layout,
+    value: &mut Value,
+    ancestors: &mut Vec<&'a Layout>
+) -> std::io::Result<()> {
+    match layout {
+        Layout::U8 | Layout::Bool => reflect_primitive!(r, value, U8, reflect_u8),
+        Layout::U16 | Layout::F16 => reflect_primitive!(r, value, U16, reflect_u16),
+        Layout::U32 => reflect_primitive!(r, value, U32, reflect_u32),
+        Layout::U64 => reflect_primitive!(r, value, U64, reflect_u64),
+        Layout::U128 => reflect_primitive!(r, value, U128, reflect_u128),
+        Layout::I8 => reflect_primitive!(r, value, I8, reflect_i8),
+        Layout::I16 => reflect_primitive!(r, value, I16, reflect_i16),
+        Layout::I32 => reflect_primitive!(r, value, I32, reflect_i32),
+        Layout::I64 => reflect_primitive!(r, value, I64, reflect_i64),
+        Layout::I128 => reflect_primitive!(r, value, I128, reflect_i128),
+        Layout::F32 => reflect_primitive!(r, value, F32, reflect_f32),
+        Layout::F64 => reflect_primitive!(r, value, F64, reflect_f64),
+        Layout::Char(encoding) => {
+            let mut data = match value {
+                Value::U32(code) => std::char::from_u32(*code).ok_or_else(mismatch)?,
+                _ if r.is_reading() => '\0',
+                _ => return Err(mismatch())
+            };
+            r.reflect_char(&mut data, *encoding)?;
+            *value = Value::U32(data as u32);
+            Ok(())
+        },
+        Layout::String(size) => {
+            let mut bytes = match value {
+                Value::String(string) => string.clone().into_bytes(),
+                Value::Array(_) => raw_bytes(value)?,
+                _ if r.is_reading() => Vec::new(),
+                _ => return Err(mismatch())
+            };
+            match size {
+                SizeEncoding::Tagged => r.reflect_u8_array(&mut bytes),
+                SizeEncoding::Fixed(size_policy) => r.reflect_u8_array_ext(&mut bytes, *size_policy)
+            }?;
+            *value = String::from_utf8(bytes)
+                .map(Value::String)
+                .unwrap_or_else(|error| bytes_value(error.into_bytes()));
+            Ok(())
+        },
+        Layout::Cp866String => {
+            let mut bytes = cp866_bytes(r, value)?;
+            let mut length = bytes.len() as u8;
+            check(bytes.len() <= 0xFF, "cp866 string is longer than 255 bytes")?;
+            r.reflect_u8(&mut length)?;
+            bytes.resize(length as usize, 0);
+            for byte in bytes.iter_mut() {
+                r.reflect_u8(byte)?;
+            }
+            *value = Value::String(bytes.iter().map(|byte| cp866_rs::decode_byte(*byte)).collect());
+            Ok(())
+        },
+        Layout::Cp866ZString => {
+            let mut bytes = cp866_bytes(r, value)?;
+            check(!bytes.contains(&0), "cp866 zero-terminated string contains a zero")?;
+            if r.is_reading() {
+                loop {
+                    let mut byte = 0;
+                    r.reflect_u8(&mut byte)?;
+                    if byte == 0 {
+                        break;
+                    }
+                    bytes.push(byte);
+                }
+            } else {
+                bytes.push(0);
+                for byte in bytes.iter_mut() {
+                    r.reflect_u8(byte)?;
+                }
+                bytes.pop();
+            }
+            *value = Value::String(bytes.iter().map(|byte| cp866_rs::decode_byte(*byte)).collect());
+            Ok(())
+        },
+        Layout::Cp866FixedZString(length) => {
+            let bytes = cp866_bytes(r, value)?;
+            check(bytes.len() <= *length as usize || r.is_reading(), "cp866 string does not fit its field")?;
+            let mut string = String::new();
+            let mut terminator_found = false;
+            for i in 0..*length as usize {
+                let mut byte = bytes.get(i).copied().unwrap_or(0);
+                r.reflect_u8(&mut byte)?;
+                terminator_found |= byte == 0;
+                if !terminator_found {
+                    string.push(cp866_rs::decode_byte(byte));
+                }
+            }
+            *value = Value::String(string);
+            Ok(())
+        },
+        Layout::Array { size, element } => {
+            let mut length = match value {
+                Value::Array(items) => items.len(),
+                _ if r.is_reading() => 0,
+                _ => return Err(mismatch())
+            };
+            reflect_size_encoding(r, &mut length, *size)?;
+            if r.is_reading() {
+                *value = Value::Array(Vec::new());
+            }
+            let items = match value {
+                Value::Array(items) => items,
+                _ => return Err(mismatch())
+            };
+            let max_empty_elements = r.options().max_empty_elements;
+            let mut empty_run = 0;
+            for i in 0..length {
+                if r.is_reading() {
+                    items.push(Value::default());
+                }
+                let start = r.position();
+                reflect_value(r, element, &mut items[i], ancestors)?;
+                empty_run = if r.position() == start { empty_run + 1 } else { 0 };
+                check(empty_run <= max_empty_elements, "too many array elements without any data")?;
+            }
+            Ok(())
+        },
+        Layout::PackedBools { size, order } => {
+            let mut bits: Vec<bool> = match value {
+                Value::Array(_) => raw_bytes(value)?.into_iter().map(|bit| bit != 0).collect(),
+                _ if r.is_reading() => Vec::new(),
+                _ => return Err(mismatch())
+            };
+            let mut length = bits.len();
+            reflect_size_encoding(r, &mut length, *size)?;
+            if r.is_reading() {
+                while bits.len() < length {
+                    let mut chunk = vec![false; (length - bits.len()).min(8)];
+                    reflect_packed_bools(r, &mut chunk, *order)?;
+                    bits.extend(chunk);
+                }
+            } else {
+                reflect_packed_bools(r, &mut bits, *order)?;
+            }
+            *value = Value::Array(bits.into_iter().map(|bit| Value::U8(bit as u8)).collect());
+            Ok(())
+        },
+        Layout::Struct { fields, .. } => reflect_fields(r, fields, value, ancestors),
+        Layout::Tagged { tag: size_policy, variants, .. } => {
+            let mut tag = match value {
+                Value::Tagged(tag, _) => *tag,
+                _ if r.is_reading() => 0,
+                _ => return Err(mismatch())
+            };
+            reflect_tag(r, &mut tag, *size_policy)?;
+            let variant = variants.iter()
+                .find(|variant| variant.tag == tag)
+                .ok_or_else(|| std::io::Error::other("unknown variant tag"))?;
+            if r.is_reading() {
+                *value = Value::Tagged(tag, Box::default());
+            }
+            let body = match value {
+                Value::Tagged(_, body) => body,
+                _ => return Err(mismatch())
+            };
+            reflect_fields(r, &variant.fields, body, ancestors)
+        },
+        Layout::Recursive(name) => {
+            let target = ancestors.iter()
+                .rev()
+                .find(|ancestor| match ancestor {
+                    Layout::Struct { name: ancestor_name, .. } | Layout::Tagged { name: ancestor_name, .. } =>
+                        ancestor_name == name,
+                    _ => false
+                })
+                .copied()
+                .ok_or_else(|| std::io::Error::other("recursive layout has no enclosing definition"))?;
+            reflect_value(r, target, value, ancestors)
+        },
+        Layout::Magic(magic) => {
+            r.reflect_magic(magic)?;
+            *value = bytes_value(magic.clone());
+            Ok(())
+        },
+        Layout::Reserved { length, byte: expected_byte } => {
+            reflect_raw_bytes(r, *length, value)?;
+            let options = r.options();
+            let strict = r.is_reading() && (options.strict_reserved || options.canonical);
+            check(
+                !strict || raw_bytes(value)?.iter().all(|byte| byte == expected_byte),
+                "unexpected value in reserved field"
+            )
+        },
+        Layout::Align(alignment) => {
+            check(*alignment != 0, "alignment must be greater than zero")?;
+            let misalignment = r.position() % alignment;
+            let length = if misalignment == 0 { 0 } else { alignment - misalignment };
+            reflect_raw_bytes(r, length, value)
+        },
+        Layout::Frame(fields) => r.reflect_frame(|r| reflect_fields(r, fields, value, ancestors)),
+        Layout::Checksummed { algorithm, fields } =>
+            r.reflect_checksummed(*algorithm, |r| reflect_fields(r, fields, value, ancestors)),
+        Layout::Compressed { codec, fields } =>
+            r.reflect_compressed(*codec, |r| reflect_fields(r, fields, value, ancestors)),
+        Layout::Encrypted(_) => Err(std::io::Error::other("encrypted sections need a key and cannot be read dynamically"))
+    }
+}
+
+fn reflect_fields<'a, R: SerializationReflector>(
+    r: &mut R,
+    fields: &'a [Layout],
+    value: &mut Value,
+    ancestors: &mut Vec<&'a Layout>
+) -> std::io::Result<()> {
+    if r.is_reading() {
+        *value = Value::Struct(vec![Value::default(); fields.len()]);
+    }
+    let items = match value {
+        Value::Struct(items) if items.len() == fields.len() => items,
+        _ => return Err(mismatch())
+    };
+    for (field, item) in fields.iter().zip(items.iter_mut()) {
+        reflect_value(r, field, item, ancestors)?;
+    }
+    Ok(())
+}
+
+fn reflect_size_encoding<R: SerializationReflector>(
+    r: &mut R,
+    size: &mut usize,
+    size_encoding: SizeEncoding
+) -> std::io::Result<()> {
+    match size_encoding {
+        SizeEncoding::Tagged => reflect_size(r, size),
+        SizeEncoding::Fixed(size_policy) => reflect_size_ext(r, size, size_policy)
+    }
+}
+
+fn reflect_tag<R: SerializationReflector>(r: &mut R, tag: &mut u64, size_policy: SizePolicy) -> std::io::Result<()> {
+    match size_policy {
+        SizePolicy::U8 => {
+            let mut narrow = *tag as u8;
+            r.reflect_u8(&mut narrow)?;
+            *tag = narrow as u64;
+        },
+        SizePolicy::U16 => {
+            let mut narrow = *tag as u16;
+            r.reflect_u16(&mut narrow)?;
+            *tag = narrow as u64;
+        },
+        SizePolicy::U32 => {
+            let mut narrow = *tag as u32;
+            r.reflect_u32(&mut narrow)?;
+            *tag = narrow as u64;
+        },
+        SizePolicy::U64 => r.reflect_u64(tag)?
+    }
+    Ok(())
+}
+
+fn reflect_raw_bytes<R: SerializationReflector>(r: &mut R, length: u64, value: &mut Value) -> std::io::Result<()> {
+    let mut bytes = match value {
+        Value::Array(_) if !r.is_reading() => raw_bytes(value)?,
+        _ => Vec::new()
+    };
+    check(r.is_reading() || bytes.len() as u64 == length, "raw bytes do not match their field length")?;
+    if r.is_reading() {
+        for _ in 0..length {
+            let mut byte = 0;
+            r.reflect_u8(&mut byte)?;
+            bytes.push(byte);
+        }
+    } else {
+        for byte in bytes.iter_mut() {
+            r.reflect_u8(byte)?;
+        }
+    }
+    *value = bytes_value(bytes);
+    Ok(())
+}
+
+fn raw_bytes(value: &Value) -> std::io::Result<Vec<u8>> {
+    match value {
+        Value::Array(items) => items.iter()
+            .map(|item| match item {
+                Value::U8(byte) => Ok(*byte),
+                _ => Err(mismatch())
+            })
+            .collect(),
+        _ => Err(mismatch())
+    }
+}
+
+fn bytes_value(bytes: Vec<u8>) -> Value {
+    Value::Array(bytes.into_iter().map(Value::U8).collect())
+}
+
+fn cp866_bytes<R: SerializationReflector>(r: &R, value: &Value) -> std::io::Result<Vec<u8>> {
+    match value {
+        Value::String(string) => string.chars()
+            .map(|ch| (0..=0xFFu8)
+                .find(|byte| cp866_rs::decode_byte(*byte) == ch)
+                .ok_or_else(|| std::io::Error::other("character has no cp866 encoding")))
+            .collect(),
+        _ if r.is_reading() => Ok(Vec::new()),
+        _ => Err(mismatch())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{
+        Reflectable, TaggedReflectable, SerializationReflector, SizePolicy, CharEncoding, ChecksumAlgorithm,
+        BitOrder, CompressionCodec, Endianness, ReflectorOptions, ReflectError, Schema, Value, DynamicReader, DynamicWriter,
+        BinaryWriterBigEndian, BinaryWriterLittleEndian
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    enum Shape {
+        #[default]
+        Empty,
+        Circle(f32),
+        Polygon(Vec<i32>)
+    }
+
+    impl TaggedReflectable for Shape {
+        fn get_size_policy() -> SizePolicy {
+            SizePolicy::U16
+        }
+
+        fn get_tag(&self) -> u64 {
+            match self {
+                Shape::Empty => 0,
+                Shape::Circle(_) => 3,
+                Shape::Polygon(_) => 7
+            }
+        }
+
+        fn variant_tags() -> Vec<u64> {
+            vec![0, 3, 7]
+        }
+
+        fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            tag: u64,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            if self.get_tag() != tag {
+                *self = match tag {
+                    3 => Shape::Circle(0.0),
+                    7 => Shape::Polygon(Vec::new()),
+                    _ => Shape::Empty
+                };
+            }
+            match self {
+                Shape::Empty => Ok(()),
+                Shape::Circle(radius) => reflector.reflect_f16(radius),
+                Shape::Polygon(points) => reflector.reflect_i32_array(points)
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Tree {
+        label: String,
+        children: Vec<Tree>
+    }
+
+    impl Reflectable for Tree {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_cp866_zstring(&mut self.label)?;
+            reflector.reflect_array_of_composites(&mut self.children)
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Drawing {
+        title: String,
+        initial: char,
+        shapes: Vec<Shape>,
+        layers: Vec<bool>,
+        notes: BTreeMap<u8, String>,
+        tree: Tree,
+        flags: bool,
+        scale: f64
+    }
+
+    impl Reflectable for Drawing {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_magic(b"DRAW")?;
+            reflector.reflect_string(&mut self.title)?;
+            reflector.reflect_char(&mut self.initial, CharEncoding::Utf8)?;
+            reflector.reflect_array_of_tagged_composites(&mut self.shapes)?;
+            reflector.reflect_packed_bool_array_ext(&mut self.layers, BitOrder::MsbFirst, SizePolicy::U8)?;
+            reflector.reflect_reserved(3, 0xEE)?;
+            reflector.reflect_checksummed(ChecksumAlgorithm::Adler32, |r| r.reflect_map(&mut self.notes))?;
+            reflector.reflect_compressed(CompressionCodec::Stored, |r| r.reflect_composite(&mut self.tree))?;
+            reflector.align(8)?;
+            reflector.reflect_bool(&mut self.flags)?;
+            reflector.reflect_frame(|r| r.reflect_f64(&mut self.scale))
+        }
+    }
+
+    fn drawing() -> Drawing {
+        Drawing {
+            title: "Схема".to_string(),
+            initial: 'λ',
+            shapes: vec![Shape::Circle(2.5), Shape::Empty, Shape::Polygon(vec![1, -2, -3, 4])],
+            layers: vec![true, false, true, true, false, false, true, false, true, true],
+            notes: vec![(1, "one".to_string()), (9, "nine".to_string())].into_iter().collect(),
+            tree: Tree {
+                label: "root".to_string(),
+                children: vec![
+                    Tree { label: "leaf".to_string(), children: vec![] },
+                    Tree {
+                        label: "branch".to_string(),
+                        children: vec![Tree { label: "twig".to_string(), children: vec![] }]
+                    }
+                ]
+            },
+            flags: true,
+            scale: -0.125
+        }
+    }
+
+    fn round_trip(endianness: Endianness, bytes: Vec<u8>) {
+        let schema = Schema::extract::<Drawing>(endianness).unwrap();
+        let mut stream = std::io::Cursor::new(bytes.clone());
+        let value = DynamicReader::new(&mut stream, &schema, ReflectorOptions::default()).read().unwrap();
+        assert_eq!(stream.position() as usize, bytes.len());
+
+        let Value::Struct(fields) = &value else { panic!("expected a struct, got {}", value) };
+        assert!(fields[1].same_as(&Value::String("Схема".to_string())));
+        assert!(fields[2].same_as(&Value::U32('λ' as u32)));
+        let Value::Array(shapes) = &fields[3] else { panic!("expected shapes, got {}", fields[3]) };
+        assert!(matches!(shapes[2], Value::Tagged(7, _)));
+
+        let mut written = Vec::new();
+        DynamicWriter::new(&mut written, &schema, Default::default()).write(&value).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn test_dynamic_round_trip() {
+        let mut little = Vec::new();
+        drawing().reflect(&mut BinaryWriterLittleEndian::new(&mut little)).unwrap();
+        round_trip(Endianness::LittleEndian, little);
+
+        let mut big = Vec::new();
+        drawing().reflect(&mut BinaryWriterBigEndian::new(&mut big)).unwrap();
+        round_trip(Endianness::BigEndian, big);
+
+        let mut negative_zero = drawing();
+        negative_zero.scale = -0.0;
+        let mut canonical = Vec::new();
+        negative_zero.serialize_ext(&mut canonical, Endianness::LittleEndian, ReflectorOptions::canonical()).unwrap();
+        let schema = Schema::extract::<Drawing>(Endianness::LittleEndian).unwrap();
+        let mut value = DynamicReader::new(&mut &canonical[..], &schema, Default::default()).read().unwrap();
+        let Value::Struct(fields) = &mut value else { unreachable!() };
+        let Some(Value::Struct(frame)) = fields.last_mut() else { panic!("expected the scale frame") };
+        frame[0] = Value::F64(-0.0);
+        let mut lenient = Vec::new();
+        DynamicWriter::new(&mut lenient, &schema, Default::default()).write(&value).unwrap();
+        assert_ne!(lenient, canonical);
+        let mut written = Vec::new();
+        DynamicWriter::new(&mut written, &schema, ReflectorOptions::canonical()).write(&value).unwrap();
+        assert_eq!(written, canonical);
+    }
+
+    #[test]
+    fn test_dynamic_rejects_mismatches() {
+        let schema = Schema::extract::<Drawing>(Endianness::LittleEndian).unwrap();
+        let mut bytes = Vec::new();
+        drawing().reflect(&mut BinaryWriterLittleEndian::new(&mut bytes)).unwrap();
+
+        let mut tampered = bytes.clone();
+        tampered[0] = b'X';
+        let mut stream = std::io::Cursor::new(tampered);
+        assert!(DynamicReader::new(&mut stream, &schema, ReflectorOptions::default()).read().is_err());
+
+        let shallow = ReflectorOptions { max_depth: 4, ..Default::default() };
+        let mut stream = std::io::Cursor::new(bytes.clone());
+        let error = DynamicReader::new(&mut stream, &schema, shallow).read().unwrap_err();
+        assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::DepthLimitExceeded { limit: 4 }));
+
+        let mut stream = std::io::Cursor::new(bytes);
+        let value = DynamicReader::new(&mut stream, &schema, ReflectorOptions::default()).read().unwrap();
+        let Value::Struct(mut fields) = value else { unreachable!() };
+        fields[1] = Value::U64(1);
+        let mut written = Vec::new();
+        assert!(DynamicWriter::new(&mut written, &schema, Default::default()).write(&Value::Struct(fields)).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, AeadCore, AeadInPlace, Nonce, Tag};
+use chacha20poly1305::aead::OsRng;
+use crate::ReflectError;
+
+pub(crate) const NONCE_SIZE: usize = 12;
+pub(crate) const TAG_SIZE: usize = 16;
+
+pub(crate) fn seal(key: &[u8; 32], data: &mut [u8]) -> std::io::Result<([u8; NONCE_SIZE], [u8; TAG_SIZE])> {
+    let cipher = ChaCha20Poly1305::new(key.into());
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let tag = cipher.encrypt_in_place_detached(&nonce, &[], data)
+        .map_err(|_| std::io::Error::other("failed to encrypt a section"))?;
+    Ok((nonce.into(), tag.into()))
+}
+
+pub(crate) fn open(
+    key: &[u8; 32],
+    nonce: &[u8; NONCE_SIZE],
+    tag: &[u8; TAG_SIZE],
+    data: &mut [u8]
+) -> std::io::Result<()> {
+    let cipher = ChaCha20Poly1305::new(key.into());
+    cipher.decrypt_in_place_detached(Nonce::from_slice(nonce), &[], data, Tag::from_slice(tag))
+        .map_err(|_| ReflectError::AuthenticationFailed.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NONCE_SIZE, TAG_SIZE};
+    use crate::{
+        ReflectError, ReflectorOptions, SerializationReflector, BinaryWriterLittleEndian, BinaryReaderLittleEndian
+    };
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    fn write_save(score: u32, name: &str) -> Vec<u8> {
+        let mut score = score;
+        let mut name = name.to_string();
+        let mut trailer = 0xABu8;
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_encrypted(&KEY, |w| {
+            w.reflect_u32(&mut score)?;
+            w.reflect_string(&mut name)
+        }).unwrap();
+        serializer.reflect_u8(&mut trailer).unwrap();
+        stream_vec
+    }
+
+    fn read_save(bytes: &[u8], key: &[u8; 32]) -> std::io::Result<(u32, String, u8)> {
+        let (mut score, mut name, mut trailer) = (0, String::new(), 0);
+        let mut stream = bytes;
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        deserializer.reflect_encrypted(key, |r| {
+            r.reflect_u32(&mut score)?;
+            r.reflect_string(&mut name)
+        })?;
+        deserializer.reflect_u8(&mut trailer)?;
+        Ok((score, name, trailer))
+    }
+
+    #[test]
+    fn test_encrypted_sections() {
+        let bytes = write_save(1337, "player one");
+        assert_ne!(bytes, write_save(1337, "player one"));
+        assert!(!bytes.windows(10).any(|window| window == b"player one"));
+        assert_eq!(read_save(&bytes, &KEY).unwrap(), (1337, "player one".to_string(), 0xAB));
+
+        let error = read_save(&bytes, &[0x24; 32]).unwrap_err();
+        assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::AuthenticationFailed));
+
+        let ciphertext_end = bytes.len() - 1;
+        for index in (0..NONCE_SIZE + TAG_SIZE).chain(ciphertext_end - 4..ciphertext_end) {
+            let mut tampered = bytes.clone();
+            tampered[index] ^= 0x01;
+            let error = read_save(&tampered, &KEY).unwrap_err();
+            assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::AuthenticationFailed));
+        }
+    }
+
+    #[test]
+    fn test_encrypted_sections_are_not_canonical() {
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::with_options(&mut stream_vec, ReflectorOptions::canonical());
+        assert!(serializer.reflect_encrypted(&KEY, |w| w.reflect_u32(&mut 7)).is_err());
+
+        let bytes = write_save(7, "x");
+        let mut stream = &bytes[..];
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, ReflectorOptions::canonical());
+        assert!(deserializer.reflect_encrypted(&KEY, |r| r.reflect_u32(&mut 0)).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: f32,
+}
+
+impl bin_serialization_rs::Reflectable for Point {
+    fn reflect<TSerializationReflector: bin_serialization_rs::SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_i32(&mut self.x)?;
+        reflector.reflect_f16(&mut self.y)?;
+        Ok(())
+    }
+
+    fn schema_name() -> String {
+        "Point".to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Empty,
+    Circle { center: Point, radius: f32 },
+    Polygon { points: Vec<Point>, label: String },
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::Empty
+    }
+}
+
+impl bin_serialization_rs::TaggedReflectable for Shape {
+    fn get_size_policy() -> bin_serialization_rs::SizePolicy {
+        bin_serialization_rs::SizePolicy::U16
+    }
+
+    fn get_tag(&self) -> u64 {
+        match self {
+            Shape::Empty => 0,
+            Shape::Circle { .. } => 3,
+            Shape::Polygon { .. } => 7,
+        }
+    }
+
+    fn variant_tags() -> Vec<u64> {
+        vec![0, 3, 7]
+    }
+
+    fn schema_name() -> String {
+        "Shape".to_string()
+    }
+
+    fn reflect_tagged<TSerializationReflector: bin_serialization_rs::SerializationReflector>(
+        &mut self,
+        tag: u64,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        if <Self as bin_serialization_rs::TaggedReflectable>::get_tag(self) != tag {
+            *self = match tag {
+                0 => Shape::Empty,
+                3 => Shape::Circle { center: Default::default(), radius: Default::default() },
+                7 => Shape::Polygon { points: Default::default(), label: Default::default() },
+                _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown Shape tag"))
+            };
+        }
+        match self {
+            Shape::Empty => {},
+            Shape::Circle { center, radius } => {
+                reflector.reflect_composite(center)?;
+                reflector.reflect_f32(radius)?;
+            },
+            Shape::Polygon { points, label } => {
+                reflector.reflect_array_of_composites_ext(points, bin_serialization_rs::SizePolicy::U16)?;
+                reflector.reflect_cp866_zstring_ext(16, label)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Scene {
+    pub name: String,
+    pub r#type: u8,
+    pub title: String,
+    pub glyph: char,
+    pub weights: Vec<f64>,
+    pub shapes: Vec<Shape>,
+    pub focus: Shape,
+    pub id: u128,
+}
+
+impl bin_serialization_rs::Reflectable for Scene {
+    fn reflect<TSerializationReflector: bin_serialization_rs::SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_string_ext(&mut self.name, bin_serialization_rs::SizePolicy::U8)?;
+        reflector.reflect_u8(&mut self.r#type)?;
+        reflector.reflect_cp866_string(&mut self.title)?;
+        reflector.reflect_char(&mut self.glyph, bin_serialization_rs::CharEncoding::Utf32)?;
+        reflector.reflect_f64_array(&mut self.weights)?;
+        reflector.reflect_array_of_tagged_composites(&mut self.shapes)?;
+        reflector.reflect_tagged_composite(&mut self.focus)?;
+        reflector.reflect_u128(&mut self.id)?;
+        Ok(())
+    }
+
+    fn schema_name() -> String {
+        "Scene".to_string()
+    }
+}
@@ -0,0 +1,234 @@
+use std::hash::Hasher;
+use crate::{
+    SerializationReflector, BinaryWriterLittleEndian, Checksum, ChecksumAlgorithm, CompressionCodec,
+    compression, reflect_digest
+};
+
+pub struct HashingReflector<H: Hasher> {
+    hasher: H,
+    position: u64,
+    version: u32,
+    captures: Vec<(Vec<u8>, u64)>,
+    taps: Vec<(usize, Checksum)>
+}
+
+impl<H: Hasher> HashingReflector<H> {
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher,
+            position: 0,
+            version: u32::MAX,
+            captures: Vec::new(),
+            taps: Vec::new()
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+
+    pub fn into_inner(self) -> H {
+        self.hasher
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self.captures.last_mut() {
+            Some((data, _)) => data.extend_from_slice(bytes),
+            None => self.hasher.write(bytes)
+        }
+        self.position += bytes.len() as u64;
+        let depth = self.captures.len();
+        for (_, checksum) in self.taps.iter_mut().filter(|(tap_depth, _)| *tap_depth == depth) {
+            checksum.update(bytes);
+        }
+        Ok(())
+    }
+
+    fn capture<F>(&mut self, body: F) -> std::io::Result<Vec<u8>>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.captures.push((Vec::new(), self.position));
+        self.position = 0;
+        let result = body(self);
+        let (data, outer_position) = self.captures.pop().unwrap();
+        self.position = outer_position;
+        result.map(|_| data)
+    }
+
+    fn write_encoded<F>(&mut self, encode: F) -> std::io::Result<()>
+        where F: FnOnce(&mut BinaryWriterLittleEndian<Vec<u8>>) -> std::io::Result<()>
+    {
+        let mut encoded = Vec::new();
+        encode(&mut BinaryWriterLittleEndian::new(&mut encoded))?;
+        self.write_bytes(&encoded)
+    }
+}
+
+impl<H: Hasher> SerializationReflector for HashingReflector<H> {
+    fn is_reading(&self) -> bool {
+        false
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut captured = self.capture(body)?;
+        self.reflect_u8_array(&mut captured)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.taps.push((self.captures.len(), Checksum::new(algorithm)));
+        let result = body(self);
+        let (_, checksum) = self.taps.pop().unwrap();
+        result?;
+        reflect_digest(self, algorithm, checksum.finish())
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let captured = self.capture(body)?;
+        let mut compressed = compression::compress(codec, &captured)?;
+        self.reflect_u8_array(&mut compressed)
+    }
+
+    // The ciphertext carries a random nonce, so the plaintext is hashed instead.
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.reflect_frame(body)
+    }
+
+    fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_u128(&mut self, data: &mut u128) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_i128(&mut self, data: &mut i128) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
+        self.write_bytes(&data.to_le_bytes())
+    }
+
+    fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.write_encoded(|w| w.reflect_cp866_string(string))
+    }
+
+    fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.write_encoded(|w| w.reflect_cp866_zstring(string))
+    }
+
+    fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        self.write_encoded(|w| w.reflect_cp866_zstring_ext(length, string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{
+        Reflectable, SerializationReflector, HashingReflector, XxHash64, ChecksumAlgorithm,
+        CompressionCodec, Endianness, SizePolicy
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Asset {
+        name: String,
+        label: String,
+        pixels: Vec<u16>,
+        tags: BTreeMap<u32, String>,
+        scale: f64
+    }
+
+    impl Reflectable for Asset {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string(&mut self.name)?;
+            reflector.reflect_cp866_zstring(&mut self.label)?;
+            reflector.reflect_frame(|r| r.reflect_u16_array_ext(&mut self.pixels, SizePolicy::U32))?;
+            reflector.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| r.reflect_map(&mut self.tags))?;
+            reflector.reflect_compressed(CompressionCodec::Stored, |r| r.reflect_f64(&mut self.scale))
+        }
+    }
+
+    #[test]
+    fn test_content_hash_matches_serialized_bytes() {
+        let mut asset = Asset {
+            name: "grass".to_string(),
+            label: "tile".to_string(),
+            pixels: (0..300).collect(),
+            tags: vec![(1, "ground".to_string()), (7, "green".to_string())].into_iter().collect(),
+            scale: 0.5
+        };
+        let mut bytes = Vec::new();
+        asset.serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+
+        let hash = asset.content_hash().unwrap();
+        let mut expected = XxHash64::new();
+        expected.update(&bytes);
+        assert_eq!(hash, expected.finish());
+
+        let mut reflector = HashingReflector::new(XxHash64::new());
+        asset.reflect(&mut reflector).unwrap();
+        assert_eq!(reflector.position(), bytes.len() as u64);
+        assert_eq!(reflector.finish(), hash);
+
+        let mut changed = asset.clone();
+        changed.pixels[299] += 1;
+        assert_ne!(changed.content_hash().unwrap(), hash);
+    }
+}
@@ -0,0 +1,873 @@
+use std::path::Path;
+use crate::{SizePolicy, CharEncoding, SizeEncoding};
+
+const CRATE: &str = "bin_serialization_rs";
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum IdlType {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F16,
+    F32,
+    F64,
+    Bool,
+    Char(CharEncoding),
+    String(SizeEncoding),
+    Cp866String,
+    Cp866ZString,
+    Cp866FixedZString(u64),
+    Array { size: SizeEncoding, element: Box<IdlType> },
+    Named(String)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IdlField {
+    pub name: String,
+    pub ty: IdlType
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IdlVariant {
+    pub tag: u64,
+    pub name: String,
+    pub fields: Vec<IdlField>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum IdlDefinition {
+    Struct { name: String, fields: Vec<IdlField> },
+    Union { name: String, tag: SizePolicy, variants: Vec<IdlVariant> }
+}
+
+impl IdlDefinition {
+    pub fn name(&self) -> &str {
+        match self {
+            IdlDefinition::Struct { name, .. } | IdlDefinition::Union { name, .. } => name
+        }
+    }
+}
+
+/// A parsed IDL file:
+///
+/// ```text
+/// struct Point { x: i32; y: i32; }
+///
+/// union Shape : u16 {
+///     0 Empty;
+///     3 Circle { center: Point; radius: f16; }
+///     7 Polygon { points: array<Point, u16>; label: cp866z<16>; }
+/// }
+/// ```
+///
+/// Field types are the primitives `u8`..`i128`, `f16`, `f32`, `f64` and `bool`, `char<utf8|utf32>`,
+/// `string` or `string<u8|u16|u32|u64>` for utf-8, `cp866`, `cp866z` and `cp866z<N>`,
+/// `array<T>` or `array<T, u8|u16|u32|u64>`, and the names of other definitions.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Idl {
+    pub definitions: Vec<IdlDefinition>
+}
+
+impl Idl {
+    pub fn parse(source: &str) -> std::io::Result<Self> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0 };
+        let mut definitions = Vec::new();
+        while !parser.at_end() {
+            definitions.push(parser.parse_definition()?);
+        }
+        let idl = Self { definitions };
+        idl.validate()?;
+        Ok(idl)
+    }
+
+    pub fn to_rust(&self) -> String {
+        let mut output = String::new();
+        for definition in self.definitions.iter() {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            match definition {
+                IdlDefinition::Struct { name, fields } => emit_struct(&mut output, &self.definitions, name, fields),
+                IdlDefinition::Union { name, tag, variants } =>
+                    emit_union(&mut output, &self.definitions, name, *tag, variants)
+            }
+        }
+        output
+    }
+
+    fn find(&self, name: &str) -> Option<&IdlDefinition> {
+        self.definitions.iter().find(|definition| definition.name() == name)
+    }
+
+    fn validate(&self) -> std::io::Result<()> {
+        for (i, definition) in self.definitions.iter().enumerate() {
+            if self.definitions[..i].iter().any(|other| other.name() == definition.name()) {
+                return Err(idl_error(format!("`{}` is defined more than once", definition.name())));
+            }
+            validate_type_name(definition.name())?;
+            match definition {
+                IdlDefinition::Struct { name, fields } => self.validate_fields(name, fields)?,
+                IdlDefinition::Union { name, tag, variants } => {
+                    if variants.is_empty() {
+                        return Err(idl_error(format!("union `{}` has no variants", name)));
+                    }
+                    let max_tag = match tag {
+                        SizePolicy::U8 => u8::MAX as u64,
+                        SizePolicy::U16 => u16::MAX as u64,
+                        SizePolicy::U32 => u32::MAX as u64,
+                        SizePolicy::U64 => u64::MAX
+                    };
+                    for (j, variant) in variants.iter().enumerate() {
+                        if variants[..j].iter().any(|other| other.tag == variant.tag) {
+                            return Err(idl_error(format!("`{}` uses tag {} more than once", name, variant.tag)));
+                        }
+                        if variant.tag > max_tag {
+                            return Err(idl_error(format!(
+                                "`{}::{}` has tag {}, which does not fit a {:?} tag", name, variant.name, variant.tag, tag
+                            )));
+                        }
+                        validate_type_name(&variant.name)?;
+                        if variants[..j].iter().any(|other| other.name == variant.name) {
+                            return Err(idl_error(format!("`{}` has more than one `{}` variant", name, variant.name)));
+                        }
+                        if variant.fields.iter().any(|field| field.name == "reflector") {
+                            return Err(idl_error(format!(
+                                "`{}::{}` uses the reserved field name `reflector`", name, variant.name
+                            )));
+                        }
+                        self.validate_fields(&format!("{}::{}", name, variant.name), &variant.fields)?;
+                    }
+                }
+            }
+            self.check_containment(definition.name(), definition, &mut Vec::new())?;
+        }
+        Ok(())
+    }
+
+    fn validate_fields(&self, owner: &str, fields: &[IdlField]) -> std::io::Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|other| other.name == field.name) {
+                return Err(idl_error(format!("`{}` has more than one `{}` field", owner, field.name)));
+            }
+            // These can't be written as raw identifiers either
+            if ["self", "Self", "super", "crate", "_"].contains(&field.name.as_str()) {
+                return Err(idl_error(format!("`{}` cannot have a field named `{}`", owner, field.name)));
+            }
+            self.validate_type(owner, &field.ty)?;
+        }
+        Ok(())
+    }
+
+    fn validate_type(&self, owner: &str, ty: &IdlType) -> std::io::Result<()> {
+        match ty {
+            IdlType::Named(name) if self.find(name).is_none() =>
+                Err(idl_error(format!("`{}` refers to undefined type `{}`", owner, name))),
+            IdlType::Array { element, .. } => match element.as_ref() {
+                IdlType::String(_) | IdlType::Cp866String | IdlType::Cp866ZString |
+                IdlType::Cp866FixedZString(_) | IdlType::Array { .. } =>
+                    Err(idl_error(format!("`{}` uses an unsupported array element type", owner))),
+                element => self.validate_type(owner, element)
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Rejects types that contain themselves by value, which would have no finite size in Rust.
+    fn check_containment<'a>(
+        &'a self,
+        root: &str,
+        definition: &'a IdlDefinition,
+        visiting: &mut Vec<&'a str>
+    ) -> std::io::Result<()> {
+        if visiting.contains(&definition.name()) {
+            return Ok(());
+        }
+        visiting.push(definition.name());
+        let fields: Vec<&IdlField> = match definition {
+            IdlDefinition::Struct { fields, .. } => fields.iter().collect(),
+            IdlDefinition::Union { variants, .. } => variants.iter().flat_map(|variant| variant.fields.iter()).collect()
+        };
+        for field in fields {
+            if let IdlType::Named(name) = &field.ty {
+                if name == root {
+                    return Err(idl_error(format!("`{}` contains itself; wrap the field in an array", root)));
+                }
+                if let Some(inner) = self.find(name) {
+                    self.check_containment(root, inner, visiting)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build script entry point: reads an IDL file and writes the generated Rust source,
+/// typically into `OUT_DIR` for an `include!`.
+pub fn compile_idl<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> std::io::Result<()> {
+    let source = std::fs::read_to_string(input)?;
+    let idl = Idl::parse(&source)?;
+    std::fs::write(output, idl.to_rust())
+}
+
+fn idl_error(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// Type and variant names are emitted as plain identifiers, unlike fields, which fall back to raw ones
+fn validate_type_name(name: &str) -> std::io::Result<()> {
+    if KEYWORDS.contains(&name) || BUILTIN_TYPES.contains(&name) || name == "_" {
+        return Err(idl_error(format!("`{}` cannot be used as a type or variant name", name)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Symbol(char)
+}
+
+fn tokenize(source: &str) -> std::io::Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' => line += 1,
+            _ if ch.is_whitespace() => {},
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|ch| *ch != '\n') {
+                    chars.next();
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(ch) => {
+                            if ch == '\n' {
+                                line += 1;
+                            }
+                            previous = ch;
+                        },
+                        None => return Err(idl_error(format!("line {}: unterminated comment", line)))
+                    }
+                }
+            },
+            '{' | '}' | '<' | '>' | ':' | ';' | ',' => tokens.push((Token::Symbol(ch), line)),
+            _ if ch.is_ascii_digit() => {
+                let mut number = ch.to_string();
+                while let Some(digit) = chars.peek().filter(|ch| ch.is_ascii_alphanumeric()) {
+                    number.push(*digit);
+                    chars.next();
+                }
+                let value = number.parse()
+                    .map_err(|_| idl_error(format!("line {}: invalid number `{}`", line, number)))?;
+                tokens.push((Token::Number(value), line));
+            },
+            _ if ch.is_ascii_alphabetic() || ch == '_' => {
+                let mut ident = ch.to_string();
+                while let Some(next) = chars.peek().filter(|ch| ch.is_ascii_alphanumeric() || **ch == '_') {
+                    ident.push(*next);
+                    chars.next();
+                }
+                tokens.push((Token::Ident(ident), line));
+            },
+            _ => return Err(idl_error(format!("line {}: unexpected character `{}`", line, ch)))
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    fn error(&self, expected: &str) -> std::io::Error {
+        match self.tokens.get(self.position) {
+            Some((token, line)) => idl_error(format!("line {}: expected {}, found {:?}", line, expected, token)),
+            None => idl_error(format!("expected {}, found end of input", expected))
+        }
+    }
+
+    fn peek_symbol(&self, symbol: char) -> bool {
+        matches!(self.tokens.get(self.position), Some((Token::Symbol(found), _)) if *found == symbol)
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        let found = self.peek_symbol(symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> std::io::Result<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{}`", symbol)))
+        }
+    }
+
+    fn expect_ident(&mut self) -> std::io::Result<String> {
+        match self.tokens.get(self.position) {
+            Some((Token::Ident(ident), _)) => {
+                self.position += 1;
+                Ok(ident.clone())
+            },
+            _ => Err(self.error("an identifier"))
+        }
+    }
+
+    fn expect_number(&mut self) -> std::io::Result<u64> {
+        match self.tokens.get(self.position) {
+            Some((Token::Number(number), _)) => {
+                self.position += 1;
+                Ok(*number)
+            },
+            _ => Err(self.error("a number"))
+        }
+    }
+
+    fn parse_definition(&mut self) -> std::io::Result<IdlDefinition> {
+        let keyword = self.expect_ident()?;
+        match keyword.as_str() {
+            "struct" => {
+                let name = self.expect_ident()?;
+                let fields = self.parse_fields()?;
+                Ok(IdlDefinition::Struct { name, fields })
+            },
+            "union" => {
+                let name = self.expect_ident()?;
+                let tag = if self.eat_symbol(':') { self.parse_size_policy()? } else { SizePolicy::U8 };
+                self.expect_symbol('{')?;
+                let mut variants = Vec::new();
+                while !self.eat_symbol('}') {
+                    let tag = self.expect_number()?;
+                    let name = self.expect_ident()?;
+                    let fields = if self.eat_symbol(';') {
+                        Vec::new()
+                    } else {
+                        let fields = self.parse_fields()?;
+                        self.eat_symbol(';');
+                        fields
+                    };
+                    variants.push(IdlVariant { tag, name, fields });
+                }
+                Ok(IdlDefinition::Union { name, tag, variants })
+            },
+            _ => {
+                self.position -= 1;
+                Err(self.error("`struct` or `union`"))
+            }
+        }
+    }
+
+    fn parse_fields(&mut self) -> std::io::Result<Vec<IdlField>> {
+        self.expect_symbol('{')?;
+        let mut fields = Vec::new();
+        while !self.eat_symbol('}') {
+            let name = self.expect_ident()?;
+            self.expect_symbol(':')?;
+            let ty = self.parse_type()?;
+            self.expect_symbol(';')?;
+            fields.push(IdlField { name, ty });
+        }
+        Ok(fields)
+    }
+
+    fn parse_size_policy(&mut self) -> std::io::Result<SizePolicy> {
+        match self.expect_ident()?.as_str() {
+            "u8" => Ok(SizePolicy::U8),
+            "u16" => Ok(SizePolicy::U16),
+            "u32" => Ok(SizePolicy::U32),
+            "u64" => Ok(SizePolicy::U64),
+            _ => {
+                self.position -= 1;
+                Err(self.error("a size policy"))
+            }
+        }
+    }
+
+    fn parse_size_encoding(&mut self) -> std::io::Result<SizeEncoding> {
+        if self.eat_symbol('<') {
+            let size_policy = self.parse_size_policy()?;
+            self.expect_symbol('>')?;
+            Ok(SizeEncoding::Fixed(size_policy))
+        } else {
+            Ok(SizeEncoding::Tagged)
+        }
+    }
+
+    fn parse_type(&mut self) -> std::io::Result<IdlType> {
+        let ident = self.expect_ident()?;
+        Ok(match ident.as_str() {
+            "u8" => IdlType::U8,
+            "u16" => IdlType::U16,
+            "u32" => IdlType::U32,
+            "u64" => IdlType::U64,
+            "u128" => IdlType::U128,
+            "i8" => IdlType::I8,
+            "i16" => IdlType::I16,
+            "i32" => IdlType::I32,
+            "i64" => IdlType::I64,
+            "i128" => IdlType::I128,
+            "f16" => IdlType::F16,
+            "f32" => IdlType::F32,
+            "f64" => IdlType::F64,
+            "bool" => IdlType::Bool,
+            "char" => {
+                self.expect_symbol('<')?;
+                let encoding = match self.expect_ident()?.as_str() {
+                    "utf8" => CharEncoding::Utf8,
+                    "utf32" => CharEncoding::Utf32,
+                    _ => {
+                        self.position -= 1;
+                        return Err(self.error("`utf8` or `utf32`"));
+                    }
+                };
+                self.expect_symbol('>')?;
+                IdlType::Char(encoding)
+            },
+            "string" => IdlType::String(self.parse_size_encoding()?),
+            "cp866" => IdlType::Cp866String,
+            "cp866z" if self.eat_symbol('<') => {
+                let length = self.expect_number()?;
+                self.expect_symbol('>')?;
+                IdlType::Cp866FixedZString(length)
+            },
+            "cp866z" => IdlType::Cp866ZString,
+            "array" => {
+                self.expect_symbol('<')?;
+                let element = Box::new(self.parse_type()?);
+                let size = if self.eat_symbol(',') {
+                    SizeEncoding::Fixed(self.parse_size_policy()?)
+                } else {
+                    SizeEncoding::Tagged
+                };
+                self.expect_symbol('>')?;
+                IdlType::Array { size, element }
+            },
+            _ => IdlType::Named(ident)
+        })
+    }
+}
+
+fn rust_type(ty: &IdlType) -> String {
+    match ty {
+        IdlType::U8 => "u8".to_string(),
+        IdlType::U16 => "u16".to_string(),
+        IdlType::U32 => "u32".to_string(),
+        IdlType::U64 => "u64".to_string(),
+        IdlType::U128 => "u128".to_string(),
+        IdlType::I8 => "i8".to_string(),
+        IdlType::I16 => "i16".to_string(),
+        IdlType::I32 => "i32".to_string(),
+        IdlType::I64 => "i64".to_string(),
+        IdlType::I128 => "i128".to_string(),
+        IdlType::F16 | IdlType::F32 => "f32".to_string(),
+        IdlType::F64 => "f64".to_string(),
+        IdlType::Bool => "bool".to_string(),
+        IdlType::Char(_) => "char".to_string(),
+        IdlType::String(_) | IdlType::Cp866String | IdlType::Cp866ZString | IdlType::Cp866FixedZString(_) =>
+            "String".to_string(),
+        IdlType::Array { element, .. } => format!("Vec<{}>", rust_type(element)),
+        IdlType::Named(name) => name.clone()
+    }
+}
+
+fn primitive_name(ty: &IdlType) -> Option<&'static str> {
+    match ty {
+        IdlType::U8 => Some("u8"),
+        IdlType::U16 => Some("u16"),
+        IdlType::U32 => Some("u32"),
+        IdlType::U64 => Some("u64"),
+        IdlType::U128 => Some("u128"),
+        IdlType::I8 => Some("i8"),
+        IdlType::I16 => Some("i16"),
+        IdlType::I32 => Some("i32"),
+        IdlType::I64 => Some("i64"),
+        IdlType::I128 => Some("i128"),
+        IdlType::F16 => Some("f16"),
+        IdlType::F32 => Some("f32"),
+        IdlType::F64 => Some("f64"),
+        IdlType::Bool => Some("bool"),
+        _ => None
+    }
+}
+
+fn size_policy_path(size_policy: SizePolicy) -> String {
+    format!("{}::SizePolicy::{:?}", CRATE, size_policy)
+}
+
+fn char_encoding_path(encoding: CharEncoding) -> String {
+    format!("{}::CharEncoding::{:?}", CRATE, encoding)
+}
+
+/// `place` is an expression of type `&mut T` for the field being reflected.
+fn reflect_call(idl: &[IdlDefinition], ty: &IdlType, place: &str) -> String {
+    let call = |method: &str, arguments: Vec<String>| {
+        let mut arguments = arguments;
+        arguments.insert(0, place.to_string());
+        format!("reflector.{}({})?;", method, arguments.join(", "))
+    };
+    let is_union = |name: &str| idl.iter()
+        .any(|definition| matches!(definition, IdlDefinition::Union { name: union_name, .. } if union_name == name));
+    match ty {
+        IdlType::Char(encoding) => call("reflect_char", vec![char_encoding_path(*encoding)]),
+        IdlType::String(SizeEncoding::Tagged) => call("reflect_string", vec![]),
+        IdlType::String(SizeEncoding::Fixed(size_policy)) =>
+            call("reflect_string_ext", vec![size_policy_path(*size_policy)]),
+        IdlType::Cp866String => call("reflect_cp866_string", vec![]),
+        IdlType::Cp866ZString => call("reflect_cp866_zstring", vec![]),
+        IdlType::Cp866FixedZString(length) =>
+            format!("reflector.reflect_cp866_zstring_ext({}, {})?;", length, place),
+        IdlType::Named(name) if is_union(name) => call("reflect_tagged_composite", vec![]),
+        IdlType::Named(_) => call("reflect_composite", vec![]),
+        IdlType::Array { size, element } => {
+            let (method, mut arguments) = match element.as_ref() {
+                IdlType::Char(encoding) => ("reflect_char_array".to_string(), vec![char_encoding_path(*encoding)]),
+                IdlType::Named(name) if is_union(name) => ("reflect_array_of_tagged_composites".to_string(), vec![]),
+                IdlType::Named(_) => ("reflect_array_of_composites".to_string(), vec![]),
+                element => (format!("reflect_{}_array", primitive_name(element).unwrap_or("u8")), vec![])
+            };
+            match size {
+                SizeEncoding::Tagged => call(&method, arguments),
+                SizeEncoding::Fixed(size_policy) => {
+                    arguments.push(size_policy_path(*size_policy));
+                    call(&format!("{}_ext", method), arguments)
+                }
+            }
+        },
+        primitive => call(&format!("reflect_{}", primitive_name(primitive).unwrap_or("u8")), vec![])
+    }
+}
+
+// Strict and reserved keywords of every edition up to 2024
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self", "Self", "static", "struct",
+    "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while",
+    "yield"
+];
+
+// Types the generated code refers to by name, which a definition of the same name would shadow
+const BUILTIN_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128", "f32", "f64", "bool", "char", "str",
+    "String", "Vec"
+];
+
+fn field_name(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn emit_struct(output: &mut String, idl: &[IdlDefinition], name: &str, fields: &[IdlField]) {
+    output.push_str("#[derive(Clone, Default, Debug, PartialEq)]\n");
+    output.push_str(&format!("pub struct {} {{\n", name));
+    for field in fields {
+        output.push_str(&format!("    pub {}: {},\n", field_name(&field.name), rust_type(&field.ty)));
+    }
+    output.push_str("}\n\n");
+    output.push_str(&format!("impl {}::Reflectable for {} {{\n", CRATE, name));
+    output.push_str(&format!(
+        "    fn reflect<TSerializationReflector: {}::SerializationReflector>(\n", CRATE
+    ));
+    output.push_str("        &mut self,\n        reflector: &mut TSerializationReflector\n");
+    output.push_str("    ) -> std::io::Result<()> {\n");
+    for field in fields {
+        let place = format!("&mut self.{}", field_name(&field.name));
+        output.push_str(&format!("        {}\n", reflect_call(idl, &field.ty, &place)));
+    }
+    output.push_str("        Ok(())\n    }\n\n");
+    output.push_str(&format!("    fn schema_name() -> String {{\n        \"{}\".to_string()\n    }}\n}}\n", name));
+}
+
+fn emit_union(
+    output: &mut String,
+    idl: &[IdlDefinition],
+    name: &str,
+    tag: SizePolicy,
+    variants: &[IdlVariant]
+) {
+    let construct = |variant: &IdlVariant| if variant.fields.is_empty() {
+        format!("{}::{}", name, variant.name)
+    } else {
+        let fields: Vec<String> = variant.fields.iter()
+            .map(|field| format!("{}: Default::default()", field_name(&field.name)))
+            .collect();
+        format!("{}::{} {{ {} }}", name, variant.name, fields.join(", "))
+    };
+    let pattern = |variant: &IdlVariant, bind: bool| if variant.fields.is_empty() {
+        format!("{}::{}", name, variant.name)
+    } else if bind {
+        let fields: Vec<String> = variant.fields.iter().map(|field| field_name(&field.name)).collect();
+        format!("{}::{} {{ {} }}", name, variant.name, fields.join(", "))
+    } else {
+        format!("{}::{} {{ .. }}", name, variant.name)
+    };
+
+    output.push_str("#[derive(Clone, Debug, PartialEq)]\n");
+    output.push_str(&format!("pub enum {} {{\n", name));
+    for variant in variants {
+        if variant.fields.is_empty() {
+            output.push_str(&format!("    {},\n", variant.name));
+        } else {
+            let fields: Vec<String> = variant.fields.iter()
+                .map(|field| format!("{}: {}", field_name(&field.name), rust_type(&field.ty)))
+                .collect();
+            output.push_str(&format!("    {} {{ {} }},\n", variant.name, fields.join(", ")));
+        }
+    }
+    output.push_str("}\n\n");
+
+    output.push_str(&format!("impl Default for {} {{\n    fn default() -> Self {{\n", name));
+    output.push_str(&format!("        {}\n    }}\n}}\n\n", construct(&variants[0])));
+
+    output.push_str(&format!("impl {}::TaggedReflectable for {} {{\n", CRATE, name));
+    output.push_str(&format!(
+        "    fn get_size_policy() -> {}::SizePolicy {{\n        {}\n    }}\n\n",
+        CRATE, size_policy_path(tag)
+    ));
+    output.push_str("    fn get_tag(&self) -> u64 {\n        match self {\n");
+    for variant in variants {
+        output.push_str(&format!("            {} => {},\n", pattern(variant, false), variant.tag));
+    }
+    output.push_str("        }\n    }\n\n");
+    let tags: Vec<String> = variants.iter().map(|variant| variant.tag.to_string()).collect();
+    output.push_str(&format!("    fn variant_tags() -> Vec<u64> {{\n        vec![{}]\n    }}\n\n", tags.join(", ")));
+    output.push_str(&format!("    fn schema_name() -> String {{\n        \"{}\".to_string()\n    }}\n\n", name));
+    output.push_str(&format!(
+        "    fn reflect_tagged<TSerializationReflector: {}::SerializationReflector>(\n", CRATE
+    ));
+    output.push_str("        &mut self,\n        tag: u64,\n        reflector: &mut TSerializationReflector\n");
+    output.push_str("    ) -> std::io::Result<()> {\n");
+    output.push_str(&format!(
+        "        if <Self as {}::TaggedReflectable>::get_tag(self) != tag {{\n            *self = match tag {{\n",
+        CRATE
+    ));
+    for variant in variants {
+        output.push_str(&format!("                {} => {},\n", variant.tag, construct(variant)));
+    }
+    output.push_str(&format!(
+        "                _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, \"unknown {} tag\"))\n",
+        name
+    ));
+    output.push_str("            };\n        }\n        match self {\n");
+    for variant in variants {
+        if variant.fields.is_empty() {
+            output.push_str(&format!("            {} => {{}},\n", pattern(variant, true)));
+        } else {
+            output.push_str(&format!("            {} => {{\n", pattern(variant, true)));
+            for field in variant.fields.iter() {
+                output.push_str(&format!("                {}\n", reflect_call(idl, &field.ty, &field_name(&field.name))));
+            }
+            output.push_str("            },\n");
+        }
+    }
+    output.push_str("        }\n        Ok(())\n    }\n}\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Idl, IdlDefinition, IdlField, IdlType, IdlVariant, SizePolicy, SizeEncoding, CharEncoding, Endianness,
+        Reflectable, SerializationReflector, BinaryWriterLittleEndian, reflect_size, reflect_size_ext
+    };
+
+    #[allow(clippy::derivable_impls)]
+    mod generated {
+        extern crate self as bin_serialization_rs;
+        include!("fixtures/scene.rs");
+    }
+
+    #[test]
+    fn test_idl_parsing() {
+        let idl = Idl::parse("
+            // shapes
+            struct Point { x: i32; y: f16; }
+            union Shape : u16 {
+                0 Empty;
+                7 Polygon { points: array<Point, u8>; glyph: char<utf32>; } /* trailing */
+            }
+            struct Scene { name: string<u16>; title: cp866z<8>; shapes: array<Shape>; }
+        ").unwrap();
+        let field = |name: &str, ty: IdlType| IdlField { name: name.to_string(), ty };
+        assert_eq!(idl.definitions, vec![
+            IdlDefinition::Struct {
+                name: "Point".to_string(),
+                fields: vec![field("x", IdlType::I32), field("y", IdlType::F16)]
+            },
+            IdlDefinition::Union {
+                name: "Shape".to_string(),
+                tag: SizePolicy::U16,
+                variants: vec![
+                    IdlVariant { tag: 0, name: "Empty".to_string(), fields: vec![] },
+                    IdlVariant {
+                        tag: 7,
+                        name: "Polygon".to_string(),
+                        fields: vec![
+                            field("points", IdlType::Array {
+                                size: SizeEncoding::Fixed(SizePolicy::U8),
+                                element: Box::new(IdlType::Named("Point".to_string()))
+                            }),
+                            field("glyph", IdlType::Char(CharEncoding::Utf32))
+                        ]
+                    }
+                ]
+            },
+            IdlDefinition::Struct {
+                name: "Scene".to_string(),
+                fields: vec![
+                    field("name", IdlType::String(SizeEncoding::Fixed(SizePolicy::U16))),
+                    field("title", IdlType::Cp866FixedZString(8)),
+                    field("shapes", IdlType::Array {
+                        size: SizeEncoding::Tagged,
+                        element: Box::new(IdlType::Named("Shape".to_string()))
+                    })
+                ]
+            }
+        ]);
+    }
+
+    #[test]
+    fn test_rust_generation() {
+        let idl = Idl::parse("struct Pair { type: u8; values: array<f16, u32>; }").unwrap();
+        assert_eq!(idl.to_rust(), "\
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Pair {
+    pub r#type: u8,
+    pub values: Vec<f32>,
+}
+
+impl bin_serialization_rs::Reflectable for Pair {
+    fn reflect<TSerializationReflector: bin_serialization_rs::SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u8(&mut self.r#type)?;
+        reflector.reflect_f16_array_ext(&mut self.values, bin_serialization_rs::SizePolicy::U32)?;
+        Ok(())
+    }
+
+    fn schema_name() -> String {
+        \"Pair\".to_string()
+    }
+}
+");
+
+        let idl = Idl::parse("union Toggle { 1 On { level: u8; } 2 Off; }").unwrap();
+        let rust = idl.to_rust();
+        assert!(rust.contains("    On { level: u8 },\n    Off,\n"));
+        assert!(rust.contains("Toggle::On { level: Default::default() }\n    }\n}"));
+        assert!(rust.contains("        bin_serialization_rs::SizePolicy::U8\n"));
+        assert!(rust.contains("            Toggle::On { level } => {\n                reflector.reflect_u8(level)?;\n"));
+        assert!(rust.contains("        vec![1, 2]\n    }\n\n    fn schema_name() -> String {\n        \"Toggle\".to_string()\n"));
+    }
+
+    #[test]
+    fn test_generated_fixture() {
+        use generated::{Point, Shape, Scene};
+
+        let idl = Idl::parse(include_str!("fixtures/scene.idl")).unwrap();
+        assert_eq!(idl.to_rust(), include_str!("fixtures/scene.rs"));
+
+        let mut scene = Scene {
+            name: "demo".to_string(),
+            r#type: 2,
+            title: "hall".to_string(),
+            glyph: '€',
+            weights: vec![0.5, -1.0],
+            shapes: vec![
+                Shape::Circle { center: Point { x: -3, y: 1.5 }, radius: 2.0 },
+                Shape::Polygon { points: vec![Point { x: 1, y: 0.25 }], label: "tri".to_string() },
+                Shape::Empty
+            ],
+            focus: Shape::Empty,
+            id: 0x0102_0304_0506_0708_090A_0B0C_0D0E_0F10
+        };
+        let mut bytes = Vec::new();
+        scene.serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+
+        let mut expected = Vec::new();
+        let mut writer = BinaryWriterLittleEndian::new(&mut expected);
+        writer.reflect_string_ext(&mut "demo".to_string(), SizePolicy::U8).unwrap();
+        writer.reflect_u8(&mut 2).unwrap();
+        writer.reflect_cp866_string(&mut "hall".to_string()).unwrap();
+        writer.reflect_char(&mut '€', CharEncoding::Utf32).unwrap();
+        writer.reflect_f64_array(&mut vec![0.5, -1.0]).unwrap();
+        reflect_size(&mut writer, &mut 3).unwrap();
+        writer.reflect_u16(&mut 3).unwrap();
+        writer.reflect_i32(&mut -3).unwrap();
+        writer.reflect_f16(&mut 1.5).unwrap();
+        writer.reflect_f32(&mut 2.0).unwrap();
+        writer.reflect_u16(&mut 7).unwrap();
+        reflect_size_ext(&mut writer, &mut 1, SizePolicy::U16).unwrap();
+        writer.reflect_i32(&mut 1).unwrap();
+        writer.reflect_f16(&mut 0.25).unwrap();
+        writer.reflect_cp866_zstring_ext(16, &mut "tri".to_string()).unwrap();
+        writer.reflect_u16(&mut 0).unwrap();
+        writer.reflect_u16(&mut 0).unwrap();
+        writer.reflect_u128(&mut scene.id.clone()).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(Scene::deserialize(&mut &expected[..], Endianness::LittleEndian).unwrap(), scene);
+    }
+
+    #[test]
+    fn test_idl_errors() {
+        let invalid = [
+            "struct A { x: u8 }",
+            "struct A { x: B; }",
+            "struct A { x: u8; } struct A { y: u8; }",
+            "struct A { x: u8; x: u16; }",
+            "union A { 1 X; 1 Y; }",
+            "union A { }",
+            "union A : u24 { 1 X; }",
+            "struct A { names: array<string>; }",
+            "struct A { b: B; } struct B { a: A; }",
+            "struct A { x: char<utf16>; }",
+            "enum A { }",
+            "struct A { x: u8; } /* open",
+            "union A { 256 X; }",
+            "union A : u16 { 1 X; 65536 Y; }",
+            "union A : u32 { 4294967296 X; }",
+            "struct A { self: u8; }",
+            "struct A { crate: u8; }",
+            "union A { 1 X { super: u8; } }",
+            "struct A { try: u8; yield: u8; } struct try { x: u8; }",
+            "struct Vec { x: u8; }",
+            "struct String { x: u8; }",
+            "union u8 { 1 X; }",
+            "union A { 1 Self; }",
+            "union A { 1 box; }"
+        ];
+        for source in invalid.iter() {
+            assert!(Idl::parse(source).is_err(), "{}", source);
+        }
+        assert!(Idl::parse("struct A { children: array<A>; }").is_ok());
+        let idl = Idl::parse("struct A { try: u8; gen: u8; abstract: u8; }").unwrap();
+        assert!(idl.to_rust().contains("pub r#try: u8,\n    pub r#gen: u8,\n    pub r#abstract: u8,"));
+        assert!(Idl::parse("union A : u16 { 65535 X; } union B : u64 { 18446744073709551615 X; }").is_ok());
+    }
+}
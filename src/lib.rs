@@ -1,12 +1,54 @@
-use std::io::{Write, Read};
-
-#[derive(Copy, Clone, PartialEq)]
+use std::io::{Write, Read, Cursor, Seek, SeekFrom};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
+use std::hash::{Hash, BuildHasher};
+
+#[macro_use]
+mod macros;
+mod archive;
+mod bits;
+mod checksum;
+mod compatibility;
+mod compression;
+mod diff;
+mod dynamic;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod hashing;
+mod idl;
+mod patch;
+#[cfg(feature = "random")]
+mod random;
+mod record;
+mod schema;
+mod value;
+mod versioning;
+
+pub use archive::{ArchiveEntry, ArchiveWriter, ArchiveReader};
+pub use bits::BitReflector;
+pub use checksum::{Crc32, Adler32, XxHash64, Checksum, ChecksumAlgorithm, crc32};
+pub use compatibility::{Compatibility, LayoutChange, SchemaChange, check_compatibility, check_compatibility_ext};
+pub use compression::CompressionCodec;
+pub use diff::{Change, Difference, DiffReflector, diff};
+pub use dynamic::{DynamicReader, DynamicWriter};
+pub use hashing::HashingReflector;
+pub use idl::{Idl, IdlDefinition, IdlField, IdlType, IdlVariant, compile_idl};
+pub use patch::{Patch, PatchOperation, make_patch, apply_patch};
+#[cfg(feature = "random")]
+pub use random::{RandomFillReflector, RandomFillLimits, check_round_trip, check_random_round_trips};
+pub use record::{RecordReader, RecordWriter};
+pub use schema::{Schema, Layout, Variant, SizeEncoding, SchemaReflector};
+pub use value::Value;
+pub use versioning::{Versioned, VersionedReflectable};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Endianness {
     BigEndian,
     LittleEndian
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SizePolicy {
     U8,
     U16,
@@ -14,6 +56,107 @@ pub enum SizePolicy {
     U64
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CharEncoding {
+    Utf32,
+    Utf8
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ReflectorOptions {
+    pub reject_duplicate_keys: bool,
+    pub strict_reserved: bool,
+    pub strict_size_tags: bool,
+    pub canonical: bool,
+    pub max_decompressed_size: u64,
+    pub max_depth: usize,
+    pub max_empty_elements: usize
+}
+
+impl Default for ReflectorOptions {
+    fn default() -> Self {
+        Self {
+            reject_duplicate_keys: false,
+            strict_reserved: false,
+            strict_size_tags: false,
+            canonical: false,
+            max_decompressed_size: 64 * 1024 * 1024,
+            max_depth: 128,
+            // Elements that take up no bytes cost nothing to claim, so a corrupt size could otherwise
+            // spin through billions of them before the stream runs dry
+            max_empty_elements: 0x10000
+        }
+    }
+}
+
+impl ReflectorOptions {
+    // Canonical mode implies strict size tags and reserved bytes, and rejects duplicate keys by
+    // requiring them to be strictly ascending. Compressor output differs between library versions,
+    // so only stored sections are allowed. Encrypted sections draw a fresh nonce on every write, so
+    // they never re-encode to the same bytes and are rejected altogether
+    pub fn canonical() -> Self {
+        Self { canonical: true, ..Default::default() }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ReflectError {
+    BadMagic { expected: Vec<u8>, found: Vec<u8> },
+    UnsupportedVersion { version: u32, latest: u32 },
+    ChecksumMismatch { expected: u64, actual: u64 },
+    DecompressedSizeExceeded { limit: u64 },
+    AuthenticationFailed,
+    PatchBaseMismatch { expected: u64, actual: u64 },
+    InvalidSizeTag { tag: u8 },
+    NonCanonicalSizeTag { tag: u8, expected: u8 },
+    DepthLimitExceeded { limit: usize }
+}
+
+impl ReflectError {
+    pub fn from_io_error(error: &std::io::Error) -> Option<&ReflectError> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<ReflectError>())
+    }
+}
+
+impl std::fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReflectError::BadMagic { expected, found } =>
+                write!(f, "bad magic: expected {:02X?}, found {:02X?}", expected, found),
+            ReflectError::UnsupportedVersion { version, latest } =>
+                write!(f, "unsupported version {} (latest known is {})", version, latest),
+            ReflectError::ChecksumMismatch { expected, actual } =>
+                write!(f, "checksum mismatch: stored {:#X}, computed {:#X}", expected, actual),
+            ReflectError::DecompressedSizeExceeded { limit } =>
+                write!(f, "decompressed section is larger than {} bytes", limit),
+            ReflectError::AuthenticationFailed =>
+                write!(f, "encrypted section failed authentication"),
+            ReflectError::PatchBaseMismatch { expected, actual } =>
+                write!(f, "patch expects base {:#X}, found {:#X}", expected, actual),
+            ReflectError::InvalidSizeTag { tag } =>
+                write!(f, "invalid size tag {}", tag),
+            ReflectError::NonCanonicalSizeTag { tag, expected } =>
+                write!(f, "size stored with tag {} where {} was expected", tag, expected),
+            ReflectError::DepthLimitExceeded { limit } =>
+                write!(f, "nesting is deeper than {} levels", limit)
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+impl From<ReflectError> for std::io::Error {
+    fn from(error: ReflectError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
 fn check(cond: bool, errText: &str) -> std::io::Result<()> {
     if cond {
         Some(())
@@ -23,213 +166,297 @@ fn check(cond: bool, errText: &str) -> std::io::Result<()> {
 }
 
 pub trait SerializationReflector: Sized {
+    fn is_reading(&self) -> bool;
+    fn position(&self) -> u64;
+    // u32::MAX outside of any Versioned section, so that every field is present
+    fn version(&self) -> u32;
+    fn set_version(&mut self, version: u32);
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>;
+    // Reflectors implemented outside this crate don't have to know about sections
+    fn reflect_checksummed<F>(&mut self, _algorithm: ChecksumAlgorithm, _body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reflector does not support checksummed sections"))
+    }
+    fn reflect_compressed<F>(&mut self, _codec: CompressionCodec, _body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reflector does not support compressed sections"))
+    }
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], _body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reflector does not support encrypted sections"))
+    }
+    fn options(&self) -> ReflectorOptions {
+        ReflectorOptions::default()
+    }
+    // Byte order of multi-byte values, which the default wide integer reflection follows
+    fn endianness(&self) -> Endianness {
+        Endianness::LittleEndian
+    }
     fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()>;
     fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()>;
     fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()>;
     fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()>;
+    fn reflect_u128(&mut self, data: &mut u128) -> std::io::Result<()> {
+        let mut high = (*data >> 64) as u64;
+        let mut low = *data as u64;
+        match self.endianness() {
+            Endianness::BigEndian => {
+                self.reflect_u64(&mut high)?;
+                self.reflect_u64(&mut low)?;
+            },
+            Endianness::LittleEndian => {
+                self.reflect_u64(&mut low)?;
+                self.reflect_u64(&mut high)?;
+            }
+        }
+        *data = (high as u128) << 64 | low as u128;
+        Ok(())
+    }
     fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()>;
     fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()>;
     fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()>;
     fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()>;
+    fn reflect_i128(&mut self, data: &mut i128) -> std::io::Result<()> {
+        let mut bits = *data as u128;
+        self.reflect_u128(&mut bits)?;
+        *data = bits as i128;
+        Ok(())
+    }
     fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()>;
     fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()>;
     fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()>;
     fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()>;
     fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()>;
-    fn reflect_u8_array(&mut self, data: &mut Vec<u8>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_u8(&mut data[i])?;
+    fn reflect_f16(&mut self, data: &mut f32) -> std::io::Result<()> {
+        let canonical = self.options().canonical;
+        let mut bits = f32_to_f16_bits(if canonical { canonical_f32(*data) } else { *data });
+        self.reflect_u16(&mut bits)?;
+        if self.is_reading() {
+            *data = f16_bits_to_f32(bits);
+        }
+        check(!canonical || f32_to_f16_bits(canonical_f32(*data)) == bits, "non-canonical float")
+    }
+    fn reflect_char(&mut self, data: &mut char, encoding: CharEncoding) -> std::io::Result<()> {
+        match encoding {
+            CharEncoding::Utf32 => {
+                let mut code = *data as u32;
+                self.reflect_u32(&mut code)?;
+                *data = std::char::from_u32(code)
+                    .ok_or_else(|| std::io::Error::other("invalid utf-32 char"))?;
+            }
+            CharEncoding::Utf8 => {
+                let mut bytes = [0u8; 4];
+                data.encode_utf8(&mut bytes);
+                self.reflect_u8(&mut bytes[0])?;
+                let length = match bytes[0] {
+                    0x00..=0x7F => 1,
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => 0
+                };
+                check(length != 0, "invalid utf-8 char")?;
+                for byte in bytes[1..length].iter_mut() {
+                    self.reflect_u8(byte)?;
+                }
+                *data = std::str::from_utf8(&bytes[..length])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .ok_or_else(|| std::io::Error::other("invalid utf-8 char"))?;
+            }
         }
         Ok(())
     }
+    fn reflect_u8_array(&mut self, data: &mut Vec<u8>) -> std::io::Result<()> {
+        reflect_vec(self, data, None, |r, item| r.reflect_u8(item))
+    }
     fn reflect_u8_array_ext(&mut self, data: &mut Vec<u8>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_u8(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_u8(item))
     }
     fn reflect_u16_array(&mut self, data: &mut Vec<u16>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_u16(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_u16(item))
     }
     fn reflect_u16_array_ext(&mut self, data: &mut Vec<u16>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_u16(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_u16(item))
     }
     fn reflect_u32_array(&mut self, data: &mut Vec<u32>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_u32(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_u32(item))
     }
     fn reflect_u32_array_ext(&mut self, data: &mut Vec<u32>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_u32(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_u32(item))
     }
     fn reflect_u64_array(&mut self, data: &mut Vec<u64>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_u64(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_u64(item))
     }
     fn reflect_u64_array_ext(&mut self, data: &mut Vec<u64>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_u64(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_u64(item))
+    }
+    fn reflect_u128_array(&mut self, data: &mut Vec<u128>) -> std::io::Result<()> {
+        reflect_vec(self, data, None, |r, item| r.reflect_u128(item))
+    }
+    fn reflect_u128_array_ext(&mut self, data: &mut Vec<u128>, size_policy: SizePolicy) -> std::io::Result<()> {
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_u128(item))
     }
     fn reflect_i8_array(&mut self, data: &mut Vec<i8>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_i8(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_i8(item))
     }
     fn reflect_i8_array_ext(&mut self, data: &mut Vec<i8>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_i8(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_i8(item))
     }
     fn reflect_i16_array(&mut self, data: &mut Vec<i16>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_i16(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_i16(item))
     }
     fn reflect_i16_array_ext(&mut self, data: &mut Vec<i16>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_i16(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_i16(item))
     }
     fn reflect_i32_array(&mut self, data: &mut Vec<i32>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_i32(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_i32(item))
     }
     fn reflect_i32_array_ext(&mut self, data: &mut Vec<i32>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_i32(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_i32(item))
     }
     fn reflect_i64_array(&mut self, data: &mut Vec<i64>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_i64(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_i64(item))
     }
     fn reflect_i64_array_ext(&mut self, data: &mut Vec<i64>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_i64(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_i64(item))
+    }
+    fn reflect_i128_array(&mut self, data: &mut Vec<i128>) -> std::io::Result<()> {
+        reflect_vec(self, data, None, |r, item| r.reflect_i128(item))
+    }
+    fn reflect_i128_array_ext(&mut self, data: &mut Vec<i128>, size_policy: SizePolicy) -> std::io::Result<()> {
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_i128(item))
+    }
+    fn reflect_f16_array(&mut self, data: &mut Vec<f32>) -> std::io::Result<()> {
+        reflect_vec(self, data, None, |r, item| r.reflect_f16(item))
+    }
+    fn reflect_f16_array_ext(&mut self, data: &mut Vec<f32>, size_policy: SizePolicy) -> std::io::Result<()> {
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_f16(item))
     }
     fn reflect_f32_array(&mut self, data: &mut Vec<f32>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_f32(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_f32(item))
     }
     fn reflect_f32_array_ext(&mut self, data: &mut Vec<f32>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_f32(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_f32(item))
     }
     fn reflect_f64_array(&mut self, data: &mut Vec<f64>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_f64(&mut data[i as usize])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_f64(item))
     }
     fn reflect_f64_array_ext(&mut self, data: &mut Vec<f64>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_f64(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_f64(item))
+    }
+    fn reflect_char_array(&mut self, data: &mut Vec<char>, encoding: CharEncoding) -> std::io::Result<()> {
+        reflect_vec(self, data, None, |r, item| r.reflect_char(item, encoding))
+    }
+    fn reflect_char_array_ext(
+        &mut self,
+        data: &mut Vec<char>,
+        encoding: CharEncoding,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_char(item, encoding))
     }
     fn reflect_string(&mut self, string: &mut String) -> std::io::Result<()> {
-        unsafe {
-            let vec_repr = string.as_mut_vec();
-            self.reflect_u8_array(vec_repr)?;
-        }
-        Ok(())
+        reflect_utf8(self, string, None)
     }
     fn reflect_string_ext(&mut self, string: &mut String, size_policy: SizePolicy) -> std::io::Result<()> {
-        unsafe {
-            let vec_repr = string.as_mut_vec();
-            self.reflect_u8_array_ext(vec_repr, size_policy)?;
-        }
-        Ok(())
+        reflect_utf8(self, string, Some(size_policy))
     }
     fn reflect_bool(&mut self, data: &mut bool) -> std::io::Result<()> {
         let mut casted = if *data { 1 } else { 0 };
         self.reflect_u8(&mut casted)?;
         *data = casted != 0;
-        Ok(())
+        check(casted <= 1 || !self.options().canonical, "non-canonical bool")
     }
     fn reflect_bool_array(&mut self, data: &mut Vec<bool>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_bool(&mut data[i])?;
+        reflect_vec(self, data, None, |r, item| r.reflect_bool(item))
+    }
+    fn reflect_bool_array_ext(&mut self, data: &mut Vec<bool>, size_policy: SizePolicy) -> std::io::Result<()> {
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_bool(item))
+    }
+    fn reflect_packed_bool_array(&mut self, data: &mut Vec<bool>, bit_order: BitOrder) -> std::io::Result<()> {
+        reflect_packed_bool_vec(self, data, None, bit_order)
+    }
+    fn reflect_packed_bool_array_ext(
+        &mut self,
+        data: &mut Vec<bool>,
+        bit_order: BitOrder,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        reflect_packed_bool_vec(self, data, Some(size_policy), bit_order)
+    }
+    fn reflect_padding(&mut self, length: usize) -> std::io::Result<()> {
+        let canonical = self.options().canonical;
+        for _ in 0..length {
+            let mut zero = 0;
+            self.reflect_u8(&mut zero)?;
+            check(zero == 0 || !canonical, "non-canonical padding")?;
         }
         Ok(())
     }
-    fn reflect_bool_array_ext(&mut self, data: &mut Vec<bool>, size_policy: SizePolicy) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_bool(&mut data[i])?;
+    fn align(&mut self, alignment: u64) -> std::io::Result<()> {
+        check(alignment != 0, "alignment must be greater than zero")?;
+        let misalignment = self.position() % alignment;
+        if misalignment == 0 {
+            return Ok(());
+        }
+        self.reflect_padding((alignment - misalignment) as usize)
+    }
+    fn reflect_reserved(&mut self, length: usize, expected_byte: u8) -> std::io::Result<()> {
+        let options = self.options();
+        let strict = options.strict_reserved || options.canonical;
+        for _ in 0..length {
+            let mut byte = expected_byte;
+            self.reflect_u8(&mut byte)?;
+            check(!strict || byte == expected_byte, "unexpected value in reserved field")?;
+        }
+        Ok(())
+    }
+    fn reflect_magic(&mut self, magic: &[u8]) -> std::io::Result<()> {
+        let mut found = magic.to_vec();
+        for byte in found.iter_mut() {
+            self.reflect_u8(byte)?;
+        }
+        if found != magic {
+            return Err(ReflectError::BadMagic { expected: magic.to_vec(), found }.into());
         }
         Ok(())
     }
+    fn reflect_since<R: Reflectable>(&mut self, since: u32, field: &mut R, default: R) -> std::io::Result<()> {
+        reflect_in_versions(self, since, u32::MAX, field, default)
+    }
+    fn reflect_until<R: Reflectable>(&mut self, until: u32, field: &mut R, default: R) -> std::io::Result<()> {
+        reflect_in_versions(self, 0, until, field, default)
+    }
+    fn reflect_since_until<R: Reflectable>(
+        &mut self,
+        since: u32,
+        until: u32,
+        field: &mut R,
+        default: R
+    ) -> std::io::Result<()> {
+        reflect_in_versions(self, since, until, field, default)
+    }
     fn reflect_composite<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
         composite.reflect(self)
     }
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| composite.reflect(r))
+    }
     fn reflect_array_of_composites<R: Reflectable>(&mut self, data: &mut Vec<R>) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_composite(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_composite(item))
     }
     fn reflect_array_of_composites_ext<R: Reflectable>(
         &mut self,
         data: &mut Vec<R>,
         size_policy: SizePolicy
     ) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_composite(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_composite(item))
     }
     fn reflect_tagged_composite<R: TaggedReflectable>(
         &mut self,
@@ -237,32 +464,217 @@ pub trait SerializationReflector: Sized {
     ) -> std::io::Result<()> {
         composite.reflect(self)
     }
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(
+        &mut self,
+        composite: &mut R
+    ) -> std::io::Result<()> {
+        self.reflect_frame(|r| composite.reflect(r))
+    }
     fn reflect_array_of_tagged_composites<R: TaggedReflectable>(
         &mut self,
         data: &mut Vec<R>
     ) -> std::io::Result<()> {
-        reflect_vec_size(self, data)?;
-        for i in 0..data.len(){
-            self.reflect_tagged_composite(&mut data[i])?;
-        }
-        Ok(())
+        reflect_vec(self, data, None, |r, item| r.reflect_tagged_composite(item))
     }
     fn reflect_array_of_tagged_composites_ext<R: TaggedReflectable>(
         &mut self,
         data: &mut Vec<R>,
         size_policy: SizePolicy
     ) -> std::io::Result<()> {
-        reflect_vec_size_ext(self, data, size_policy)?;
-        for i in 0..data.len(){
-            self.reflect_tagged_composite(&mut data[i])?;
+        reflect_vec(self, data, Some(size_policy), |r, item| r.reflect_tagged_composite(item))
+    }
+    fn reflect_map<M: ReflectableMap>(&mut self, map: &mut M) -> std::io::Result<()> {
+        let mut entries = map.sorted_entries();
+        reflect_vec(self, &mut entries, None, |r, (key, value)| {
+            r.reflect_composite(key)?;
+            r.reflect_composite(value)
+        })?;
+        if self.is_reading() {
+            fill_map(self, map, entries)?;
+        }
+        Ok(())
+    }
+    fn reflect_map_ext<M: ReflectableMap>(
+        &mut self,
+        map: &mut M,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        let mut entries = map.sorted_entries();
+        reflect_vec(self, &mut entries, Some(size_policy), |r, (key, value)| {
+            r.reflect_composite(key)?;
+            r.reflect_composite(value)
+        })?;
+        if self.is_reading() {
+            fill_map(self, map, entries)?;
+        }
+        Ok(())
+    }
+    fn reflect_set<S: ReflectableSet>(&mut self, set: &mut S) -> std::io::Result<()> {
+        let mut items = set.sorted_items();
+        reflect_vec(self, &mut items, None, |r, item| r.reflect_composite(item))?;
+        if self.is_reading() {
+            fill_set(self, set, items)?;
+        }
+        Ok(())
+    }
+    fn reflect_set_ext<S: ReflectableSet>(
+        &mut self,
+        set: &mut S,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        let mut items = set.sorted_items();
+        reflect_vec(self, &mut items, Some(size_policy), |r, item| r.reflect_composite(item))?;
+        if self.is_reading() {
+            fill_set(self, set, items)?;
         }
         Ok(())
     }
 }
 
-fn reflect_size<R: SerializationReflector>(r: &mut R, s: &mut usize) -> std::io::Result<()> {
-    let mut size = *s;
-    let mut tag = if size <= 0xFF {
+fn reflect_digest<R: SerializationReflector>(
+    r: &mut R,
+    algorithm: ChecksumAlgorithm,
+    computed: u64
+) -> std::io::Result<()> {
+    let stored = match algorithm {
+        ChecksumAlgorithm::XxHash64 => {
+            let mut digest = computed;
+            r.reflect_u64(&mut digest)?;
+            digest
+        },
+        ChecksumAlgorithm::Crc32 | ChecksumAlgorithm::Adler32 => {
+            let mut digest = computed as u32;
+            r.reflect_u32(&mut digest)?;
+            digest as u64
+        }
+    };
+    if stored != computed {
+        return Err(ReflectError::ChecksumMismatch { expected: stored, actual: computed }.into());
+    }
+    Ok(())
+}
+
+fn reflect_in_versions<TSerializationReflector: SerializationReflector, R: Reflectable>(
+    r: &mut TSerializationReflector,
+    since: u32,
+    until: u32,
+    field: &mut R,
+    default: R
+) -> std::io::Result<()> {
+    let version = r.version();
+    if version >= since && version < until {
+        r.reflect_composite(field)
+    } else {
+        if r.is_reading() {
+            *field = default;
+        }
+        Ok(())
+    }
+}
+
+fn bit_mask(index: usize, bit_order: BitOrder) -> u8 {
+    match bit_order {
+        BitOrder::MsbFirst => 0x80 >> index,
+        BitOrder::LsbFirst => 0x01 << index
+    }
+}
+
+fn reflect_packed_bools<R: SerializationReflector>(
+    r: &mut R,
+    data: &mut [bool],
+    bit_order: BitOrder
+) -> std::io::Result<()> {
+    for chunk in data.chunks_mut(8) {
+        let mut packed = 0u8;
+        for (i, bit) in chunk.iter().enumerate() {
+            if *bit {
+                packed |= bit_mask(i, bit_order);
+            }
+        }
+        r.reflect_u8(&mut packed)?;
+        for (i, bit) in chunk.iter_mut().enumerate() {
+            *bit = packed & bit_mask(i, bit_order) != 0;
+            packed &= !bit_mask(i, bit_order);
+        }
+        check(packed == 0 || !r.options().canonical, "non-canonical packed bools")?;
+    }
+    Ok(())
+}
+
+fn canonical_f32(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::from_bits(0x7FC00000)
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn canonical_f64(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::from_bits(0x7FF8000000000000)
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7FFFFF;
+    if exponent == 0xFF {
+        return if mantissa == 0 {
+            sign | 0x7C00
+        } else {
+            sign | 0x7E00 | (mantissa >> 13) as u16
+        };
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x800000;
+        let shift = (14 - half_exponent) as u32;
+        let halfway = 1 << (shift - 1);
+        let remainder = mantissa & ((1 << shift) - 1);
+        let mut half = mantissa >> shift;
+        if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+            half += 1;
+        }
+        return sign | half as u16;
+    }
+    let remainder = mantissa & 0x1FFF;
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) {
+        half += 1;
+    }
+    sign | half as u16
+}
+
+fn f16_bits_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1F) as u32;
+    let mantissa = (half & 0x3FF) as u32;
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign == 0 { magnitude } else { -magnitude }
+        },
+        0x1F => f32::from_bits(sign | 0x7F800000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13))
+    }
+}
+
+fn size_tag(size: usize) -> u8 {
+    if size <= 0xFF {
         1
     } else if size <= 0xFFFF {
         2
@@ -270,33 +682,23 @@ fn reflect_size<R: SerializationReflector>(r: &mut R, s: &mut usize) -> std::io:
         4
     } else {
         8
-    };
+    }
+}
+
+fn size_policy_tag(size_policy: SizePolicy) -> u8 {
+    match size_policy {
+        SizePolicy::U8 => 1,
+        SizePolicy::U16 => 2,
+        SizePolicy::U32 => 4,
+        SizePolicy::U64 => 8
+    }
+}
+
+fn reflect_size<R: SerializationReflector>(r: &mut R, s: &mut usize) -> std::io::Result<()> {
+    let mut tag = size_tag(*s);
     r.reflect_u8(&mut tag)?;
-    size = match tag {
-        1 => {
-            let mut size_u8 = size as u8;
-            r.reflect_u8(&mut size_u8)?;
-            size_u8 as usize
-        },
-        2 => {
-            let mut size_u16 = size as u16;
-            r.reflect_u16(&mut size_u16)?;
-            size_u16 as usize
-        },
-        4 => {
-            let mut size_u32 = size as u32;
-            r.reflect_u32(&mut size_u32)?;
-            size_u32 as usize
-        },
-        8 => {
-            let mut size_u64 = size as u64;
-            r.reflect_u64(&mut size_u64)?;
-            size_u64 as usize
-        },
-        _ => unreachable!()
-    };
-    *s = size;
-    Ok(())
+    reflect_tagged_size(r, tag, s)?;
+    check_size_tag(r, tag, size_tag(*s))
 }
 
 fn reflect_size_ext<R: SerializationReflector>(
@@ -304,15 +706,24 @@ fn reflect_size_ext<R: SerializationReflector>(
     &mut usize,
     size_policy: SizePolicy
 ) -> std::io::Result<()> {
-    let mut size = *s;
-    let mut tag = match size_policy {
-        SizePolicy::U8 => 1,
-        SizePolicy::U16 => 2,
-        SizePolicy::U32 => 4,
-        SizePolicy::U64 => 8
-    };
+    let mut tag = size_policy_tag(size_policy);
+    check(r.is_reading() || size_tag(*s) <= tag, "size does not fit its size policy")?;
     r.reflect_u8(&mut tag)?;
-    size = match tag {
+    reflect_tagged_size(r, tag, s)?;
+    check_size_tag(r, tag, size_policy_tag(size_policy))
+}
+
+fn check_size_tag<R: SerializationReflector>(r: &R, tag: u8, expected: u8) -> std::io::Result<()> {
+    let options = r.options();
+    if r.is_reading() && (options.strict_size_tags || options.canonical) && tag != expected {
+        return Err(ReflectError::NonCanonicalSizeTag { tag, expected }.into());
+    }
+    Ok(())
+}
+
+fn reflect_tagged_size<R: SerializationReflector>(r: &mut R, tag: u8, s: &mut usize) -> std::io::Result<()> {
+    let size = *s;
+    *s = match tag {
         1 => {
             let mut size_u8 = size as u8;
             r.reflect_u8(&mut size_u8)?;
@@ -331,260 +742,830 @@ fn reflect_size_ext<R: SerializationReflector>(
         8 => {
             let mut size_u64 = size as u64;
             r.reflect_u64(&mut size_u64)?;
-            size_u64 as usize
+            usize::try_from(size_u64)
+                .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "size does not fit in usize"))?
         },
-        _ => unreachable!()
+        tag => return Err(ReflectError::InvalidSizeTag { tag }.into())
     };
-    *s = size;
     Ok(())
 }
 
-fn reflect_vec_size<R: SerializationReflector, T: Default+Clone>(r: &mut R, v: &mut Vec<T>) -> std::io::Result<()> {
+fn reflect_vec<R, T, F>(
+    r: &mut R,
+    v: &mut Vec<T>,
+    size_policy: Option<SizePolicy>,
+    mut reflect_item: F
+) -> std::io::Result<()>
+    where R: SerializationReflector, T: Default, F: FnMut(&mut R, &mut T) -> std::io::Result<()>
+{
+    let mut size = v.len();
+    match size_policy {
+        Some(size_policy) => reflect_size_ext(r, &mut size, size_policy)?,
+        None => reflect_size(r, &mut size)?
+    }
+    v.truncate(size);
+    let max_empty_elements = r.options().max_empty_elements;
+    let mut empty_run = 0;
+    for i in 0..size {
+        if i == v.len() {
+            v.push(T::default());
+        }
+        let start = r.position();
+        reflect_item(r, &mut v[i])?;
+        empty_run = if r.position() == start { empty_run + 1 } else { 0 };
+        check(empty_run <= max_empty_elements, "too many array elements without any data")?;
+    }
+    Ok(())
+}
+
+fn reflect_packed_bool_vec<R: SerializationReflector>(
+    r: &mut R,
+    v: &mut Vec<bool>,
+    size_policy: Option<SizePolicy>,
+    bit_order: BitOrder
+) -> std::io::Result<()> {
     let mut size = v.len();
-    reflect_size(r, &mut size)?;
-    if v.len() != size {
-        v.resize(size, Default::default());
+    match size_policy {
+        Some(size_policy) => reflect_size_ext(r, &mut size, size_policy)?,
+        None => reflect_size(r, &mut size)?
+    }
+    if !r.is_reading() {
+        return reflect_packed_bools(r, v, bit_order);
+    }
+    v.clear();
+    while v.len() < size {
+        let start = v.len();
+        v.resize(start + (size - start).min(0x8000), false);
+        reflect_packed_bools(r, &mut v[start..], bit_order)?;
+    }
+    Ok(())
+}
+
+fn reflect_utf8<R: SerializationReflector>(
+    r: &mut R,
+    string: &mut String,
+    size_policy: Option<SizePolicy>
+) -> std::io::Result<()> {
+    let mut bytes = std::mem::take(string).into_bytes();
+    let result = match size_policy {
+        Some(size_policy) => r.reflect_u8_array_ext(&mut bytes, size_policy),
+        None => r.reflect_u8_array(&mut bytes)
+    };
+    match String::from_utf8(bytes) {
+        Ok(decoded) => {
+            *string = decoded;
+            result
+        },
+        Err(_) => {
+            result?;
+            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "string is not valid utf-8"))
+        }
+    }
+}
+
+// Bytes taken by a cp866 string or zstring: one length or terminator byte and one byte per character
+fn cp866_size(string: &str) -> u64 {
+    1 + string.chars().count() as u64
+}
+
+// The writers only ever emit ASCII, so nothing else can survive a canonical round trip
+fn check_cp866_byte<R: SerializationReflector>(r: &R, byte: u8) -> std::io::Result<()> {
+    check(byte.is_ascii() || !r.options().canonical, "non-canonical cp866 character")
+}
+
+fn fill_map<R: SerializationReflector, M: ReflectableMap>(
+    r: &R,
+    map: &mut M,
+    entries: Vec<(M::Key, M::Value)>
+) -> std::io::Result<()> {
+    let options = r.options();
+    check(
+        !options.canonical || entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+        "map keys are not in canonical order"
+    )?;
+    let reject_duplicates = options.reject_duplicate_keys;
+    map.clear_entries();
+    for (key, value) in entries {
+        let inserted = map.insert_entry(key, value);
+        check(inserted || !reject_duplicates, "duplicate key in map")?;
+    }
+    Ok(())
+}
+
+fn fill_set<R: SerializationReflector, S: ReflectableSet>(
+    r: &R,
+    set: &mut S,
+    items: Vec<S::Item>
+) -> std::io::Result<()> {
+    let options = r.options();
+    check(
+        !options.canonical || items.windows(2).all(|pair| pair[0] < pair[1]),
+        "set items are not in canonical order"
+    )?;
+    let reject_duplicates = options.reject_duplicate_keys;
+    set.clear_items();
+    for item in items {
+        let inserted = set.insert_item(item);
+        check(inserted || !reject_duplicates, "duplicate key in set")?;
     }
     Ok(())
 }
 
-fn reflect_vec_size_ext<R: SerializationReflector, T: Default+Clone>(
-    r: &mut R,
-    v: &mut Vec<T>,
-    size_policy: SizePolicy
-) -> std::io::Result<()> {
-    let mut size = v.len();
-    reflect_size_ext(r, &mut size, size_policy)?;
-    if v.len() != size {
-        v.resize(size, Default::default());
+pub trait TaggedReflectable: Default+Clone {
+    fn get_size_policy() -> SizePolicy;
+    fn get_tag(&self) -> u64;
+    fn variant_tags() -> Vec<u64> {
+        Vec::new()
+    }
+    // See Reflectable::schema_name
+    fn schema_name() -> String {
+        String::new()
+    }
+    fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        tag: u64,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()>;
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        let tag = match Self::get_size_policy() {
+            SizePolicy::U8 => {
+                let mut tag = self.get_tag() as u8;
+                reflector.reflect_u8(&mut tag)?;
+                tag as u64
+            }
+            SizePolicy::U16 => {
+                let mut tag = self.get_tag() as u16;
+                reflector.reflect_u16(&mut tag)?;
+                tag as u64
+            }
+            SizePolicy::U32 => {
+                let mut tag = self.get_tag() as u32;
+                reflector.reflect_u32(&mut tag)?;
+                tag as u64
+            }
+            SizePolicy::U64 => {
+                let mut tag = self.get_tag();
+                reflector.reflect_u64(&mut tag)?;
+                tag as u64
+            }
+        };
+        self.reflect_tagged(tag, reflector)
+    }
+    fn serialize<TStream: Write>(
+        &mut self,
+        stream: &mut TStream,
+        endianness: Endianness
+    ) -> std::io::Result<()> {
+        self.serialize_ext(stream, endianness, ReflectorOptions::default())
+    }
+    fn serialize_ext<TStream: Write>(
+        &mut self,
+        stream: &mut TStream,
+        endianness: Endianness,
+        options: ReflectorOptions
+    ) -> std::io::Result<()> {
+        match endianness {
+            Endianness::BigEndian => serialize_tagged_to_stream_be(self, stream, options),
+            Endianness::LittleEndian => serialize_tagged_to_stream_le(self, stream, options)
+        }
+    }
+    fn deserialize<TStream: Read>(
+        stream: &mut TStream,
+        endianness: Endianness
+    ) -> std::io::Result<Self> {
+        Self::deserialize_ext(stream, endianness, ReflectorOptions::default())
+    }
+    fn deserialize_ext<TStream: Read>(
+        stream: &mut TStream,
+        endianness: Endianness,
+        options: ReflectorOptions
+    ) -> std::io::Result<Self> {
+        let mut data = Default::default();
+        match endianness {
+            Endianness::BigEndian => deserialize_tagged_from_stream_be(&mut data, stream, options),
+            Endianness::LittleEndian => deserialize_tagged_from_stream_le(&mut data, stream, options)
+        }?;
+        Ok(data)
+    }
+}
+
+pub trait Reflectable: Default+Clone {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()>;
+    // Name stored for the type in extracted schemas, which leave it anonymous by default:
+    // std::any::type_name changes between compiler versions, so it can't go on the wire
+    fn schema_name() -> String {
+        String::new()
+    }
+    fn serialize<TStream: Write>(
+        &mut self,
+        stream: &mut TStream,
+        endianness: Endianness
+    ) -> std::io::Result<()> {
+        self.serialize_ext(stream, endianness, ReflectorOptions::default())
+    }
+    fn serialize_ext<TStream: Write>(
+        &mut self,
+        stream: &mut TStream,
+        endianness: Endianness,
+        options: ReflectorOptions
+    ) -> std::io::Result<()> {
+        match endianness {
+            Endianness::BigEndian => serialize_to_stream_be(self, stream, options),
+            Endianness::LittleEndian => serialize_to_stream_le(self, stream, options)
+        }
+    }
+    fn deserialize<TStream: Read>(
+        stream: &mut TStream,
+        endianness: Endianness
+    ) -> std::io::Result<Self> {
+        Self::deserialize_ext(stream, endianness, ReflectorOptions::default())
+    }
+    fn deserialize_ext<TStream: Read>(
+        stream: &mut TStream,
+        endianness: Endianness,
+        options: ReflectorOptions
+    ) -> std::io::Result<Self> {
+        let mut data = Default::default();
+        match endianness {
+            Endianness::BigEndian => deserialize_from_stream_be(&mut data, stream, options),
+            Endianness::LittleEndian => deserialize_from_stream_le(&mut data, stream, options)
+        }?;
+        Ok(data)
+    }
+    fn content_hash(&mut self) -> std::io::Result<u64> {
+        let mut reflector = HashingReflector::new(XxHash64::new());
+        self.reflect(&mut reflector)?;
+        Ok(reflector.finish())
+    }
+}
+
+pub trait ReflectableMap {
+    type Key: Reflectable + Ord;
+    type Value: Reflectable;
+    fn sorted_entries(&self) -> Vec<(Self::Key, Self::Value)>;
+    fn clear_entries(&mut self);
+    fn insert_entry(&mut self, key: Self::Key, value: Self::Value) -> bool;
+}
+
+pub trait ReflectableSet {
+    type Item: Reflectable + Ord;
+    fn sorted_items(&self) -> Vec<Self::Item>;
+    fn clear_items(&mut self);
+    fn insert_item(&mut self, item: Self::Item) -> bool;
+}
+
+impl<K, V, S> ReflectableMap for HashMap<K, V, S>
+    where K: Reflectable + Ord + Hash, V: Reflectable, S: BuildHasher
+{
+    type Key = K;
+    type Value = V;
+    fn sorted_entries(&self) -> Vec<(K, V)> {
+        let mut entries: Vec<(K, V)> = self.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries
+    }
+    fn clear_entries(&mut self) {
+        self.clear()
+    }
+    fn insert_entry(&mut self, key: K, value: V) -> bool {
+        self.insert(key, value).is_none()
+    }
+}
+
+impl<K, V> ReflectableMap for BTreeMap<K, V>
+    where K: Reflectable + Ord, V: Reflectable
+{
+    type Key = K;
+    type Value = V;
+    fn sorted_entries(&self) -> Vec<(K, V)> {
+        self.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+    fn clear_entries(&mut self) {
+        self.clear()
+    }
+    fn insert_entry(&mut self, key: K, value: V) -> bool {
+        self.insert(key, value).is_none()
+    }
+}
+
+impl<T, S> ReflectableSet for HashSet<T, S>
+    where T: Reflectable + Ord + Hash, S: BuildHasher
+{
+    type Item = T;
+    fn sorted_items(&self) -> Vec<T> {
+        let mut items: Vec<T> = self.iter().cloned().collect();
+        items.sort();
+        items
+    }
+    fn clear_items(&mut self) {
+        self.clear()
+    }
+    fn insert_item(&mut self, item: T) -> bool {
+        self.insert(item)
+    }
+}
+
+impl<T> ReflectableSet for BTreeSet<T>
+    where T: Reflectable + Ord
+{
+    type Item = T;
+    fn sorted_items(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+    fn clear_items(&mut self) {
+        self.clear()
+    }
+    fn insert_item(&mut self, item: T) -> bool {
+        self.insert(item)
+    }
+}
+
+impl Reflectable for u8 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u8(self)
+    }
+}
+
+impl Reflectable for u16 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u16(self)
+    }
+}
+
+impl Reflectable for u32 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u32(self)
+    }
+}
+
+impl Reflectable for u64 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u64(self)
+    }
+}
+
+impl Reflectable for u128 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u128(self)
+    }
+}
+
+impl Reflectable for i8 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_i8(self)
+    }
+}
+
+impl Reflectable for i16 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_i16(self)
+    }
+}
+
+impl Reflectable for i32 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_i32(self)
+    }
+}
+
+impl Reflectable for i64 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_i64(self)
     }
-    Ok(())
 }
 
-pub trait TaggedReflectable: Default+Clone {
-    fn get_size_policy() -> SizePolicy;
-    fn get_tag(&self) -> u64;
-    fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+impl Reflectable for i128 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self,
-        tag: u64,
         reflector: &mut TSerializationReflector
-    ) -> std::io::Result<()>;
+    ) -> std::io::Result<()> {
+        reflector.reflect_i128(self)
+    }
+}
+
+impl Reflectable for f32 {
     fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self,
         reflector: &mut TSerializationReflector
     ) -> std::io::Result<()> {
-        let tag = match Self::get_size_policy() {
-            SizePolicy::U8 => {
-                let mut tag = self.get_tag() as u8;
-                reflector.reflect_u8(&mut tag)?;
-                tag as u64
-            }
-            SizePolicy::U16 => {
-                let mut tag = self.get_tag() as u16;
-                reflector.reflect_u16(&mut tag)?;
-                tag as u64
-            }
-            SizePolicy::U32 => {
-                let mut tag = self.get_tag() as u32;
-                reflector.reflect_u32(&mut tag)?;
-                tag as u64
-            }
-            SizePolicy::U64 => {
-                let mut tag = self.get_tag();
-                reflector.reflect_u64(&mut tag)?;
-                tag as u64
-            }
-        };
-        self.reflect_tagged(tag, reflector)
+        reflector.reflect_f32(self)
     }
-    fn serialize<TStream: Write>(
+}
+
+impl Reflectable for f64 {
+    fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self,
-        stream: &mut TStream,
-        endianness: Endianness
+        reflector: &mut TSerializationReflector
     ) -> std::io::Result<()> {
-        match endianness {
-            Endianness::BigEndian => serialize_tagged_to_stream_be(self, stream),
-            Endianness::LittleEndian => serialize_tagged_to_stream_le(self, stream)
-        }
-    }
-    fn deserialize<TStream: Read>(
-        stream: &mut TStream,
-        endianness: Endianness
-    ) -> std::io::Result<Self> {
-        let mut data = Default::default();
-        match endianness {
-            Endianness::BigEndian => deserialize_tagged_from_stream_be(&mut data, stream),
-            Endianness::LittleEndian => deserialize_tagged_from_stream_le(&mut data, stream)
-        }?;
-        Ok(data)
+        reflector.reflect_f64(self)
     }
 }
 
-pub trait Reflectable: Default+Clone {
+impl Reflectable for bool {
     fn reflect<TSerializationReflector: SerializationReflector>(
         &mut self,
         reflector: &mut TSerializationReflector
-    ) -> std::io::Result<()>;
-    fn serialize<TStream: Write>(
-        &mut self,
-        stream: &mut TStream,
-        endianness: Endianness
     ) -> std::io::Result<()> {
-        match endianness {
-            Endianness::BigEndian => serialize_to_stream_be(self, stream),
-            Endianness::LittleEndian => serialize_to_stream_le(self, stream)
-        }
+        reflector.reflect_bool(self)
     }
-    fn deserialize<TStream: Read>(
-        stream: &mut TStream,
-        endianness: Endianness
-    ) -> std::io::Result<Self> {
-        let mut data = Default::default();
-        match endianness {
-            Endianness::BigEndian => deserialize_from_stream_be(&mut data, stream),
-            Endianness::LittleEndian => deserialize_from_stream_le(&mut data, stream)
-        }?;
-        Ok(data)
+}
+
+impl Reflectable for String {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_string(self)
     }
 }
 
 fn serialize_to_stream_be<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: Reflectable, TStream: Write
 {
-    let mut serializer = BinaryWriterBigEndian { stream };
+    let mut serializer = BinaryWriterBigEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 fn serialize_tagged_to_stream_be<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: TaggedReflectable, TStream: Write
 {
-    let mut serializer = BinaryWriterBigEndian { stream };
+    let mut serializer = BinaryWriterBigEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 
 fn serialize_to_stream_le<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: Reflectable, TStream: Write
 {
-    let mut serializer = BinaryWriterLittleEndian { stream };
+    let mut serializer = BinaryWriterLittleEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 fn serialize_tagged_to_stream_le<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: TaggedReflectable, TStream: Write
 {
-    let mut serializer = BinaryWriterLittleEndian { stream };
+    let mut serializer = BinaryWriterLittleEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 
 fn deserialize_from_stream_be<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: Reflectable, TStream: Read
 {
-    let mut serializer = BinaryReaderBigEndian { stream };
+    let mut serializer = BinaryReaderBigEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 fn deserialize_tagged_from_stream_be<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: TaggedReflectable, TStream: Read
 {
-    let mut serializer = BinaryReaderBigEndian { stream };
+    let mut serializer = BinaryReaderBigEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 
 fn deserialize_from_stream_le<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: Reflectable, TStream: Read
 {
-    let mut serializer = BinaryReaderLittleEndian { stream };
+    let mut serializer = BinaryReaderLittleEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 fn deserialize_tagged_from_stream_le<'a, T, TStream>(
     data: &'a mut T,
-    stream: &'a mut TStream
+    stream: &'a mut TStream,
+    options: ReflectorOptions
 ) -> std::io::Result<()>
     where T: TaggedReflectable, TStream: Read
 {
-    let mut serializer = BinaryReaderLittleEndian { stream };
+    let mut serializer = BinaryReaderLittleEndian::with_options(stream, options);
     data.reflect(&mut serializer)
 }
 
-struct BinaryWriterBigEndian<'a, TStream: Write> {
-    stream: &'a mut TStream
+struct Capture {
+    data: Vec<u8>,
+    outer_position: u64,
+    id: u64
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Slot<T> {
+    position: u64,
+    length: usize,
+    capture_id: u64,
+    checksummed: bool,
+    marker: PhantomData<T>
+}
+
+impl<T> Clone for Slot<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Slot<T> {}
+
+impl<T> Slot<T> {
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+pub struct BinaryWriterBigEndian<'a, TStream: Write> {
+    stream: &'a mut TStream,
+    options: ReflectorOptions,
+    position: u64,
+    version: u32,
+    captures: Vec<Capture>,
+    captures_started: u64,
+    taps: Vec<(usize, Checksum)>
+}
+
+pub struct BinaryWriterLittleEndian<'a, TStream: Write> {
+    stream: &'a mut TStream,
+    options: ReflectorOptions,
+    position: u64,
+    version: u32,
+    captures: Vec<Capture>,
+    captures_started: u64,
+    taps: Vec<(usize, Checksum)>
+}
+
+impl<'a, TStream: Write> BinaryWriterBigEndian<'a, TStream> {
+    pub fn new(stream: &'a mut TStream) -> Self {
+        Self::with_options(stream, Default::default())
+    }
+
+    pub fn with_options(stream: &'a mut TStream, options: ReflectorOptions) -> Self {
+        Self {
+            stream,
+            options,
+            position: 0,
+            version: u32::MAX,
+            captures: Vec::new(),
+            captures_started: 0,
+            taps: Vec::new()
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8], err_text: &str) -> std::io::Result<()> {
+        let bytes_written = match self.captures.last_mut() {
+            Some(capture) => capture.data.write(bytes)?,
+            None => self.stream.write(bytes)?
+        };
+        self.position += bytes_written as u64;
+        let depth = self.captures.len();
+        for (_, checksum) in self.taps.iter_mut().filter(|(tap_depth, _)| *tap_depth == depth) {
+            checksum.update(&bytes[..bytes_written]);
+        }
+        check(bytes_written == bytes.len(), err_text)
+    }
+
+    fn tap<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<u64>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.taps.push((self.captures.len(), Checksum::new(algorithm)));
+        let result = body(self);
+        let (_, checksum) = self.taps.pop().unwrap();
+        result.map(|_| checksum.finish())
+    }
+
+    fn capture<F>(&mut self, body: F) -> std::io::Result<Vec<u8>>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.captures_started += 1;
+        self.captures.push(Capture { data: Vec::new(), outer_position: self.position, id: self.captures_started });
+        self.position = 0;
+        let result = body(self);
+        let capture = self.captures.pop().unwrap();
+        self.position = capture.outer_position;
+        result.map(|_| capture.data)
+    }
+
+    fn current_capture_id(&self) -> u64 {
+        self.captures.last().map_or(0, |capture| capture.id)
+    }
 }
 
-struct BinaryWriterLittleEndian<'a, TStream: Write> {
-    stream: &'a mut TStream
+impl<'a, TStream: Write + Seek> BinaryWriterBigEndian<'a, TStream> {
+    pub fn reserve<T: Reflectable>(&mut self) -> std::io::Result<Slot<T>> {
+        let position = self.position;
+        T::default().reflect(self)?;
+        Ok(Slot {
+            position,
+            length: (self.position - position) as usize,
+            capture_id: self.current_capture_id(),
+            checksummed: self.taps.iter().any(|(tap_depth, _)| *tap_depth == self.captures.len()),
+            marker: PhantomData
+        })
+    }
+
+    pub fn reserve_u32(&mut self) -> std::io::Result<Slot<u32>> {
+        self.reserve()
+    }
+
+    pub fn reserve_u64(&mut self) -> std::io::Result<Slot<u64>> {
+        self.reserve()
+    }
+
+    pub fn patch<T: Reflectable>(&mut self, slot: Slot<T>, mut value: T) -> std::io::Result<()> {
+        // A checksum over the slot has already taken in the reserved bytes
+        check(!slot.checksummed, "slot lies inside a checksummed section")?;
+        let mut bytes = Vec::new();
+        value.reflect(&mut BinaryWriterBigEndian::with_options(&mut bytes, self.options))?;
+        check(bytes.len() == slot.length, "patched value does not fit its slot")?;
+        if slot.capture_id != 0 {
+            let capture = self.captures.iter_mut()
+                .find(|capture| capture.id == slot.capture_id)
+                .ok_or_else(|| std::io::Error::other("slot belongs to a finished frame"))?;
+            let start = slot.position as usize;
+            capture.data[start..start + bytes.len()].copy_from_slice(&bytes);
+            return Ok(());
+        }
+        let stream_position = self.captures.first().map_or(self.position, |capture| capture.outer_position);
+        let distance = (stream_position - slot.position) as i64;
+        self.stream.seek(SeekFrom::Current(-distance))?;
+        self.stream.write_all(&bytes)?;
+        self.stream.seek(SeekFrom::Current(distance - bytes.len() as i64))?;
+        Ok(())
+    }
+
+    pub fn reflect_offset_to<T, F>(&mut self, slot: Slot<T>, block: F) -> std::io::Result<()>
+        where T: Reflectable + TryFrom<u64>, F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        // Positions restart at zero inside every frame, so the offset is only meaningful in the slot's own frame
+        check(slot.capture_id == self.current_capture_id(), "offset target lies in a different frame than its slot")?;
+        let offset = T::try_from(self.position)
+            .map_err(|_| std::io::Error::other("offset does not fit its slot"))?;
+        self.patch(slot, offset)?;
+        block(self)
+    }
 }
 
 impl<'a, TStream: Write> SerializationReflector for BinaryWriterBigEndian<'a, TStream> {
+    fn is_reading(&self) -> bool {
+        false
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut captured = self.capture(body)?;
+        self.reflect_u8_array(&mut captured)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let computed = self.tap(algorithm, body)?;
+        reflect_digest(self, algorithm, computed)
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical || codec == CompressionCodec::Stored, "non-canonical compression codec")?;
+        let captured = self.capture(body)?;
+        let mut compressed = compression::compress(codec, &captured)?;
+        self.reflect_u8_array(&mut compressed)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical, "encrypted sections are not canonical")?;
+        let mut data = self.capture(body)?;
+        let (nonce, tag) = encryption::seal(key, &mut data)?;
+        self.write_bytes(&nonce, "failed to write a nonce")?;
+        self.write_bytes(&tag, "failed to write an authentication tag")?;
+        self.reflect_u8_array(&mut data)
+    }
+
+    fn options(&self) -> ReflectorOptions {
+        self.options
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::BigEndian
+    }
+
     fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 1, "failed to write one byte")
+        self.write_bytes(&data.to_be_bytes(), "failed to write one byte")
     }
 
     fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 2, "failed to write two bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write two bytes")
     }
 
     fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write four bytes")
     }
 
     fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 1, "failed to write one byte")
+        self.write_bytes(&data.to_be_bytes(), "failed to write one byte")
     }
 
     fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 2, "failed to write two bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write two bytes")
     }
 
     fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write four bytes")
     }
 
     fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()>{
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        self.write_bytes(&data.to_be_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        let value = if self.options.canonical { canonical_f32(*data) } else { *data };
+        self.write_bytes(&value.to_be_bytes(), "failed to write four bytes")
     }
 
     fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_be_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        let value = if self.options.canonical { canonical_f64(*data) } else { *data };
+        self.write_bytes(&value.to_be_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
-        let mut size = string.len() as u8;
+        let length = string.chars().count();
+        check(length <= 0xFF, "cp866 string is longer than 255 characters")?;
+        let mut size = length as u8;
         self.reflect_u8(&mut size)?;
         for ch in string.chars() {
             let mut chr_id= if ch.is_ascii() {
@@ -611,6 +1592,8 @@ impl<'a, TStream: Write> SerializationReflector for BinaryWriterBigEndian<'a, TS
     }
 
     fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        let count = string.chars().count();
+        check(count <= length, "cp866 string does not fit its fixed length")?;
         for ch in string.chars() {
             let mut chr_id= if ch.is_ascii() {
                 ch as u8
@@ -619,64 +1602,231 @@ impl<'a, TStream: Write> SerializationReflector for BinaryWriterBigEndian<'a, TS
             };
             self.reflect_u8(&mut chr_id)?;
         };
-        let mut zero = 0;
-        self.reflect_u8(&mut zero)
+        for _ in count..length {
+            let mut zero = 0;
+            self.reflect_u8(&mut zero)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, TStream: Write> BinaryWriterLittleEndian<'a, TStream> {
+    pub fn new(stream: &'a mut TStream) -> Self {
+        Self::with_options(stream, Default::default())
+    }
+
+    pub fn with_options(stream: &'a mut TStream, options: ReflectorOptions) -> Self {
+        Self {
+            stream,
+            options,
+            position: 0,
+            version: u32::MAX,
+            captures: Vec::new(),
+            captures_started: 0,
+            taps: Vec::new()
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8], err_text: &str) -> std::io::Result<()> {
+        let bytes_written = match self.captures.last_mut() {
+            Some(capture) => capture.data.write(bytes)?,
+            None => self.stream.write(bytes)?
+        };
+        self.position += bytes_written as u64;
+        let depth = self.captures.len();
+        for (_, checksum) in self.taps.iter_mut().filter(|(tap_depth, _)| *tap_depth == depth) {
+            checksum.update(&bytes[..bytes_written]);
+        }
+        check(bytes_written == bytes.len(), err_text)
+    }
+
+    fn tap<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<u64>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.taps.push((self.captures.len(), Checksum::new(algorithm)));
+        let result = body(self);
+        let (_, checksum) = self.taps.pop().unwrap();
+        result.map(|_| checksum.finish())
+    }
+
+    fn capture<F>(&mut self, body: F) -> std::io::Result<Vec<u8>>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.captures_started += 1;
+        self.captures.push(Capture { data: Vec::new(), outer_position: self.position, id: self.captures_started });
+        self.position = 0;
+        let result = body(self);
+        let capture = self.captures.pop().unwrap();
+        self.position = capture.outer_position;
+        result.map(|_| capture.data)
+    }
+
+    fn current_capture_id(&self) -> u64 {
+        self.captures.last().map_or(0, |capture| capture.id)
+    }
+}
+
+impl<'a, TStream: Write + Seek> BinaryWriterLittleEndian<'a, TStream> {
+    pub fn reserve<T: Reflectable>(&mut self) -> std::io::Result<Slot<T>> {
+        let position = self.position;
+        T::default().reflect(self)?;
+        Ok(Slot {
+            position,
+            length: (self.position - position) as usize,
+            capture_id: self.current_capture_id(),
+            checksummed: self.taps.iter().any(|(tap_depth, _)| *tap_depth == self.captures.len()),
+            marker: PhantomData
+        })
+    }
+
+    pub fn reserve_u32(&mut self) -> std::io::Result<Slot<u32>> {
+        self.reserve()
+    }
+
+    pub fn reserve_u64(&mut self) -> std::io::Result<Slot<u64>> {
+        self.reserve()
+    }
+
+    pub fn patch<T: Reflectable>(&mut self, slot: Slot<T>, mut value: T) -> std::io::Result<()> {
+        // A checksum over the slot has already taken in the reserved bytes
+        check(!slot.checksummed, "slot lies inside a checksummed section")?;
+        let mut bytes = Vec::new();
+        value.reflect(&mut BinaryWriterLittleEndian::with_options(&mut bytes, self.options))?;
+        check(bytes.len() == slot.length, "patched value does not fit its slot")?;
+        if slot.capture_id != 0 {
+            let capture = self.captures.iter_mut()
+                .find(|capture| capture.id == slot.capture_id)
+                .ok_or_else(|| std::io::Error::other("slot belongs to a finished frame"))?;
+            let start = slot.position as usize;
+            capture.data[start..start + bytes.len()].copy_from_slice(&bytes);
+            return Ok(());
+        }
+        let stream_position = self.captures.first().map_or(self.position, |capture| capture.outer_position);
+        let distance = (stream_position - slot.position) as i64;
+        self.stream.seek(SeekFrom::Current(-distance))?;
+        self.stream.write_all(&bytes)?;
+        self.stream.seek(SeekFrom::Current(distance - bytes.len() as i64))?;
+        Ok(())
+    }
+
+    pub fn reflect_offset_to<T, F>(&mut self, slot: Slot<T>, block: F) -> std::io::Result<()>
+        where T: Reflectable + TryFrom<u64>, F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        // Positions restart at zero inside every frame, so the offset is only meaningful in the slot's own frame
+        check(slot.capture_id == self.current_capture_id(), "offset target lies in a different frame than its slot")?;
+        let offset = T::try_from(self.position)
+            .map_err(|_| std::io::Error::other("offset does not fit its slot"))?;
+        self.patch(slot, offset)?;
+        block(self)
     }
 }
 
 impl<'a, TStream: Write> SerializationReflector for BinaryWriterLittleEndian<'a, TStream> {
+    fn is_reading(&self) -> bool {
+        false
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut captured = self.capture(body)?;
+        self.reflect_u8_array(&mut captured)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let computed = self.tap(algorithm, body)?;
+        reflect_digest(self, algorithm, computed)
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical || codec == CompressionCodec::Stored, "non-canonical compression codec")?;
+        let captured = self.capture(body)?;
+        let mut compressed = compression::compress(codec, &captured)?;
+        self.reflect_u8_array(&mut compressed)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical, "encrypted sections are not canonical")?;
+        let mut data = self.capture(body)?;
+        let (nonce, tag) = encryption::seal(key, &mut data)?;
+        self.write_bytes(&nonce, "failed to write a nonce")?;
+        self.write_bytes(&tag, "failed to write an authentication tag")?;
+        self.reflect_u8_array(&mut data)
+    }
+
+    fn options(&self) -> ReflectorOptions {
+        self.options
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::LittleEndian
+    }
+
     fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 1, "failed to write one byte")
+        self.write_bytes(&data.to_le_bytes(), "failed to write one byte")
     }
 
     fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 2, "failed to write two bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write two bytes")
     }
 
     fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write four bytes")
     }
 
     fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 1, "failed to write one byte")
+        self.write_bytes(&data.to_le_bytes(), "failed to write one byte")
     }
 
     fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 2, "failed to write two bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write two bytes")
     }
 
     fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write four bytes")
     }
 
     fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()>{
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        self.write_bytes(&data.to_le_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 4, "failed to write four bytes")
+        let value = if self.options.canonical { canonical_f32(*data) } else { *data };
+        self.write_bytes(&value.to_le_bytes(), "failed to write four bytes")
     }
 
     fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
-        let bytes_written = self.stream.write(&data.to_le_bytes())?;
-        check(bytes_written == 8, "failed to write eight bytes")
+        let value = if self.options.canonical { canonical_f64(*data) } else { *data };
+        self.write_bytes(&value.to_le_bytes(), "failed to write eight bytes")
     }
 
     fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
-        let mut size = string.len() as u8;
+        let length = string.chars().count();
+        check(length <= 0xFF, "cp866 string is longer than 255 characters")?;
+        let mut size = length as u8;
         self.reflect_u8(&mut size)?;
         for ch in string.chars() {
             let mut chr_id= if ch.is_ascii() {
@@ -703,6 +1853,8 @@ impl<'a, TStream: Write> SerializationReflector for BinaryWriterLittleEndian<'a,
     }
 
     fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        let count = string.chars().count();
+        check(count <= length, "cp866 string does not fit its fixed length")?;
         for ch in string.chars() {
             let mut chr_id= if ch.is_ascii() {
                 ch as u8
@@ -711,88 +1863,377 @@ impl<'a, TStream: Write> SerializationReflector for BinaryWriterLittleEndian<'a,
             };
             self.reflect_u8(&mut chr_id)?;
         };
-        let mut zero = 0;
-        self.reflect_u8(&mut zero)
+        for _ in count..length {
+            let mut zero = 0;
+            self.reflect_u8(&mut zero)?;
+        }
+        Ok(())
+    }
+}
+
+struct PointerRange {
+    depth: usize,
+    start: u64,
+    end: u64,
+    active: bool
+}
+
+pub struct BinaryReaderBigEndian<'a, TStream: Read> {
+    stream: &'a mut TStream,
+    options: ReflectorOptions,
+    position: u64,
+    version: u32,
+    sources: Vec<(Cursor<Vec<u8>>, u64)>,
+    pointer_ranges: Vec<PointerRange>,
+    taps: Vec<(usize, Checksum)>,
+    depth: usize
+}
+
+pub struct BinaryReaderLittleEndian<'a, TStream: Read> {
+    stream: &'a mut TStream,
+    options: ReflectorOptions,
+    position: u64,
+    version: u32,
+    sources: Vec<(Cursor<Vec<u8>>, u64)>,
+    pointer_ranges: Vec<PointerRange>,
+    taps: Vec<(usize, Checksum)>,
+    depth: usize
+}
+
+impl<'a, TStream: Read> BinaryReaderBigEndian<'a, TStream> {
+    pub fn new(stream: &'a mut TStream) -> Self {
+        Self::with_options(stream, Default::default())
+    }
+
+    pub fn with_options(stream: &'a mut TStream, options: ReflectorOptions) -> Self {
+        Self {
+            stream,
+            options,
+            position: 0,
+            version: u32::MAX,
+            sources: Vec::new(),
+            pointer_ranges: Vec::new(),
+            taps: Vec::new(),
+            depth: 0
+        }
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8], err_text: &str) -> std::io::Result<()> {
+        let mut size_read = 0;
+        while size_read < buffer.len() {
+            let chunk = match self.sources.last_mut() {
+                Some((source, _)) => source.read(&mut buffer[size_read..]),
+                None => self.stream.read(&mut buffer[size_read..])
+            };
+            match chunk {
+                Ok(0) => break,
+                Ok(chunk_size) => size_read += chunk_size,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+        self.position += size_read as u64;
+        let depth = self.sources.len();
+        for (_, checksum) in self.taps.iter_mut().filter(|(tap_depth, _)| *tap_depth == depth) {
+            checksum.update(&buffer[..size_read]);
+        }
+        check(size_read == buffer.len(), err_text)
+    }
+
+    fn nested<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        if self.depth >= self.options.max_depth {
+            return Err(ReflectError::DepthLimitExceeded { limit: self.options.max_depth }.into());
+        }
+        self.depth += 1;
+        let result = body(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn tap<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<u64>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.taps.push((self.sources.len(), Checksum::new(algorithm)));
+        let result = body(self);
+        let (_, checksum) = self.taps.pop().unwrap();
+        result.map(|_| checksum.finish())
+    }
+
+    fn feed<F>(&mut self, data: Vec<u8>, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.sources.push((Cursor::new(data), self.position));
+        self.position = 0;
+        let result = body(self).and_then(|_| self.check_fully_read());
+        let (_, outer_position) = self.sources.pop().unwrap();
+        self.position = outer_position;
+        let depth = self.sources.len();
+        self.pointer_ranges.retain(|range| range.depth <= depth);
+        result
+    }
+
+    // A canonical section has exactly one encoding, so it can't carry bytes nothing has read
+    fn check_fully_read(&self) -> std::io::Result<()> {
+        if !self.options.canonical {
+            return Ok(());
+        }
+        let depth = self.sources.len();
+        let end = self.pointer_ranges.iter()
+            .filter(|range| range.depth == depth)
+            .map(|range| range.end)
+            .fold(self.position, u64::max);
+        let length = self.sources.last().map_or(0, |(source, _)| source.get_ref().len() as u64);
+        check(end == length, "unread bytes at the end of a canonical section")
+    }
+
+    fn read_offset(&mut self, size_policy: SizePolicy) -> std::io::Result<u64> {
+        Ok(match size_policy {
+            SizePolicy::U8 => {
+                let mut offset = 0u8;
+                self.reflect_u8(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U16 => {
+                let mut offset = 0u16;
+                self.reflect_u16(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U32 => {
+                let mut offset = 0u32;
+                self.reflect_u32(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U64 => {
+                let mut offset = 0u64;
+                self.reflect_u64(&mut offset)?;
+                offset
+            }
+        })
+    }
+}
+
+impl<'a, TStream: Read + Seek> BinaryReaderBigEndian<'a, TStream> {
+    fn seek_to(&mut self, offset: u64) -> std::io::Result<()> {
+        let distance = i64::try_from(offset as i128 - self.position as i128)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "offset is out of range"))?;
+        match self.sources.last_mut() {
+            Some((source, _)) => source.seek(SeekFrom::Current(distance))?,
+            None => self.stream.seek(SeekFrom::Current(distance))?
+        };
+        self.position = offset;
+        Ok(())
+    }
+
+    pub fn reflect_at<F>(&mut self, offset: u64, block: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let return_position = self.position;
+        self.seek_to(offset)?;
+        let result = block(self);
+        self.seek_to(return_position)?;
+        result
+    }
+
+    pub fn reflect_pointer<T: Reflectable>(&mut self, data: &mut T, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.reflect_pointer_with(size_policy, |r| data.reflect(r))
+    }
+
+    pub fn reflect_pointer_with<F>(&mut self, size_policy: SizePolicy, block: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let offset = self.read_offset(size_policy)?;
+        let depth = self.sources.len();
+        let position = self.position;
+        let parent = self.pointer_ranges.iter_mut()
+            .rev()
+            .find(|range| range.depth == depth && range.active);
+        if let Some(parent) = parent {
+            parent.end = parent.end.max(position);
+        }
+        let overlaps = self.pointer_ranges.iter().any(|range|
+            range.depth == depth &&
+                offset >= range.start &&
+                (offset < range.end || (range.active && offset == range.start))
+        );
+        check(!overlaps, "pointer targets data that was already read through a pointer")?;
+        self.pointer_ranges.push(PointerRange { depth, start: offset, end: offset, active: true });
+        let index = self.pointer_ranges.len() - 1;
+        let mut end = offset;
+        let result = self.nested(|r| r.reflect_at(offset, |r| {
+            let result = block(r);
+            end = r.position;
+            result
+        }));
+        let range = &mut self.pointer_ranges[index];
+        range.end = range.end.max(end);
+        range.active = false;
+        let end = range.end;
+        result?;
+        // The target's extent is only known now, so it can still run into a range read earlier
+        let overlaps = self.pointer_ranges.iter().enumerate().any(|(other, range)|
+            other != index && range.depth == depth && !range.active && range.start < end && offset < range.end
+        );
+        check(!overlaps, "pointer targets data that was already read through a pointer")
+    }
+}
+
+impl<'a, TStream: Read> SerializationReflector for BinaryReaderBigEndian<'a, TStream> {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_composite<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| composite.reflect(r))
+    }
+
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| r.reflect_frame(|r| composite.reflect(r)))
+    }
+
+    fn reflect_tagged_composite<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| composite.reflect(r))
+    }
+
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| r.reflect_frame(|r| composite.reflect(r)))
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut frame = Vec::new();
+        self.reflect_u8_array(&mut frame)?;
+        self.feed(frame, body)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let computed = self.tap(algorithm, body)?;
+        reflect_digest(self, algorithm, computed)
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical || codec == CompressionCodec::Stored, "non-canonical compression codec")?;
+        let mut compressed = Vec::new();
+        self.reflect_u8_array(&mut compressed)?;
+        let decompressed = compression::decompress(codec, &compressed, self.options.max_decompressed_size)?;
+        self.feed(decompressed, body)
     }
-}
 
-struct BinaryReaderBigEndian<'a, TStream: Read> {
-    stream: &'a mut TStream
-}
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical, "encrypted sections are not canonical")?;
+        let mut nonce = [0u8; encryption::NONCE_SIZE];
+        let mut tag = [0u8; encryption::TAG_SIZE];
+        let mut data = Vec::new();
+        self.read_bytes(&mut nonce, "failed to read a nonce")?;
+        self.read_bytes(&mut tag, "failed to read an authentication tag")?;
+        self.reflect_u8_array(&mut data)?;
+        encryption::open(key, &nonce, &tag, &mut data)?;
+        self.feed(data, body)
+    }
 
-struct BinaryReaderLittleEndian<'a, TStream: Read> {
-    stream: &'a mut TStream
-}
+    fn options(&self) -> ReflectorOptions {
+        self.options
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::BigEndian
+    }
 
-impl<'a, TStream: Read> SerializationReflector for BinaryReaderBigEndian<'a, TStream> {
     fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()> {
         let d = &mut [0; 1];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read one byte")?;
         *data = u8::from_be_bytes(*d);
-        check(size_read == 1, "failed to read one byte")
+        Ok(())
     }
 
     fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()> {
         let d = &mut [0; 2];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read two bytes")?;
         *data = u16::from_be_bytes(*d);
-        check(size_read == 2, "failed to read two bytes")
+        Ok(())
     }
 
     fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = u32::from_be_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        Ok(())
     }
 
     fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = u64::from_be_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        Ok(())
     }
 
     fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()> {
         let d = &mut [0; 1];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read one byte")?;
         *data = i8::from_be_bytes(*d);
-        check(size_read == 1, "failed to read one byte")
+        Ok(())
     }
 
     fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()> {
         let d = &mut [0; 2];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read two bytes")?;
         *data = i16::from_be_bytes(*d);
-        check(size_read == 2, "failed to read two bytes")
+        Ok(())
     }
 
     fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = i32::from_be_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        Ok(())
     }
 
     fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = i64::from_be_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        Ok(())
     }
 
     fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = f32::from_be_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        check(
+            !self.options.canonical || canonical_f32(*data).to_bits() == data.to_bits(),
+            "non-canonical float"
+        )
     }
 
     fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = f64::from_be_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        check(
+            !self.options.canonical || canonical_f64(*data).to_bits() == data.to_bits(),
+            "non-canonical float"
+        )
     }
 
     fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
@@ -802,30 +2243,35 @@ impl<'a, TStream: Read> SerializationReflector for BinaryReaderBigEndian<'a, TSt
         for _ in 0..size {
             let mut chr_id = 0u8;
             self.reflect_u8(&mut chr_id)?;
+            check_cp866_byte(self, chr_id)?;
             string.push(cp866_rs::decode_byte(chr_id));
         }
         Ok(())
     }
 
     fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
-        let mut chr_id = 255;
         string.clear();
-        while chr_id != 0 {
+        loop {
+            let mut chr_id = 0u8;
             self.reflect_u8(&mut chr_id)?;
+            if chr_id == 0 {
+                return Ok(());
+            }
+            check_cp866_byte(self, chr_id)?;
             string.push(cp866_rs::decode_byte(chr_id));
         }
-        Ok(())
     }
 
     fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
         let mut chr_id = 255;
         string.clear();
-        let mut offset = 0;
         let mut terminator_found = false;
-        for _ in 0..offset {
+        for _ in 0..length {
             self.reflect_u8(&mut chr_id)?;
+            check(!terminator_found || chr_id == 0 || !self.options.canonical, "non-canonical cp866 padding")?;
             if chr_id == 0 { terminator_found = true; }
             if !terminator_found {
+                check_cp866_byte(self, chr_id)?;
                 string.push(cp866_rs::decode_byte(chr_id));
             }
         }
@@ -833,75 +2279,340 @@ impl<'a, TStream: Read> SerializationReflector for BinaryReaderBigEndian<'a, TSt
     }
 }
 
+impl<'a, TStream: Read> BinaryReaderLittleEndian<'a, TStream> {
+    pub fn new(stream: &'a mut TStream) -> Self {
+        Self::with_options(stream, Default::default())
+    }
+
+    pub fn with_options(stream: &'a mut TStream, options: ReflectorOptions) -> Self {
+        Self {
+            stream,
+            options,
+            position: 0,
+            version: u32::MAX,
+            sources: Vec::new(),
+            pointer_ranges: Vec::new(),
+            taps: Vec::new(),
+            depth: 0
+        }
+    }
+
+    fn read_bytes(&mut self, buffer: &mut [u8], err_text: &str) -> std::io::Result<()> {
+        let mut size_read = 0;
+        while size_read < buffer.len() {
+            let chunk = match self.sources.last_mut() {
+                Some((source, _)) => source.read(&mut buffer[size_read..]),
+                None => self.stream.read(&mut buffer[size_read..])
+            };
+            match chunk {
+                Ok(0) => break,
+                Ok(chunk_size) => size_read += chunk_size,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e)
+            }
+        }
+        self.position += size_read as u64;
+        let depth = self.sources.len();
+        for (_, checksum) in self.taps.iter_mut().filter(|(tap_depth, _)| *tap_depth == depth) {
+            checksum.update(&buffer[..size_read]);
+        }
+        check(size_read == buffer.len(), err_text)
+    }
+
+    fn nested<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        if self.depth >= self.options.max_depth {
+            return Err(ReflectError::DepthLimitExceeded { limit: self.options.max_depth }.into());
+        }
+        self.depth += 1;
+        let result = body(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn tap<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<u64>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.taps.push((self.sources.len(), Checksum::new(algorithm)));
+        let result = body(self);
+        let (_, checksum) = self.taps.pop().unwrap();
+        result.map(|_| checksum.finish())
+    }
+
+    fn feed<F>(&mut self, data: Vec<u8>, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.sources.push((Cursor::new(data), self.position));
+        self.position = 0;
+        let result = body(self).and_then(|_| self.check_fully_read());
+        let (_, outer_position) = self.sources.pop().unwrap();
+        self.position = outer_position;
+        let depth = self.sources.len();
+        self.pointer_ranges.retain(|range| range.depth <= depth);
+        result
+    }
+
+    // A canonical section has exactly one encoding, so it can't carry bytes nothing has read
+    fn check_fully_read(&self) -> std::io::Result<()> {
+        if !self.options.canonical {
+            return Ok(());
+        }
+        let depth = self.sources.len();
+        let end = self.pointer_ranges.iter()
+            .filter(|range| range.depth == depth)
+            .map(|range| range.end)
+            .fold(self.position, u64::max);
+        let length = self.sources.last().map_or(0, |(source, _)| source.get_ref().len() as u64);
+        check(end == length, "unread bytes at the end of a canonical section")
+    }
+
+    fn read_offset(&mut self, size_policy: SizePolicy) -> std::io::Result<u64> {
+        Ok(match size_policy {
+            SizePolicy::U8 => {
+                let mut offset = 0u8;
+                self.reflect_u8(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U16 => {
+                let mut offset = 0u16;
+                self.reflect_u16(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U32 => {
+                let mut offset = 0u32;
+                self.reflect_u32(&mut offset)?;
+                offset as u64
+            },
+            SizePolicy::U64 => {
+                let mut offset = 0u64;
+                self.reflect_u64(&mut offset)?;
+                offset
+            }
+        })
+    }
+}
+
+impl<'a, TStream: Read + Seek> BinaryReaderLittleEndian<'a, TStream> {
+    fn seek_to(&mut self, offset: u64) -> std::io::Result<()> {
+        let distance = i64::try_from(offset as i128 - self.position as i128)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "offset is out of range"))?;
+        match self.sources.last_mut() {
+            Some((source, _)) => source.seek(SeekFrom::Current(distance))?,
+            None => self.stream.seek(SeekFrom::Current(distance))?
+        };
+        self.position = offset;
+        Ok(())
+    }
+
+    pub fn reflect_at<F>(&mut self, offset: u64, block: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let return_position = self.position;
+        self.seek_to(offset)?;
+        let result = block(self);
+        self.seek_to(return_position)?;
+        result
+    }
+
+    pub fn reflect_pointer<T: Reflectable>(&mut self, data: &mut T, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.reflect_pointer_with(size_policy, |r| data.reflect(r))
+    }
+
+    pub fn reflect_pointer_with<F>(&mut self, size_policy: SizePolicy, block: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let offset = self.read_offset(size_policy)?;
+        let depth = self.sources.len();
+        let position = self.position;
+        let parent = self.pointer_ranges.iter_mut()
+            .rev()
+            .find(|range| range.depth == depth && range.active);
+        if let Some(parent) = parent {
+            parent.end = parent.end.max(position);
+        }
+        let overlaps = self.pointer_ranges.iter().any(|range|
+            range.depth == depth &&
+                offset >= range.start &&
+                (offset < range.end || (range.active && offset == range.start))
+        );
+        check(!overlaps, "pointer targets data that was already read through a pointer")?;
+        self.pointer_ranges.push(PointerRange { depth, start: offset, end: offset, active: true });
+        let index = self.pointer_ranges.len() - 1;
+        let mut end = offset;
+        let result = self.nested(|r| r.reflect_at(offset, |r| {
+            let result = block(r);
+            end = r.position;
+            result
+        }));
+        let range = &mut self.pointer_ranges[index];
+        range.end = range.end.max(end);
+        range.active = false;
+        let end = range.end;
+        result?;
+        // The target's extent is only known now, so it can still run into a range read earlier
+        let overlaps = self.pointer_ranges.iter().enumerate().any(|(other, range)|
+            other != index && range.depth == depth && !range.active && range.start < end && offset < range.end
+        );
+        check(!overlaps, "pointer targets data that was already read through a pointer")
+    }
+}
+
 impl<'a, TStream: Read> SerializationReflector for BinaryReaderLittleEndian<'a, TStream> {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_composite<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| composite.reflect(r))
+    }
+
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| r.reflect_frame(|r| composite.reflect(r)))
+    }
+
+    fn reflect_tagged_composite<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| composite.reflect(r))
+    }
+
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.nested(|r| r.reflect_frame(|r| composite.reflect(r)))
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut frame = Vec::new();
+        self.reflect_u8_array(&mut frame)?;
+        self.feed(frame, body)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let computed = self.tap(algorithm, body)?;
+        reflect_digest(self, algorithm, computed)
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical || codec == CompressionCodec::Stored, "non-canonical compression codec")?;
+        let mut compressed = Vec::new();
+        self.reflect_u8_array(&mut compressed)?;
+        let decompressed = compression::decompress(codec, &compressed, self.options.max_decompressed_size)?;
+        self.feed(decompressed, body)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        check(!self.options.canonical, "encrypted sections are not canonical")?;
+        let mut nonce = [0u8; encryption::NONCE_SIZE];
+        let mut tag = [0u8; encryption::TAG_SIZE];
+        let mut data = Vec::new();
+        self.read_bytes(&mut nonce, "failed to read a nonce")?;
+        self.read_bytes(&mut tag, "failed to read an authentication tag")?;
+        self.reflect_u8_array(&mut data)?;
+        encryption::open(key, &nonce, &tag, &mut data)?;
+        self.feed(data, body)
+    }
+
+    fn options(&self) -> ReflectorOptions {
+        self.options
+    }
+
+    fn endianness(&self) -> Endianness {
+        Endianness::LittleEndian
+    }
+
     fn reflect_u8(&mut self, data: &mut u8) -> std::io::Result<()> {
         let d = &mut [0; 1];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read one byte")?;
         *data = u8::from_le_bytes(*d);
-        check(size_read == 1, "failed to read one byte")
+        Ok(())
     }
 
     fn reflect_u16(&mut self, data: &mut u16) -> std::io::Result<()> {
         let d = &mut [0; 2];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read two bytes")?;
         *data = u16::from_le_bytes(*d);
-        check(size_read == 2, "failed to read two bytes")
+        Ok(())
     }
 
     fn reflect_u32(&mut self, data: &mut u32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = u32::from_le_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        Ok(())
     }
 
     fn reflect_u64(&mut self, data: &mut u64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = u64::from_le_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        Ok(())
     }
 
     fn reflect_i8(&mut self, data: &mut i8) -> std::io::Result<()> {
         let d = &mut [0; 1];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read one byte")?;
         *data = i8::from_le_bytes(*d);
-        check(size_read == 1, "failed to read one byte")
+        Ok(())
     }
 
     fn reflect_i16(&mut self, data: &mut i16) -> std::io::Result<()> {
         let d = &mut [0; 2];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read two bytes")?;
         *data = i16::from_le_bytes(*d);
-        check(size_read == 2, "failed to read two bytes")
+        Ok(())
     }
 
     fn reflect_i32(&mut self, data: &mut i32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = i32::from_le_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        Ok(())
     }
 
     fn reflect_i64(&mut self, data: &mut i64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = i64::from_le_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        Ok(())
     }
 
     fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
         let d = &mut [0; 4];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read four bytes")?;
         *data = f32::from_le_bytes(*d);
-        check(size_read == 4, "failed to read four bytes")
+        check(
+            !self.options.canonical || canonical_f32(*data).to_bits() == data.to_bits(),
+            "non-canonical float"
+        )
     }
 
     fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
         let d = &mut [0; 8];
-        let size_read = self.stream.read(d)?;
+        self.read_bytes(d, "failed to read eight bytes")?;
         *data = f64::from_le_bytes(*d);
-        check(size_read == 8, "failed to read eight bytes")
+        check(
+            !self.options.canonical || canonical_f64(*data).to_bits() == data.to_bits(),
+            "non-canonical float"
+        )
     }
 
     fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
@@ -911,30 +2622,35 @@ impl<'a, TStream: Read> SerializationReflector for BinaryReaderLittleEndian<'a,
         for _ in 0..size {
             let mut chr_id = 0u8;
             self.reflect_u8(&mut chr_id)?;
+            check_cp866_byte(self, chr_id)?;
             string.push(cp866_rs::decode_byte(chr_id));
         }
         Ok(())
     }
 
     fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
-        let mut chr_id = 255;
         string.clear();
-        while chr_id != 0 {
+        loop {
+            let mut chr_id = 0u8;
             self.reflect_u8(&mut chr_id)?;
+            if chr_id == 0 {
+                return Ok(());
+            }
+            check_cp866_byte(self, chr_id)?;
             string.push(cp866_rs::decode_byte(chr_id));
         }
-        Ok(())
     }
 
     fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
         let mut chr_id = 255;
         string.clear();
-        let mut offset = 0;
         let mut terminator_found = false;
-        for _ in 0..offset {
+        for _ in 0..length {
             self.reflect_u8(&mut chr_id)?;
+            check(!terminator_found || chr_id == 0 || !self.options.canonical, "non-canonical cp866 padding")?;
             if chr_id == 0 { terminator_found = true; }
             if !terminator_found {
+                check_cp866_byte(self, chr_id)?;
                 string.push(cp866_rs::decode_byte(chr_id));
             }
         }
@@ -945,7 +2661,8 @@ impl<'a, TStream: Read> SerializationReflector for BinaryReaderLittleEndian<'a,
 #[cfg(test)]
 mod tests {
     use std::io::{Cursor, Seek, SeekFrom};
-    use crate::{Reflectable, SerializationReflector, Endianness, BinaryReaderLittleEndian, BinaryReaderBigEndian, BinaryWriterLittleEndian};
+    use std::collections::{HashMap, BTreeMap, BTreeSet};
+    use crate::{Reflectable, SerializationReflector, Endianness, BinaryReaderLittleEndian, BinaryReaderBigEndian, BinaryWriterLittleEndian, BinaryWriterBigEndian, ReflectorOptions, CharEncoding, SizePolicy, ChecksumAlgorithm, CompressionCodec, XxHash64, ReflectError, DiffReflector, make_patch, apply_patch};
 
     #[derive(Default, Debug, Copy, Clone)]
     struct TestStruct {
@@ -1011,7 +2728,8 @@ mod tests {
              9, 10, 11, 12, 13, 14, 15, 16,
             17, 18, 19, 20
         ];
-        let mut deserializer = BinaryReaderLittleEndian{stream: &mut &test_set[..]};
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
         let mut vec = vec![0u8; 20];
         deserializer.reflect_u8_array(&mut vec).unwrap();
         assert_eq!(vec, vec![
@@ -1025,7 +2743,8 @@ mod tests {
             9, 10, 11, 12, 13, 14, 15, 16,
             17, 18, 19, 20
         ];
-        let mut deserializer = BinaryReaderBigEndian{stream: &mut &test_set[..]};
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderBigEndian::new(&mut stream);
         let mut vec = vec![0u8; 20];
         deserializer.reflect_u8_array(&mut vec).unwrap();
         assert_eq!(vec, vec![
@@ -1064,9 +2783,10 @@ mod tests {
         let mut s = "Hey dude".to_string();
         let mut s2 = "Yo dude".to_string();
         let mut stream_vec = Vec::new();
-        let mut serializer = BinaryWriterLittleEndian{ stream: &mut stream_vec };
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
         serializer.reflect_string(&mut s).unwrap();
-        let mut deserializer = BinaryReaderLittleEndian{ stream: &mut &stream_vec[..] };
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
         deserializer.reflect_string(&mut s2).unwrap();
         assert_eq!(s, s2);
     }
@@ -1087,14 +2807,15 @@ mod tests {
 
         let mut stream_vec = Vec::new();
 
-        let mut serializer = BinaryWriterLittleEndian{ stream: &mut stream_vec };
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
         serializer.reflect_i8(&mut a).unwrap();
         serializer.reflect_i16(&mut b).unwrap();
         serializer.reflect_i32(&mut c).unwrap();
         serializer.reflect_f32(&mut d).unwrap();
         serializer.reflect_f64(&mut e).unwrap();
 
-        let mut deserializer = BinaryReaderLittleEndian{ stream: &mut &stream_vec[..] };
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
         deserializer.reflect_i8(&mut a1).unwrap();
         deserializer.reflect_i16(&mut b1).unwrap();
         deserializer.reflect_i32(&mut c1).unwrap();
@@ -1107,4 +2828,794 @@ mod tests {
         assert_eq!(d, d1);
         assert_eq!(e, e1);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_map_serialization() {
+        let mut items = HashMap::new();
+        for i in (0..20u32).rev() {
+            items.insert(i * 7, TestStruct { a: i, e: i as u8, ..Default::default() });
+        }
+        let mut reordered = HashMap::new();
+        for i in 0..20u32 {
+            reordered.insert(i * 7, TestStruct { a: i, e: i as u8, ..Default::default() });
+        }
+
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_map(&mut items).unwrap();
+        let mut reordered_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut reordered_vec);
+        serializer.reflect_map(&mut reordered).unwrap();
+        assert_eq!(stream_vec, reordered_vec);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut stats: BTreeMap<u32, TestStruct> = BTreeMap::new();
+        deserializer.reflect_map(&mut stats).unwrap();
+        assert_eq!(stats.len(), 20);
+        assert_eq!(stats[&21].a, 3);
+        assert_eq!(stats[&21].e, 3);
+    }
+
+    #[test]
+    fn test_duplicate_set_keys() {
+        let test_set = &[1, 3, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0];
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut set = BTreeSet::new();
+        deserializer.reflect_set(&mut set).unwrap();
+        assert_eq!(set.into_iter().collect::<Vec<u32>>(), vec![1, 2]);
+
+        let options = ReflectorOptions { reject_duplicate_keys: true, ..Default::default() };
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, options);
+        let mut set: BTreeSet<u32> = BTreeSet::new();
+        assert!(deserializer.reflect_set(&mut set).is_err());
+    }
+    #[test]
+    fn test_wide_integers_chars_and_halves() {
+        let mut guid = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128;
+        let mut hash = -0x7123_4567_89AB_CDEF_0000_1111_2222_3333i128;
+        let mut chars = vec!['a', 'ж', '€', '🦀'];
+        let mut halves = vec![1.0f32, -2.5, 65504.0, 0.000061035156, 5.9604645e-8, f32::INFINITY];
+
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterBigEndian::new(&mut stream_vec);
+        serializer.reflect_u128(&mut guid).unwrap();
+        serializer.reflect_i128(&mut hash).unwrap();
+        serializer.reflect_char_array(&mut chars, CharEncoding::Utf8).unwrap();
+        serializer.reflect_char_array(&mut chars, CharEncoding::Utf32).unwrap();
+        serializer.reflect_f16_array(&mut halves).unwrap();
+        assert_eq!(&stream_vec[..16], &[
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF,
+            0xFE, 0xDC, 0xBA, 0x98, 0x76, 0x54, 0x32, 0x10
+        ]);
+        assert_eq!(&stream_vec[32..44], &[1, 4, b'a', 0xD0, 0xB6, 0xE2, 0x82, 0xAC, 0xF0, 0x9F, 0xA6, 0x80]);
+        assert_eq!(&stream_vec[stream_vec.len() - 12..], &[
+            0x3C, 0x00, 0xC1, 0x00, 0x7B, 0xFF, 0x04, 0x00, 0x00, 0x01, 0x7C, 0x00
+        ]);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderBigEndian::new(&mut stream);
+        let (mut guid1, mut hash1) = (0, 0);
+        let (mut chars1, mut chars2, mut halves1) = (Vec::new(), Vec::new(), Vec::new());
+        deserializer.reflect_u128(&mut guid1).unwrap();
+        deserializer.reflect_i128(&mut hash1).unwrap();
+        deserializer.reflect_char_array(&mut chars1, CharEncoding::Utf8).unwrap();
+        deserializer.reflect_char_array(&mut chars2, CharEncoding::Utf32).unwrap();
+        deserializer.reflect_f16_array(&mut halves1).unwrap();
+        assert_eq!(guid, guid1);
+        assert_eq!(hash, hash1);
+        assert_eq!(chars, chars1);
+        assert_eq!(chars, chars2);
+        assert_eq!(halves, halves1);
+
+        let mut rounded = 1.0009766f32 + 0.0001;
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_f16(&mut rounded).unwrap();
+        serializer.reflect_u128(&mut guid).unwrap();
+        assert_eq!(rounded, 1.0009766f32 + 0.0001);
+        assert_eq!(&stream_vec[2..], &[
+            0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE,
+            0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01
+        ]);
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        deserializer.reflect_f16(&mut rounded).unwrap();
+        deserializer.reflect_u128(&mut guid1).unwrap();
+        assert_eq!(rounded, 1.0009766);
+        assert_eq!(guid, guid1);
+    }
+
+    #[test]
+    fn test_invalid_chars() {
+        let test_set = &[0xFF, 0x00, 0xD8, 0x00];
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut ch = ' ';
+        assert!(deserializer.reflect_char(&mut ch, CharEncoding::Utf8).is_err());
+        let mut stream = &test_set[..];
+        let mut deserializer = BinaryReaderBigEndian::new(&mut stream);
+        assert!(deserializer.reflect_char(&mut ch, CharEncoding::Utf32).is_err());
+    }
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct AlignedRecord {
+        kind: u8,
+        value: u64,
+        payload_offset: u64,
+        flags: u16
+    }
+
+    impl Reflectable for AlignedRecord {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self, reflector:
+            &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u8(&mut self.kind)?;
+            reflector.align(8)?;
+            reflector.reflect_u64(&mut self.value)?;
+            reflector.reflect_reserved(2, 0xAA)?;
+            reflector.reflect_padding(2)?;
+            self.payload_offset = reflector.position();
+            reflector.reflect_u16(&mut self.flags)
+        }
+    }
+
+    #[test]
+    fn test_padding_and_alignment() {
+        let mut record = AlignedRecord { kind: 3, value: 0x0102030405060708, payload_offset: 0, flags: 0xBEEF };
+        let mut stream_vec = Vec::new();
+        record.serialize(&mut stream_vec, Endianness::BigEndian).unwrap();
+        assert_eq!(stream_vec, vec![
+            3, 0, 0, 0, 0, 0, 0, 0,
+            1, 2, 3, 4, 5, 6, 7, 8,
+            0xAA, 0xAA, 0, 0, 0xBE, 0xEF
+        ]);
+        assert_eq!(record.payload_offset, 20);
+
+        stream_vec[17] = 0;
+        stream_vec[18] = 0xFF;
+        let relaxed = AlignedRecord::deserialize(&mut &stream_vec[..], Endianness::BigEndian).unwrap();
+        assert_eq!(relaxed, record);
+
+        let options = ReflectorOptions { strict_reserved: true, ..Default::default() };
+        let strict = AlignedRecord::deserialize_ext(&mut &stream_vec[..], Endianness::BigEndian, options);
+        assert!(strict.is_err());
+    }
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct UnitV1 {
+        id: u16,
+        hp: u16
+    }
+
+    impl Reflectable for UnitV1 {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self, reflector:
+            &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u16(&mut self.id)?;
+            reflector.reflect_u16(&mut self.hp)
+        }
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct UnitV2 {
+        id: u16,
+        hp: u16,
+        mana: u32
+    }
+
+    impl Reflectable for UnitV2 {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self, reflector:
+            &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u16(&mut self.id)?;
+            reflector.reflect_u16(&mut self.hp)?;
+            reflector.reflect_u32(&mut self.mana)
+        }
+    }
+
+    #[test]
+    fn test_framed_composites() {
+        let mut units = [
+            UnitV2 { id: 1, hp: 10, mana: 100 },
+            UnitV2 { id: 2, hp: 20, mana: 200 }
+        ];
+        let mut trailer = 0xCAFEu16;
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        for unit in units.iter_mut() {
+            serializer.reflect_composite_framed(unit).unwrap();
+        }
+        serializer.reflect_u16(&mut trailer).unwrap();
+        assert_eq!(serializer.position(), 22);
+        assert_eq!(&stream_vec[..10], &[1, 8, 1, 0, 10, 0, 100, 0, 0, 0]);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut old_units = vec![UnitV1::default(); 2];
+        for unit in old_units.iter_mut() {
+            deserializer.reflect_composite_framed(unit).unwrap();
+        }
+        let mut trailer1 = 0;
+        deserializer.reflect_u16(&mut trailer1).unwrap();
+        assert_eq!(old_units, vec![UnitV1 { id: 1, hp: 10 }, UnitV1 { id: 2, hp: 20 }]);
+        assert_eq!(trailer1, trailer);
+
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_composite_framed(&mut old_units[0]).unwrap();
+        serializer.reflect_u16(&mut trailer).unwrap();
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        assert!(deserializer.reflect_composite_framed(&mut units[0]).is_err());
+    }
+    #[test]
+    fn test_back_patching() {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut serializer = BinaryWriterBigEndian::new(&mut cursor);
+        serializer.reflect_magic(b"TOC").unwrap();
+        let table_offset = serializer.reserve_u32().unwrap();
+        let table_length = serializer.reserve::<u16>().unwrap();
+        let mut payload = vec![7u8; 5];
+        serializer.reflect_u8_array(&mut payload).unwrap();
+        serializer.reflect_offset_to(table_offset, |w| {
+            let start = w.position();
+            let mut entries = vec![10u32, 20, 30];
+            w.reflect_u32_array(&mut entries)?;
+            let length = (w.position() - start) as u16;
+            w.patch(table_length, length)
+        }).unwrap();
+        let mut trailer = 0xFFu8;
+        serializer.reflect_frame(|w| {
+            let inner = w.reserve_u32()?;
+            w.reflect_u8(&mut trailer)?;
+            w.patch(inner, 0xDEADBEEF)
+        }).unwrap();
+        assert_eq!(table_offset.position(), 3);
+        assert_eq!(cursor.into_inner(), vec![
+            b'T', b'O', b'C',
+            0, 0, 0, 16,
+            0, 14,
+            1, 5, 7, 7, 7, 7, 7,
+            1, 3, 0, 0, 0, 10, 0, 0, 0, 20, 0, 0, 0, 30,
+            1, 5, 0xDE, 0xAD, 0xBE, 0xEF, 0xFF
+        ]);
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut serializer = BinaryWriterBigEndian::new(&mut cursor);
+        let outer = serializer.reserve_u32().unwrap();
+        serializer.reflect_u32(&mut 1).unwrap();
+        assert!(serializer.reflect_frame(|w| {
+            w.reflect_u8(&mut 2)?;
+            w.reflect_offset_to(outer, |w| w.reflect_u8(&mut 3))
+        }).is_err());
+        serializer.reflect_frame(|w| {
+            let inner = w.reserve::<u8>()?;
+            w.reflect_u8(&mut 2)?;
+            w.reflect_offset_to(inner, |w| w.reflect_u8(&mut 3))
+        }).unwrap();
+    }
+
+    #[test]
+    fn test_back_patching_checksummed_sections() {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut serializer = BinaryWriterLittleEndian::new(&mut cursor);
+        let mut slot = None;
+        serializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |w| {
+            slot = Some(w.reserve_u32()?);
+            w.reflect_u8(&mut 1)
+        }).unwrap();
+        assert!(serializer.patch(slot.unwrap(), 7).is_err());
+        assert!(serializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |w| {
+            let inner = w.reserve_u32()?;
+            w.patch(inner, 7)
+        }).is_err());
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut serializer = BinaryWriterLittleEndian::new(&mut cursor);
+        let count = serializer.reserve::<u16>().unwrap();
+        serializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |w| {
+            w.reflect_compressed(CompressionCodec::Stored, |w| {
+                let inner = w.reserve_u32()?;
+                w.reflect_u8(&mut 2)?;
+                w.patch(inner, 0x01020304)
+            })
+        }).unwrap();
+        serializer.patch(count, 1).unwrap();
+
+        let bytes = cursor.into_inner();
+        let mut stream = &bytes[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let (mut count, mut inner, mut tail) = (0u16, 0u32, 0u8);
+        deserializer.reflect_u16(&mut count).unwrap();
+        deserializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| {
+            r.reflect_compressed(CompressionCodec::Stored, |r| {
+                r.reflect_u32(&mut inner)?;
+                r.reflect_u8(&mut tail)
+            })
+        }).unwrap();
+        assert_eq!((count, inner, tail), (1, 0x01020304, 2));
+    }
+    fn read_linked_list(
+        reader: &mut BinaryReaderLittleEndian<Cursor<&[u8]>>,
+        values: &mut Vec<u16>
+    ) -> std::io::Result<()> {
+        reader.reflect_pointer_with(SizePolicy::U16, |r| {
+            let mut value = 0;
+            r.reflect_u16(&mut value)?;
+            values.push(value);
+            if value != 0 {
+                read_linked_list(r, values)?;
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_random_access_reads() {
+        let test_set: &[u8] = &[
+            8, 0, 0, 0,
+            28, 0,
+            0xAA, 0,
+            1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1,
+            7, 0, 32, 0,
+            0, 0
+        ];
+        let mut cursor = Cursor::new(test_set);
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut cursor);
+        let mut record = TestStruct::default();
+        deserializer.reflect_pointer(&mut record, SizePolicy::U32).unwrap();
+        let mut tail = 0u8;
+        deserializer.reflect_at(6, |r| r.reflect_u8(&mut tail)).unwrap();
+        assert_eq!(deserializer.position(), 4);
+        assert_eq!((record.a, record.b, record.e, record.f), (1, 2, 1, 1));
+        assert_eq!(tail, 0xAA);
+
+        let mut values = Vec::new();
+        assert!(read_linked_list(&mut deserializer, &mut values).is_ok());
+        assert_eq!(values, vec![7, 0]);
+    }
+
+    #[test]
+    fn test_pointer_cycles_and_overlaps() {
+        let cycle: &[u8] = &[2, 0, 5, 0, 6, 0, 9, 0, 2, 0];
+        let mut cursor = Cursor::new(cycle);
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut cursor);
+        let mut values = Vec::new();
+        assert!(read_linked_list(&mut deserializer, &mut values).is_err());
+        assert_eq!(values, vec![5, 9]);
+
+        let overlap: &[u8] = &[4, 0, 5, 0, 1, 2, 3, 4];
+        let mut cursor = Cursor::new(overlap);
+        let mut deserializer = BinaryReaderBigEndian::new(&mut cursor);
+        let mut first = 0u32;
+        let mut second = 0u16;
+        deserializer.reflect_pointer(&mut first, SizePolicy::U8).unwrap();
+        deserializer.reflect_u8(&mut 0).unwrap();
+        assert!(deserializer.reflect_pointer(&mut second, SizePolicy::U8).is_err());
+        assert_eq!(first, 0x01020304);
+
+        let overlap_before: &[u8] = &[4, 2, 1, 2, 3, 4];
+        let mut cursor = Cursor::new(overlap_before);
+        let mut deserializer = BinaryReaderBigEndian::new(&mut cursor);
+        let mut first = 0u16;
+        let mut second = 0u32;
+        deserializer.reflect_pointer(&mut first, SizePolicy::U8).unwrap();
+        assert!(deserializer.reflect_pointer(&mut second, SizePolicy::U8).is_err());
+
+        let mut chain = vec![2, 0];
+        for node in 0..10u16 {
+            let next = 2 + 4 * (node + 1);
+            chain.extend_from_slice(&[1, 0, next as u8, 0]);
+        }
+        chain.extend_from_slice(&[0, 0]);
+        let options = ReflectorOptions { max_depth: 4, ..Default::default() };
+        let mut cursor = Cursor::new(&chain[..]);
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut cursor, options);
+        let mut values = Vec::new();
+        let error = read_linked_list(&mut deserializer, &mut values).unwrap_err();
+        assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::DepthLimitExceeded { limit: 4 }));
+        assert_eq!(values.len(), 4);
+
+        let far: &[u8] = &[0, 0, 0, 0, 0, 0, 0, 0x80, 1];
+        let mut cursor = Cursor::new(far);
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut cursor);
+        assert!(deserializer.reflect_pointer(&mut 0u8, SizePolicy::U64).is_err());
+        let farthest: &[u8] = &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 1];
+        let mut cursor = Cursor::new(farthest);
+        let mut deserializer = BinaryReaderBigEndian::new(&mut cursor);
+        let error = deserializer.reflect_pointer(&mut 0u8, SizePolicy::U64).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+    #[test]
+    fn test_checksummed_sections() {
+        let mut record = TestStruct { a: 1, b: 2, c: 3, d: 4, e: 5, f: 6 };
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |w| w.reflect_composite(&mut record)).unwrap();
+        serializer.reflect_checksummed(ChecksumAlgorithm::XxHash64, |w| {
+            w.reflect_composite_framed(&mut record)?;
+            w.reflect_checksummed(ChecksumAlgorithm::Adler32, |w| w.reflect_u8(&mut 9))
+        }).unwrap();
+        assert_eq!(&stream_vec[20..24], &crate::crc32(&stream_vec[..20]).to_le_bytes());
+        let mut xxhash = XxHash64::new();
+        xxhash.update(&stream_vec[24..51]);
+        assert_eq!(&stream_vec[51..], &xxhash.finish().to_le_bytes());
+
+        let read_back = |bytes: &[u8]| {
+            let mut stream = bytes;
+            let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+            let mut first = TestStruct::default();
+            let mut second = TestStruct::default();
+            let mut tail = 0u8;
+            deserializer.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| r.reflect_composite(&mut first))?;
+            deserializer.reflect_checksummed(ChecksumAlgorithm::XxHash64, |r| {
+                r.reflect_composite_framed(&mut second)?;
+                r.reflect_checksummed(ChecksumAlgorithm::Adler32, |r| r.reflect_u8(&mut tail))
+            })?;
+            Ok::<_, std::io::Error>((first.a, second.f, tail))
+        };
+        assert_eq!(read_back(&stream_vec).unwrap(), (1, 6, 9));
+
+        let mut corrupted = stream_vec.clone();
+        corrupted[3] ^= 0x10;
+        let error = read_back(&corrupted).unwrap_err();
+        let expected = crate::crc32(&stream_vec[..20]) as u64;
+        let actual = crate::crc32(&corrupted[..20]) as u64;
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::ChecksumMismatch { expected, actual })
+        );
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Empty;
+
+    impl Reflectable for Empty {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            _reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq)]
+    struct Chain {
+        next: Vec<Chain>
+    }
+
+    impl Reflectable for Chain {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_array_of_composites(&mut self.next)
+        }
+    }
+
+    #[test]
+    fn test_malformed_sizes() {
+        let mut stream = &[3u8, 1, 2][..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut bytes = Vec::new();
+        let error = deserializer.reflect_u8_array(&mut bytes).unwrap_err();
+        assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::InvalidSizeTag { tag: 3 }));
+
+        let mut stream = &[8u8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 1, 2, 3, 4][..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut words: Vec<u32> = Vec::new();
+        assert!(deserializer.reflect_u32_array(&mut words).is_err());
+
+        let mut stream = &[4u8, 0xFF, 0xFF, 0xFF, 0xFF, 0xAA][..];
+        let mut deserializer = BinaryReaderBigEndian::new(&mut stream);
+        let mut bits = Vec::new();
+        assert!(deserializer.reflect_packed_bool_array(&mut bits, crate::BitOrder::MsbFirst).is_err());
+
+        let mut stream = &[4u8, 0xFF, 0xFF, 0xFF, 0xFF][..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut empties: Vec<Empty> = Vec::new();
+        assert!(deserializer.reflect_array_of_composites(&mut empties).is_err());
+
+        let mut stream = &[1u8, 3][..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        deserializer.reflect_array_of_composites(&mut empties).unwrap();
+        assert_eq!(empties.len(), 3);
+
+        let options = ReflectorOptions { max_empty_elements: 2, ..Default::default() };
+        let mut stream = &[1u8, 3][..];
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, options);
+        assert!(deserializer.reflect_array_of_composites(&mut empties).is_err());
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::with_options(&mut stream_vec, options);
+        assert!(serializer.reflect_array_of_composites(&mut empties).is_err());
+        empties.truncate(2);
+        serializer.reflect_array_of_composites(&mut empties).unwrap();
+    }
+
+    #[test]
+    fn test_cp866_strings() {
+        let mut name = "hero".to_string();
+        let mut title = "sir".to_string();
+        let mut mixed = "aж".to_string();
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        serializer.reflect_cp866_zstring(&mut name).unwrap();
+        serializer.reflect_cp866_zstring_ext(6, &mut title).unwrap();
+        serializer.reflect_cp866_string(&mut mixed).unwrap();
+        assert!(serializer.reflect_cp866_zstring_ext(2, &mut title).is_err());
+        assert_eq!(stream_vec, [b'h', b'e', b'r', b'o', 0, b's', b'i', b'r', 0, 0, 0, 2, b'a', b' ']);
+
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let (mut name1, mut title1, mut mixed1) = (String::new(), String::new(), String::new());
+        deserializer.reflect_cp866_zstring(&mut name1).unwrap();
+        deserializer.reflect_cp866_zstring_ext(6, &mut title1).unwrap();
+        deserializer.reflect_cp866_string(&mut mixed1).unwrap();
+        assert_eq!((name1.as_str(), title1.as_str(), mixed1.as_str()), ("hero", "sir", "a "));
+        assert_eq!(deserializer.position(), stream_vec.len() as u64);
+    }
+
+    #[derive(Clone, Default, Debug)]
+    struct Sign {
+        title: String,
+        caption: String,
+        end: u64
+    }
+
+    impl Reflectable for Sign {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_cp866_string(&mut self.title)?;
+            reflector.reflect_cp866_zstring(&mut self.caption)?;
+            self.end = reflector.position();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cp866_positions_count_characters() {
+        let old = Sign::default();
+        let mut new = Sign { title: "жук".to_string(), caption: "ёж".to_string(), end: 0 };
+        let mut bytes = Vec::new();
+        new.serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+        assert_eq!(new.end, bytes.len() as u64);
+
+        new.reflect(&mut DiffReflector::new()).unwrap();
+        assert_eq!(new.end, bytes.len() as u64);
+        let mut patched = old.clone();
+        apply_patch(&mut patched, &make_patch(&old, &new).unwrap()).unwrap();
+        assert_eq!(patched.end, bytes.len() as u64);
+    }
+
+    struct Trickle<'a> {
+        data: &'a [u8],
+        interrupted: bool
+    }
+
+    impl<'a> std::io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupted = !self.interrupted;
+            if self.interrupted {
+                return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "interrupted"));
+            }
+            let size = buf.len().min(self.data.len()).min(1);
+            buf[..size].copy_from_slice(&self.data[..size]);
+            self.data = &self.data[size..];
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn test_short_reads() {
+        let mut record = TestStruct { a: 1, b: 2, c: 3, d: 4, e: 5, f: 6 };
+        let mut stream_vec = Vec::new();
+        record.serialize(&mut stream_vec, Endianness::BigEndian).unwrap();
+        let mut stream = Trickle { data: &stream_vec, interrupted: false };
+        let read_back = TestStruct::deserialize(&mut stream, Endianness::BigEndian).unwrap();
+        assert_eq!((read_back.a, read_back.d, read_back.f), (1, 4, 6));
+
+        let mut stream = Trickle { data: &stream_vec[..10], interrupted: false };
+        assert!(TestStruct::deserialize(&mut stream, Endianness::BigEndian).is_err());
+    }
+
+    #[test]
+    fn test_invalid_utf8_string() {
+        let mut stream = &[1u8, 2, 0xC3, 0x28][..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut string = String::new();
+        assert!(deserializer.reflect_string(&mut string).is_err());
+        assert!(string.is_empty());
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut stream_vec = Vec::new();
+        for _ in 0..200 {
+            stream_vec.extend_from_slice(&[1, 1]);
+        }
+        stream_vec.extend_from_slice(&[1, 0]);
+
+        let error = Chain::deserialize(&mut &stream_vec[..], Endianness::LittleEndian).unwrap_err();
+        assert_eq!(ReflectError::from_io_error(&error), Some(&ReflectError::DepthLimitExceeded { limit: 128 }));
+
+        let options = ReflectorOptions { max_depth: 256, ..Default::default() };
+        let mut stream = &stream_vec[..];
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, options);
+        let mut chain = Chain::default();
+        deserializer.reflect_composite(&mut chain).unwrap();
+        let mut depth = 0;
+        let mut link = &chain;
+        while let Some(next) = link.next.first() {
+            depth += 1;
+            link = next;
+        }
+        assert_eq!(depth, 200);
+    }
+
+    #[test]
+    fn test_strict_size_tags() {
+        let wide = &[8, 3, 0, 0, 0, 0, 0, 0, 0, 7, 8, 9];
+        let mut stream = &wide[..];
+        let mut deserializer = BinaryReaderLittleEndian::new(&mut stream);
+        let mut bytes = Vec::new();
+        deserializer.reflect_u8_array(&mut bytes).unwrap();
+        assert_eq!(bytes, vec![7, 8, 9]);
+
+        let strict = ReflectorOptions { strict_size_tags: true, ..Default::default() };
+        let mut stream = &wide[..];
+        let mut deserializer = BinaryReaderLittleEndian::with_options(&mut stream, strict);
+        let error = deserializer.reflect_u8_array(&mut bytes).unwrap_err();
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::NonCanonicalSizeTag { tag: 8, expected: 1 })
+        );
+
+        let mut stream = &[1, 3, 7, 8, 9][..];
+        let mut deserializer = BinaryReaderBigEndian::with_options(&mut stream, strict);
+        let error = deserializer.reflect_u8_array_ext(&mut bytes, SizePolicy::U16).unwrap_err();
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::NonCanonicalSizeTag { tag: 1, expected: 2 })
+        );
+
+        let mut stream = &[2, 0, 3, 7, 8, 9][..];
+        let mut deserializer = BinaryReaderBigEndian::with_options(&mut stream, strict);
+        deserializer.reflect_u8_array_ext(&mut bytes, SizePolicy::U16).unwrap();
+        assert_eq!(bytes, vec![7, 8, 9]);
+
+        let mut long = vec![0u8; 300];
+        let mut stream_vec = Vec::new();
+        let mut serializer = BinaryWriterLittleEndian::new(&mut stream_vec);
+        assert!(serializer.reflect_u8_array_ext(&mut long, SizePolicy::U8).is_err());
+        assert!(stream_vec.is_empty());
+    }
+
+    #[derive(Default, Debug, Clone)]
+    struct Ledger {
+        balance: f64,
+        rate: f32,
+        half: f32,
+        flags: Vec<bool>,
+        verified: bool,
+        owners: HashMap<u32, String>,
+        tags: BTreeSet<u16>,
+        code: String,
+        history: Vec<f64>
+    }
+
+    impl Reflectable for Ledger {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_f64(&mut self.balance)?;
+            reflector.reflect_f32(&mut self.rate)?;
+            reflector.reflect_f16(&mut self.half)?;
+            reflector.reflect_packed_bool_array(&mut self.flags, crate::BitOrder::LsbFirst)?;
+            reflector.reflect_bool(&mut self.verified)?;
+            reflector.align(4)?;
+            reflector.reflect_reserved(1, 0)?;
+            reflector.reflect_map(&mut self.owners)?;
+            reflector.reflect_set(&mut self.tags)?;
+            reflector.reflect_cp866_zstring_ext(6, &mut self.code)?;
+            reflector.reflect_compressed(crate::CompressionCodec::Stored, |r| r.reflect_f64_array(&mut self.history))
+        }
+    }
+
+    fn ledger() -> Ledger {
+        Ledger {
+            balance: -0.0,
+            rate: f32::from_bits(0xFFC01234),
+            half: f32::NAN,
+            flags: vec![true, false, true],
+            verified: true,
+            owners: vec![(7, "ann".to_string()), (2, "bob".to_string()), (300, "cy".to_string())]
+                .into_iter()
+                .collect(),
+            tags: vec![9, 1, 4].into_iter().collect(),
+            code: "AB-1".to_string(),
+            history: vec![1.5, f64::from_bits(0x7FF0000000000001), -0.0, -2.0]
+        }
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let canonical = ReflectorOptions::canonical();
+        for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+            let mut encoded = Vec::new();
+            ledger().serialize_ext(&mut encoded, endianness, canonical).unwrap();
+            let mut decoded = Ledger::deserialize_ext(&mut &encoded[..], endianness, canonical).unwrap();
+            assert_eq!(decoded.balance.to_bits(), 0);
+            assert!(decoded.rate.is_nan() && decoded.history[1].is_nan());
+            let mut reencoded = Vec::new();
+            decoded.serialize_ext(&mut reencoded, endianness, canonical).unwrap();
+            assert_eq!(encoded, reencoded);
+
+            let mut plain = Vec::new();
+            ledger().serialize(&mut plain, endianness).unwrap();
+            assert_ne!(plain, encoded);
+            assert!(Ledger::deserialize(&mut &plain[..], endianness).is_ok());
+            assert!(Ledger::deserialize_ext(&mut &plain[..], endianness, canonical).is_err());
+        }
+
+        let mut encoded = Vec::new();
+        ledger().serialize_ext(&mut encoded, Endianness::LittleEndian, canonical).unwrap();
+        let rejected = |offset: usize, byte: u8| {
+            let mut tampered = encoded.clone();
+            tampered[offset] = byte;
+            assert!(Ledger::deserialize(&mut &tampered[..], Endianness::LittleEndian).is_ok());
+            Ledger::deserialize_ext(&mut &tampered[..], Endianness::LittleEndian, canonical).is_err()
+        };
+        assert!(rejected(7, 0x80));
+        assert!(rejected(16, 0x85));
+        assert!(rejected(17, 2));
+        assert!(rejected(18, 1));
+        assert!(rejected(20, 7));
+
+        let mut widened = encoded.clone();
+        widened.splice(21..23, vec![2, 3, 0]);
+        assert!(Ledger::deserialize(&mut &widened[..], Endianness::LittleEndian).is_ok());
+        assert!(Ledger::deserialize_ext(&mut &widened[..], Endianness::LittleEndian, canonical).is_err());
+    }
+
+    #[test]
+    fn test_canonical_rejects_non_canonical_input() {
+        let canonical = ReflectorOptions::canonical();
+        let read_map = |bytes: &[u8], options| {
+            let mut stream = bytes;
+            let mut map: BTreeMap<u8, u8> = BTreeMap::new();
+            BinaryReaderLittleEndian::with_options(&mut stream, options).reflect_map(&mut map).map(|_| map)
+        };
+        assert!(read_map(&[1, 2, 3, 1, 5, 1], canonical).is_ok());
+        assert!(read_map(&[1, 2, 5, 1, 3, 1], Default::default()).is_ok());
+        assert!(read_map(&[1, 2, 5, 1, 3, 1], canonical).is_err());
+
+        let mut stream = &[1u8, 2, 4, 4][..];
+        let mut set: BTreeSet<u8> = BTreeSet::new();
+        assert!(BinaryReaderLittleEndian::with_options(&mut stream, canonical).reflect_set(&mut set).is_err());
+
+        let read_zstring = |bytes: &[u8], options| {
+            let mut stream = bytes;
+            let mut string = String::new();
+            BinaryReaderLittleEndian::with_options(&mut stream, options).reflect_cp866_zstring_ext(3, &mut string)
+        };
+        assert!(read_zstring(&[b'a', 0x80, 0], Default::default()).is_ok());
+        assert!(read_zstring(&[b'a', 0x80, 0], canonical).is_err());
+        assert!(read_zstring(&[b'a', 0, b'b'], Default::default()).is_ok());
+        assert!(read_zstring(&[b'a', 0, b'b'], canonical).is_err());
+
+        let read_frame = |bytes: &[u8], options| {
+            let mut stream = bytes;
+            let mut byte = 0u8;
+            BinaryReaderLittleEndian::with_options(&mut stream, options).reflect_frame(|r| r.reflect_u8(&mut byte))
+        };
+        assert!(read_frame(&[1, 1, 5], canonical).is_ok());
+        assert!(read_frame(&[1, 2, 5, 9], Default::default()).is_ok());
+        assert!(read_frame(&[1, 2, 5, 9], canonical).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// Implements every `reflect_*_array` and `reflect_*_array_ext` pair of a reflector through one of its
+// own methods, called as `$method(data, size_policy, reflect_element)` with no size policy for the
+// tagged variant.
+macro_rules! delegate_arrays {
+    ($method:ident) => {
+        delegate_arrays! {
+            $method;
+            reflect_u8_array, reflect_u8_array_ext, u8, reflect_u8;
+            reflect_u16_array, reflect_u16_array_ext, u16, reflect_u16;
+            reflect_u32_array, reflect_u32_array_ext, u32, reflect_u32;
+            reflect_u64_array, reflect_u64_array_ext, u64, reflect_u64;
+            reflect_u128_array, reflect_u128_array_ext, u128, reflect_u128;
+            reflect_i8_array, reflect_i8_array_ext, i8, reflect_i8;
+            reflect_i16_array, reflect_i16_array_ext, i16, reflect_i16;
+            reflect_i32_array, reflect_i32_array_ext, i32, reflect_i32;
+            reflect_i64_array, reflect_i64_array_ext, i64, reflect_i64;
+            reflect_i128_array, reflect_i128_array_ext, i128, reflect_i128;
+            reflect_f16_array, reflect_f16_array_ext, f32, reflect_f16;
+            reflect_f32_array, reflect_f32_array_ext, f32, reflect_f32;
+            reflect_f64_array, reflect_f64_array_ext, f64, reflect_f64;
+            reflect_bool_array, reflect_bool_array_ext, bool, reflect_bool;
+        }
+    };
+    ($method:ident; $($array:ident, $array_ext:ident, $element:ty, $reflect:ident;)*) => {$(
+        fn $array(&mut self, data: &mut Vec<$element>) -> std::io::Result<()> {
+            self.$method(data, None, |r, element| r.$reflect(element))
+        }
+
+        fn $array_ext(&mut self, data: &mut Vec<$element>, size_policy: SizePolicy) -> std::io::Result<()> {
+            self.$method(data, Some(size_policy), |r, element| r.$reflect(element))
+        }
+    )*};
+}
+
+// Passes every fixed-width primitive to `$callback` as `reflect method, type, variant;`, where the
+// variant is named the same in both `Value` and `Layout`.
+macro_rules! with_primitives {
+    ($callback:ident) => {
+        $callback! {
+            reflect_u8, u8, U8;
+            reflect_u16, u16, U16;
+            reflect_u32, u32, U32;
+            reflect_u64, u64, U64;
+            reflect_u128, u128, U128;
+            reflect_i8, i8, I8;
+            reflect_i16, i16, I16;
+            reflect_i32, i32, I32;
+            reflect_i64, i64, I64;
+            reflect_i128, i128, I128;
+            reflect_f32, f32, F32;
+            reflect_f64, f64, F64;
+        }
+    };
+}
@@ -0,0 +1,776 @@
+use crate::{
+    SerializationReflector, Reflectable, TaggedReflectable, ReflectableMap, ReflectableSet, ReflectError,
+    ChecksumAlgorithm, CompressionCodec, SizePolicy, CharEncoding, BitOrder, Value, DiffReflector,
+    reflect_size, reflect_size_ext, reflect_digest, reflect_packed_bools, fill_map, fill_set, check,
+    cp866_size
+};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatchOperation {
+    Set { path: Vec<u32>, value: Value },
+    Insert { path: Vec<u32>, index: u32, value: Value },
+    Remove { path: Vec<u32>, index: u32 }
+}
+
+impl Default for PatchOperation {
+    fn default() -> Self {
+        PatchOperation::Set { path: Vec::new(), value: Value::default() }
+    }
+}
+
+impl TaggedReflectable for PatchOperation {
+    fn get_size_policy() -> SizePolicy {
+        SizePolicy::U8
+    }
+
+    fn get_tag(&self) -> u64 {
+        match self {
+            PatchOperation::Set { .. } => 0,
+            PatchOperation::Insert { .. } => 1,
+            PatchOperation::Remove { .. } => 2
+        }
+    }
+
+    fn variant_tags() -> Vec<u64> {
+        vec![0, 1, 2]
+    }
+
+    fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        tag: u64,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        if self.get_tag() != tag {
+            *self = match tag {
+                0 => PatchOperation::Set { path: Vec::new(), value: Value::default() },
+                1 => PatchOperation::Insert { path: Vec::new(), index: 0, value: Value::default() },
+                2 => PatchOperation::Remove { path: Vec::new(), index: 0 },
+                _ => return Err(std::io::Error::other("unknown patch operation"))
+            };
+        }
+        match self {
+            PatchOperation::Set { path, value } => {
+                reflector.reflect_u32_array(path)?;
+                reflector.reflect_tagged_composite(value)
+            },
+            PatchOperation::Insert { path, index, value } => {
+                reflector.reflect_u32_array(path)?;
+                reflector.reflect_u32(index)?;
+                reflector.reflect_tagged_composite(value)
+            },
+            PatchOperation::Remove { path, index } => {
+                reflector.reflect_u32_array(path)?;
+                reflector.reflect_u32(index)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Patch {
+    pub base_hash: u64,
+    pub operations: Vec<PatchOperation>
+}
+
+impl Reflectable for Patch {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_magic(b"BSPT")?;
+        reflector.reflect_u64(&mut self.base_hash)?;
+        reflector.reflect_array_of_tagged_composites(&mut self.operations)
+    }
+}
+
+pub fn make_patch<T: Reflectable>(old: &T, new: &T) -> std::io::Result<Patch> {
+    let mut old = old.clone();
+    let base_hash = old.content_hash()?;
+    let old = DiffReflector::record(&mut old)?;
+    let new = DiffReflector::record(&mut new.clone())?;
+    let mut operations = Vec::new();
+    collect_operations(&mut Vec::new(), &old, &new, &mut operations);
+    Ok(Patch { base_hash, operations })
+}
+
+pub fn apply_patch<T: Reflectable>(target: &mut T, patch: &Patch) -> std::io::Result<()> {
+    let actual = target.content_hash()?;
+    if actual != patch.base_hash {
+        return Err(ReflectError::PatchBaseMismatch { expected: patch.base_hash, actual }.into());
+    }
+    let mut tree = DiffReflector::record(target)?;
+    for operation in patch.operations.iter() {
+        apply_operation(&mut tree, operation)?;
+    }
+    let fields = match tree {
+        Value::Struct(fields) => fields,
+        _ => return Err(layout_error())
+    };
+    let mut patched = T::default();
+    let mut reflector = ReplayReflector::new(fields);
+    patched.reflect(&mut reflector)?;
+    *target = patched;
+    Ok(())
+}
+
+fn collect_operations(path: &mut Vec<u32>, old: &Value, new: &Value, operations: &mut Vec<PatchOperation>) {
+    match (old, new) {
+        (Value::Struct(old_fields), Value::Struct(new_fields)) if old_fields.len() == new_fields.len() => {
+            for (i, (old, new)) in old_fields.iter().zip(new_fields.iter()).enumerate() {
+                path.push(i as u32);
+                collect_operations(path, old, new, operations);
+                path.pop();
+            }
+        },
+        (Value::Tagged(old_tag, old_body), Value::Tagged(new_tag, new_body)) if old_tag == new_tag =>
+            collect_operations(path, old_body, new_body, operations),
+        (Value::Array(old_items), Value::Array(new_items)) =>
+            collect_array_operations(path, old_items, new_items, operations),
+        _ if !old.same_as(new) =>
+            operations.push(PatchOperation::Set { path: path.clone(), value: new.clone() }),
+        _ => {}
+    }
+}
+
+const MAX_EDIT_DISTANCE: usize = 256;
+
+enum Edit {
+    Keep,
+    Remove,
+    Insert
+}
+
+fn collect_array_operations(path: &mut Vec<u32>, old: &[Value], new: &[Value], operations: &mut Vec<PatchOperation>) {
+    let common = old.len().min(new.len());
+    let prefix = (0..common)
+        .take_while(|&i| old[i].same_as(&new[i]))
+        .count();
+    let suffix = (0..common - prefix)
+        .take_while(|&i| old[old.len() - 1 - i].same_as(&new[new.len() - 1 - i]))
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let edits = edit_script(old_middle, new_middle).unwrap_or_else(|| {
+        let mut edits = Vec::new();
+        edits.extend(old_middle.iter().map(|_| Edit::Remove));
+        edits.extend(new_middle.iter().map(|_| Edit::Insert));
+        edits
+    });
+
+    let (mut index, mut old_index, mut new_index) = (prefix, 0, 0);
+    let mut edits = edits.into_iter().peekable();
+    while let Some(edit) = edits.next() {
+        if let Edit::Keep = edit {
+            index += 1;
+            old_index += 1;
+            new_index += 1;
+            continue;
+        }
+        let (mut removed, mut inserted) = (0, 0);
+        let mut count = |edit: &Edit| match edit {
+            Edit::Remove => removed += 1,
+            Edit::Insert => inserted += 1,
+            Edit::Keep => {}
+        };
+        count(&edit);
+        while let Some(edit) = edits.next_if(|edit| !matches!(edit, Edit::Keep)) {
+            count(&edit);
+        }
+        let paired = removed.min(inserted);
+        for i in 0..paired {
+            path.push((index + i) as u32);
+            collect_operations(path, &old_middle[old_index + i], &new_middle[new_index + i], operations);
+            path.pop();
+        }
+        for i in paired..inserted {
+            let value = new_middle[new_index + i].clone();
+            operations.push(PatchOperation::Insert { path: path.clone(), index: (index + i) as u32, value });
+        }
+        for _ in paired..removed {
+            operations.push(PatchOperation::Remove { path: path.clone(), index: (index + paired) as u32 });
+        }
+        index += inserted;
+        old_index += removed;
+        new_index += inserted;
+    }
+}
+
+// Myers' shortest edit script, bounded so that heavily rewritten arrays fall back to replacement.
+fn edit_script(old: &[Value], new: &[Value]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max_distance = (old.len() + new.len()).min(MAX_EDIT_DISTANCE) as isize;
+    let offset = max_distance + 1;
+    let mut furthest = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    for distance in 0..=max_distance {
+        trace.push(furthest.clone());
+        for k in (-distance..=distance).step_by(2) {
+            let down = k == -distance
+                || (k != distance && furthest[(offset + k - 1) as usize] < furthest[(offset + k + 1) as usize]);
+            let mut x = if down {
+                furthest[(offset + k + 1) as usize]
+            } else {
+                furthest[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize].same_as(&new[y as usize]) {
+                x += 1;
+                y += 1;
+            }
+            furthest[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, offset, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], offset: isize, n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (distance, furthest) in trace.iter().enumerate().skip(1).rev() {
+        let distance = distance as isize;
+        let k = x - y;
+        let previous_k = if k == -distance
+            || (k != distance && furthest[(offset + k - 1) as usize] < furthest[(offset + k + 1) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest[(offset + previous_k) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == previous_x { Edit::Insert } else { Edit::Remove });
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.extend((0..x).map(|_| Edit::Keep));
+    edits.reverse();
+    edits
+}
+
+fn apply_operation(tree: &mut Value, operation: &PatchOperation) -> std::io::Result<()> {
+    match operation {
+        PatchOperation::Set { path, value } => {
+            *navigate(tree, path)? = value.clone();
+        },
+        PatchOperation::Insert { path, index, value } => {
+            let items = navigate_to_array(tree, path)?;
+            check(*index as usize <= items.len(), "patch inserts past the end of an array")?;
+            items.insert(*index as usize, value.clone());
+        },
+        PatchOperation::Remove { path, index } => {
+            let items = navigate_to_array(tree, path)?;
+            check((*index as usize) < items.len(), "patch removes past the end of an array")?;
+            items.remove(*index as usize);
+        }
+    }
+    Ok(())
+}
+
+fn navigate<'a>(node: &'a mut Value, path: &[u32]) -> std::io::Result<&'a mut Value> {
+    match path.split_first() {
+        None => Ok(node),
+        Some((index, rest)) => {
+            let child = match node {
+                Value::Struct(items) | Value::Array(items) => items.get_mut(*index as usize),
+                Value::Tagged(_, body) => return navigate(body, path),
+                _ => None
+            };
+            navigate(child.ok_or_else(missing_path_error)?, rest)
+        }
+    }
+}
+
+fn navigate_to_array<'a>(node: &'a mut Value, path: &[u32]) -> std::io::Result<&'a mut Vec<Value>> {
+    match navigate(node, path)? {
+        Value::Array(items) => Ok(items),
+        _ => Err(missing_path_error())
+    }
+}
+
+fn missing_path_error() -> std::io::Error {
+    std::io::Error::other("patch path does not exist")
+}
+
+fn layout_error() -> std::io::Error {
+    std::io::Error::other("patched value does not match the layout")
+}
+
+macro_rules! replay_primitives {
+    ($($reflect:ident, $primitive:ty, $variant:ident;)*) => {$(
+        fn $reflect(&mut self, data: &mut $primitive) -> std::io::Result<()> {
+            self.position += std::mem::size_of::<$primitive>() as u64;
+            if self.muted > 0 {
+                return Ok(());
+            }
+            match self.next()? {
+                Value::$variant(value) => {
+                    *data = value;
+                    Ok(())
+                },
+                _ => Err(layout_error())
+            }
+        }
+    )*};
+}
+
+// Reads a value tree recorded by DiffReflector back into a Reflectable.
+struct ReplayReflector {
+    position: u64,
+    version: u32,
+    muted: usize,
+    sources: Vec<std::vec::IntoIter<Value>>
+}
+
+impl ReplayReflector {
+    fn new(fields: Vec<Value>) -> Self {
+        Self {
+            position: 0,
+            version: u32::MAX,
+            muted: 0,
+            sources: vec![fields.into_iter()]
+        }
+    }
+
+    fn next(&mut self) -> std::io::Result<Value> {
+        self.sources.last_mut()
+            .and_then(|source| source.next())
+            .ok_or_else(layout_error)
+    }
+
+    fn feed<F>(&mut self, values: Vec<Value>, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.sources.push(values.into_iter());
+        let result = body(self);
+        self.sources.pop();
+        result
+    }
+
+    fn muted<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.muted += 1;
+        let result = body(self);
+        self.muted -= 1;
+        result
+    }
+
+    fn replay_size(&mut self, size: usize, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        let mut size = size;
+        self.muted(|r| match size_policy {
+            Some(size_policy) => reflect_size_ext(r, &mut size, size_policy),
+            None => reflect_size(r, &mut size)
+        })
+    }
+
+    fn replay_array<T: Default + Clone, F>(
+        &mut self,
+        data: &mut Vec<T>,
+        size_policy: Option<SizePolicy>,
+        mut element: F
+    ) -> std::io::Result<()>
+        where F: FnMut(&mut Self, &mut T) -> std::io::Result<()>
+    {
+        let items = match self.next()? {
+            Value::Array(items) => items,
+            _ => return Err(layout_error())
+        };
+        self.replay_size(items.len(), size_policy)?;
+        data.clear();
+        data.resize(items.len(), T::default());
+        for (item, slot) in items.into_iter().zip(data.iter_mut()) {
+            self.feed(vec![item], |r| element(r, slot))?;
+        }
+        Ok(())
+    }
+
+    fn replay_string(&mut self, string: &mut String, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        *string = match self.next()? {
+            Value::String(value) => value,
+            _ => return Err(layout_error())
+        };
+        self.replay_size(string.len(), size_policy)?;
+        self.position += string.len() as u64;
+        Ok(())
+    }
+
+    fn replay_packed_bools(
+        &mut self,
+        data: &mut Vec<bool>,
+        bit_order: BitOrder,
+        size_policy: Option<SizePolicy>
+    ) -> std::io::Result<()> {
+        *data = match self.next()? {
+            Value::Array(items) => items.iter().map(|item| item != &Value::U8(0)).collect(),
+            _ => return Err(layout_error())
+        };
+        self.replay_size(data.len(), size_policy)?;
+        self.muted(|r| reflect_packed_bools(r, data, bit_order))
+    }
+
+    fn replay_entry<K: Reflectable, V: Reflectable>(&mut self, entry: &mut (K, V)) -> std::io::Result<()> {
+        match self.next()? {
+            Value::Struct(fields) => self.feed(fields, |r| {
+                r.reflect_composite(&mut entry.0)?;
+                r.reflect_composite(&mut entry.1)
+            }),
+            _ => Err(layout_error())
+        }
+    }
+
+    fn replay_section<F>(&mut self, overhead: u64, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let outer_position = self.position;
+        self.position = 0;
+        let result = body(self);
+        let length = self.position;
+        self.position = outer_position + overhead;
+        result?;
+        self.replay_size(length as usize, None)?;
+        self.position += length;
+        Ok(())
+    }
+}
+
+impl SerializationReflector for ReplayReflector {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.replay_section(0, body)
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)?;
+        self.muted(|r| reflect_digest(r, algorithm, 0))
+    }
+
+    fn reflect_compressed<F>(&mut self, _codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.replay_section(0, body)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.replay_section((crate::encryption::NONCE_SIZE + crate::encryption::TAG_SIZE) as u64, body)
+    }
+
+    with_primitives!(replay_primitives);
+
+    fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        *string = match self.next()? {
+            Value::String(value) => value,
+            _ => return Err(layout_error())
+        };
+        self.position += cp866_size(string);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
+        *string = match self.next()? {
+            Value::String(value) => value,
+            _ => return Err(layout_error())
+        };
+        self.position += cp866_size(string);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        *string = match self.next()? {
+            Value::String(value) => value,
+            _ => return Err(layout_error())
+        };
+        self.position += length as u64;
+        Ok(())
+    }
+
+    fn reflect_char(&mut self, data: &mut char, encoding: CharEncoding) -> std::io::Result<()> {
+        *data = match self.next()? {
+            Value::U32(code) => std::char::from_u32(code).ok_or_else(layout_error)?,
+            _ => return Err(layout_error())
+        };
+        self.position += match encoding {
+            CharEncoding::Utf32 => 4,
+            CharEncoding::Utf8 => data.len_utf8() as u64
+        };
+        Ok(())
+    }
+
+    delegate_arrays!(replay_array);
+
+    fn reflect_char_array(&mut self, data: &mut Vec<char>, encoding: CharEncoding) -> std::io::Result<()> {
+        self.replay_array(data, None, |r, element| r.reflect_char(element, encoding))
+    }
+
+    fn reflect_char_array_ext(
+        &mut self,
+        data: &mut Vec<char>,
+        encoding: CharEncoding,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.replay_array(data, Some(size_policy), |r, element| r.reflect_char(element, encoding))
+    }
+
+    fn reflect_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.replay_string(string, None)
+    }
+
+    fn reflect_string_ext(&mut self, string: &mut String, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.replay_string(string, Some(size_policy))
+    }
+
+    fn reflect_packed_bool_array(&mut self, data: &mut Vec<bool>, bit_order: BitOrder) -> std::io::Result<()> {
+        self.replay_packed_bools(data, bit_order, None)
+    }
+
+    fn reflect_packed_bool_array_ext(
+        &mut self,
+        data: &mut Vec<bool>,
+        bit_order: BitOrder,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.replay_packed_bools(data, bit_order, Some(size_policy))
+    }
+
+    fn reflect_composite<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        match self.next()? {
+            Value::Struct(fields) => self.feed(fields, |r| composite.reflect(r)),
+            _ => Err(layout_error())
+        }
+    }
+
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_composite(composite))
+    }
+
+    fn reflect_array_of_composites<R: Reflectable>(&mut self, data: &mut Vec<R>) -> std::io::Result<()> {
+        self.replay_array(data, None, |r, element| r.reflect_composite(element))
+    }
+
+    fn reflect_array_of_composites_ext<R: Reflectable>(
+        &mut self,
+        data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.replay_array(data, Some(size_policy), |r, element| r.reflect_composite(element))
+    }
+
+    fn reflect_tagged_composite<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        let (tag, body) = match self.next()? {
+            Value::Tagged(tag, body) => (tag, *body),
+            _ => return Err(layout_error())
+        };
+        let mut fields = match body {
+            Value::Struct(fields) => fields,
+            _ => return Err(layout_error())
+        };
+        fields.insert(0, match R::get_size_policy() {
+            SizePolicy::U8 => Value::U8(tag as u8),
+            SizePolicy::U16 => Value::U16(tag as u16),
+            SizePolicy::U32 => Value::U32(tag as u32),
+            SizePolicy::U64 => Value::U64(tag)
+        });
+        self.feed(fields, |r| composite.reflect(r))
+    }
+
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_tagged_composite(composite))
+    }
+
+    fn reflect_array_of_tagged_composites<R: TaggedReflectable>(&mut self, data: &mut Vec<R>) -> std::io::Result<()> {
+        self.replay_array(data, None, |r, element| r.reflect_tagged_composite(element))
+    }
+
+    fn reflect_array_of_tagged_composites_ext<R: TaggedReflectable>(
+        &mut self,
+        data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.replay_array(data, Some(size_policy), |r, element| r.reflect_tagged_composite(element))
+    }
+
+    fn reflect_map<M: ReflectableMap>(&mut self, map: &mut M) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+        self.replay_array(&mut entries, None, |r, entry| r.replay_entry(entry))?;
+        fill_map(self, map, entries)
+    }
+
+    fn reflect_map_ext<M: ReflectableMap>(&mut self, map: &mut M, size_policy: SizePolicy) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+        self.replay_array(&mut entries, Some(size_policy), |r, entry| r.replay_entry(entry))?;
+        fill_map(self, map, entries)
+    }
+
+    fn reflect_set<S: ReflectableSet>(&mut self, set: &mut S) -> std::io::Result<()> {
+        let mut items = Vec::new();
+        self.replay_array(&mut items, None, |r, item| r.reflect_composite(item))?;
+        fill_set(self, set, items)
+    }
+
+    fn reflect_set_ext<S: ReflectableSet>(&mut self, set: &mut S, size_policy: SizePolicy) -> std::io::Result<()> {
+        let mut items = Vec::new();
+        self.replay_array(&mut items, Some(size_policy), |r, item| r.reflect_composite(item))?;
+        fill_set(self, set, items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{
+        Reflectable, TaggedReflectable, SerializationReflector, ReflectError, SizePolicy, Endianness,
+        Value, Patch, PatchOperation, make_patch, apply_patch
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    enum Shape {
+        #[default]
+        Empty,
+        Circle(f32),
+        Polygon(Vec<i16>)
+    }
+
+    impl TaggedReflectable for Shape {
+        fn get_size_policy() -> SizePolicy {
+            SizePolicy::U16
+        }
+
+        fn get_tag(&self) -> u64 {
+            match self {
+                Shape::Empty => 0,
+                Shape::Circle(_) => 1,
+                Shape::Polygon(_) => 2
+            }
+        }
+
+        fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            tag: u64,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            if self.get_tag() != tag {
+                *self = match tag {
+                    1 => Shape::Circle(0.0),
+                    2 => Shape::Polygon(Vec::new()),
+                    _ => Shape::Empty
+                };
+            }
+            match self {
+                Shape::Empty => Ok(()),
+                Shape::Circle(radius) => reflector.reflect_f32(radius),
+                Shape::Polygon(points) => reflector.reflect_i16_array(points)
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Level {
+        title: String,
+        heights: Vec<u32>,
+        shapes: Vec<Shape>,
+        spawns: BTreeMap<String, u16>
+    }
+
+    impl Reflectable for Level {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string(&mut self.title)?;
+            reflector.reflect_u32_array_ext(&mut self.heights, SizePolicy::U32)?;
+            reflector.reflect_array_of_tagged_composites(&mut self.shapes)?;
+            reflector.reflect_map(&mut self.spawns)
+        }
+    }
+
+    fn level() -> Level {
+        Level {
+            title: "caves".to_string(),
+            heights: (0..1000).collect(),
+            shapes: vec![Shape::Empty, Shape::Circle(2.0), Shape::Polygon(vec![1, 2, 3])],
+            spawns: vec![("boss".to_string(), 7), ("player".to_string(), 1)].into_iter().collect()
+        }
+    }
+
+    #[test]
+    fn test_patch_round_trip() {
+        let old = level();
+        let mut new = level();
+        new.title = "deep caves".to_string();
+        new.heights.insert(500, 42);
+        new.heights.remove(10);
+        new.shapes[0] = Shape::Circle(1.0);
+        if let Shape::Polygon(points) = &mut new.shapes[2] {
+            points.push(4);
+        }
+        new.spawns.insert("chest".to_string(), 3);
+        new.spawns.remove("boss");
+
+        let mut patch = make_patch(&old, &new).unwrap();
+        assert!(patch.operations.contains(&PatchOperation::Insert { path: vec![1], index: 499, value: Value::U32(42) }));
+        assert!(patch.operations.contains(&PatchOperation::Remove { path: vec![1], index: 10 }));
+
+        let mut bytes = Vec::new();
+        patch.serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+        assert!(bytes.len() < 200);
+        let patch1 = Patch::deserialize(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(patch, patch1);
+
+        let mut patched = old.clone();
+        apply_patch(&mut patched, &patch1).unwrap();
+        assert_eq!(patched, new);
+        assert_eq!(make_patch(&new, &new).unwrap().operations, vec![]);
+
+        let mut rewritten = level();
+        rewritten.heights.reverse();
+        let patch = make_patch(&old, &rewritten).unwrap();
+        let mut patched = old.clone();
+        apply_patch(&mut patched, &patch).unwrap();
+        assert_eq!(patched, rewritten);
+    }
+
+    #[test]
+    fn test_patch_refuses_mismatched_base() {
+        let old = level();
+        let mut new = level();
+        new.heights[3] = 0;
+        let patch = make_patch(&old, &new).unwrap();
+
+        let mut other = level();
+        other.title = "tunnels".to_string();
+        let error = apply_patch(&mut other, &patch).unwrap_err();
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::PatchBaseMismatch { expected: patch.base_hash, actual: other.content_hash().unwrap() })
+        );
+        assert_eq!(other.title, "tunnels");
+    }
+}
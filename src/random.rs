@@ -0,0 +1,529 @@
+use std::io::Cursor;
+use rand_core::RngCore;
+use crate::{
+    SerializationReflector, Reflectable, TaggedReflectable, ReflectableMap, ReflectableSet, ReflectorOptions,
+    ChecksumAlgorithm, CompressionCodec, SizePolicy, CharEncoding, BitOrder, Endianness
+};
+
+macro_rules! random_primitives {
+    ($($reflect:ident, $primitive:ty, $source:ident;)*) => {$(
+        fn $reflect(&mut self, data: &mut $primitive) -> std::io::Result<()> {
+            *data = self.rng.$source() as $primitive;
+            Ok(())
+        }
+    )*};
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RandomFillLimits {
+    pub max_length: usize,
+    pub max_depth: usize
+}
+
+impl Default for RandomFillLimits {
+    fn default() -> Self {
+        Self {
+            max_length: 8,
+            max_depth: 4
+        }
+    }
+}
+
+/// Fills whatever it reflects with random data. Tagged composites only get tags from
+/// `TaggedReflectable::variant_tags`, and past `max_depth` nested arrays come out empty
+/// and tagged composites keep their default variant, so recursive types stay finite.
+/// Types that validate plain integers while reading, like the codes in `Schema`, will mostly
+/// come out as errors and need a generator of their own.
+pub struct RandomFillReflector<R: RngCore> {
+    rng: R,
+    limits: RandomFillLimits,
+    depth: usize,
+    version: u32
+}
+
+impl<R: RngCore> RandomFillReflector<R> {
+    pub fn new(rng: R, limits: RandomFillLimits) -> Self {
+        Self {
+            rng,
+            limits,
+            depth: 0,
+            version: u32::MAX
+        }
+    }
+
+    pub fn fill<T: Reflectable>(&mut self) -> std::io::Result<T> {
+        let mut data = T::default();
+        data.reflect(self)?;
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.rng
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.rng.next_u64() % (bound as u64 + 1)) as usize
+    }
+
+    fn random_length(&mut self, size_policy: Option<SizePolicy>) -> usize {
+        if self.depth >= self.limits.max_depth {
+            return 0;
+        }
+        let limit = match size_policy {
+            Some(SizePolicy::U8) => 0xFF,
+            Some(SizePolicy::U16) => 0xFFFF,
+            _ => usize::MAX
+        };
+        self.below(self.limits.max_length.min(limit))
+    }
+
+    fn nested<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.depth += 1;
+        let result = body(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn fill_array<T: Default, F>(
+        &mut self,
+        data: &mut Vec<T>,
+        size_policy: Option<SizePolicy>,
+        mut fill: F
+    ) -> std::io::Result<()>
+        where F: FnMut(&mut Self, &mut T) -> std::io::Result<()>
+    {
+        let length = self.random_length(size_policy);
+        data.clear();
+        self.nested(|r| {
+            for _ in 0..length {
+                let mut item = T::default();
+                fill(r, &mut item)?;
+                data.push(item);
+            }
+            Ok(())
+        })
+    }
+
+    fn random_char(&mut self) -> char {
+        loop {
+            if let Some(ch) = std::char::from_u32(self.rng.next_u32() % 0x110000) {
+                return ch;
+            }
+        }
+    }
+
+    fn fill_string(&mut self, string: &mut String, size_policy: Option<SizePolicy>) {
+        let length = self.random_length(size_policy);
+        string.clear();
+        loop {
+            let ch = self.random_char();
+            if string.len() + ch.len_utf8() > length {
+                break;
+            }
+            string.push(ch);
+        }
+    }
+
+    // Printable ascii only, as the writers replace anything else with a space.
+    fn fill_cp866(&mut self, string: &mut String, max_length: usize) {
+        let length = self.below(self.limits.max_length.min(max_length));
+        *string = (0..length).map(|_| (b' ' + (self.rng.next_u32() % 95) as u8) as char).collect();
+    }
+
+    fn fill_map<M: ReflectableMap>(&mut self, map: &mut M, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+        self.fill_array(&mut entries, size_policy, |r, (key, value): &mut (M::Key, M::Value)| {
+            r.reflect_composite(key)?;
+            r.reflect_composite(value)
+        })?;
+        map.clear_entries();
+        for (key, value) in entries {
+            map.insert_entry(key, value);
+        }
+        Ok(())
+    }
+
+    fn fill_set<S: ReflectableSet>(&mut self, set: &mut S, size_policy: Option<SizePolicy>) -> std::io::Result<()> {
+        let mut items = Vec::new();
+        self.fill_array(&mut items, size_policy, |r, item: &mut S::Item| r.reflect_composite(item))?;
+        set.clear_items();
+        for item in items {
+            set.insert_item(item);
+        }
+        Ok(())
+    }
+}
+
+impl<R: RngCore> SerializationReflector for RandomFillReflector<R> {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> u64 {
+        0
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)
+    }
+
+    fn reflect_checksummed<F>(&mut self, _algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)
+    }
+
+    fn reflect_compressed<F>(&mut self, _codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        body(self)
+    }
+
+    random_primitives! {
+        reflect_u8, u8, next_u32;
+        reflect_u16, u16, next_u32;
+        reflect_u32, u32, next_u32;
+        reflect_u64, u64, next_u64;
+        reflect_i8, i8, next_u32;
+        reflect_i16, i16, next_u32;
+        reflect_i32, i32, next_u32;
+        reflect_i64, i64, next_u64;
+    }
+
+    fn reflect_u128(&mut self, data: &mut u128) -> std::io::Result<()> {
+        *data = (self.rng.next_u64() as u128) << 64 | self.rng.next_u64() as u128;
+        Ok(())
+    }
+
+    fn reflect_i128(&mut self, data: &mut i128) -> std::io::Result<()> {
+        let mut bits = 0;
+        self.reflect_u128(&mut bits)?;
+        *data = bits as i128;
+        Ok(())
+    }
+
+    fn reflect_f32(&mut self, data: &mut f32) -> std::io::Result<()> {
+        *data = f32::from_bits(self.rng.next_u32());
+        Ok(())
+    }
+
+    fn reflect_f64(&mut self, data: &mut f64) -> std::io::Result<()> {
+        *data = f64::from_bits(self.rng.next_u64());
+        Ok(())
+    }
+
+    fn reflect_cp866_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.fill_cp866(string, 0xFF);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.fill_cp866(string, usize::MAX);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring_ext(&mut self, length: usize, string: &mut String) -> std::io::Result<()> {
+        self.fill_cp866(string, length);
+        Ok(())
+    }
+
+    fn reflect_char(&mut self, data: &mut char, _encoding: CharEncoding) -> std::io::Result<()> {
+        *data = self.random_char();
+        Ok(())
+    }
+
+    fn reflect_magic(&mut self, _magic: &[u8]) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    delegate_arrays!(fill_array);
+
+    fn reflect_char_array(&mut self, data: &mut Vec<char>, encoding: CharEncoding) -> std::io::Result<()> {
+        self.fill_array(data, None, |r, item| r.reflect_char(item, encoding))
+    }
+
+    fn reflect_char_array_ext(
+        &mut self,
+        data: &mut Vec<char>,
+        encoding: CharEncoding,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.fill_array(data, Some(size_policy), |r, item| r.reflect_char(item, encoding))
+    }
+
+    fn reflect_string(&mut self, string: &mut String) -> std::io::Result<()> {
+        self.fill_string(string, None);
+        Ok(())
+    }
+
+    fn reflect_string_ext(&mut self, string: &mut String, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.fill_string(string, Some(size_policy));
+        Ok(())
+    }
+
+    fn reflect_packed_bool_array(&mut self, data: &mut Vec<bool>, _bit_order: BitOrder) -> std::io::Result<()> {
+        self.fill_array(data, None, |r, item| r.reflect_bool(item))
+    }
+
+    fn reflect_packed_bool_array_ext(
+        &mut self,
+        data: &mut Vec<bool>,
+        _bit_order: BitOrder,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.fill_array(data, Some(size_policy), |r, item| r.reflect_bool(item))
+    }
+
+    fn reflect_composite<T: Reflectable>(&mut self, composite: &mut T) -> std::io::Result<()> {
+        self.nested(|r| composite.reflect(r))
+    }
+
+    fn reflect_composite_framed<T: Reflectable>(&mut self, composite: &mut T) -> std::io::Result<()> {
+        self.reflect_composite(composite)
+    }
+
+    fn reflect_tagged_composite<T: TaggedReflectable>(&mut self, composite: &mut T) -> std::io::Result<()> {
+        let tags = T::variant_tags();
+        let tag = if tags.is_empty() || self.depth >= self.limits.max_depth {
+            T::default().get_tag()
+        } else {
+            tags[self.below(tags.len() - 1)]
+        };
+        self.nested(|r| composite.reflect_tagged(tag, r))
+    }
+
+    fn reflect_tagged_composite_framed<T: TaggedReflectable>(&mut self, composite: &mut T) -> std::io::Result<()> {
+        self.reflect_tagged_composite(composite)
+    }
+
+    fn reflect_array_of_composites<T: Reflectable>(&mut self, data: &mut Vec<T>) -> std::io::Result<()> {
+        self.fill_array(data, None, |r, item| r.reflect_composite(item))
+    }
+
+    fn reflect_array_of_composites_ext<T: Reflectable>(
+        &mut self,
+        data: &mut Vec<T>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.fill_array(data, Some(size_policy), |r, item| r.reflect_composite(item))
+    }
+
+    fn reflect_array_of_tagged_composites<T: TaggedReflectable>(&mut self, data: &mut Vec<T>) -> std::io::Result<()> {
+        self.fill_array(data, None, |r, item| r.reflect_tagged_composite(item))
+    }
+
+    fn reflect_array_of_tagged_composites_ext<T: TaggedReflectable>(
+        &mut self,
+        data: &mut Vec<T>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.fill_array(data, Some(size_policy), |r, item| r.reflect_tagged_composite(item))
+    }
+
+    fn reflect_map<M: ReflectableMap>(&mut self, map: &mut M) -> std::io::Result<()> {
+        self.fill_map(map, None)
+    }
+
+    fn reflect_map_ext<M: ReflectableMap>(&mut self, map: &mut M, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.fill_map(map, Some(size_policy))
+    }
+
+    fn reflect_set<S: ReflectableSet>(&mut self, set: &mut S) -> std::io::Result<()> {
+        self.fill_set(set, None)
+    }
+
+    fn reflect_set_ext<S: ReflectableSet>(&mut self, set: &mut S, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.fill_set(set, Some(size_policy))
+    }
+}
+
+/// Writes `data` with both endiannesses, reads it back and checks that the whole stream was consumed
+/// and that the result has the same `content_hash`, which sees through compression and encryption.
+pub fn check_round_trip<T: Reflectable>(data: &T) -> std::io::Result<()> {
+    let expected = data.clone().content_hash()?;
+    for endianness in [Endianness::LittleEndian, Endianness::BigEndian].iter() {
+        let mut bytes = Vec::new();
+        data.clone().serialize(&mut bytes, *endianness)?;
+        let mut stream = Cursor::new(&bytes);
+        let mut read = T::deserialize_ext(&mut stream, *endianness, ReflectorOptions::default())?;
+        if stream.position() != bytes.len() as u64 {
+            return Err(std::io::Error::other(format!(
+                "{:?} round trip left {} of {} bytes unread",
+                endianness,
+                bytes.len() as u64 - stream.position(),
+                bytes.len()
+            )));
+        }
+        if read.content_hash()? != expected {
+            return Err(std::io::Error::other(format!("{:?} round trip changed the data", endianness)));
+        }
+    }
+    Ok(())
+}
+
+pub fn check_random_round_trips<T: Reflectable, R: RngCore>(
+    rng: R,
+    limits: RandomFillLimits,
+    iterations: usize
+) -> std::io::Result<()> {
+    let mut reflector = RandomFillReflector::new(rng, limits);
+    for _ in 0..iterations {
+        check_round_trip(&reflector.fill::<T>()?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+    use rand::{SeedableRng, rngs::StdRng};
+    use crate::{
+        Reflectable, SerializationReflector, SizePolicy, CharEncoding, BitOrder, ChecksumAlgorithm, CompressionCodec,
+        Value, Patch, Endianness, ReflectorOptions, RandomFillReflector, RandomFillLimits, check_round_trip,
+        check_random_round_trips
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Record {
+        id: u64,
+        ratio: f32,
+        name: String,
+        short_name: String,
+        label: String,
+        title: String,
+        initials: Vec<char>,
+        flags: Vec<bool>,
+        samples: Vec<i16>,
+        tags: HashSet<u32>,
+        children: BTreeMap<u8, Record>,
+        note: String,
+        code: String,
+        revision: u32
+    }
+
+    impl Reflectable for Record {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_magic(b"REC")?;
+            reflector.reflect_u64(&mut self.id)?;
+            reflector.reflect_f16(&mut self.ratio)?;
+            reflector.reflect_string(&mut self.name)?;
+            reflector.reflect_string_ext(&mut self.short_name, SizePolicy::U8)?;
+            reflector.reflect_cp866_string(&mut self.label)?;
+            reflector.reflect_cp866_zstring(&mut self.title)?;
+            reflector.reflect_char_array_ext(&mut self.initials, CharEncoding::Utf8, SizePolicy::U16)?;
+            reflector.reflect_packed_bool_array(&mut self.flags, BitOrder::LsbFirst)?;
+            reflector.align(4)?;
+            reflector.reflect_reserved(2, 0)?;
+            reflector.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| r.reflect_i16_array(&mut self.samples))?;
+            reflector.reflect_set(&mut self.tags)?;
+            reflector.reflect_compressed(CompressionCodec::Stored, |r| r.reflect_map(&mut self.children))?;
+            reflector.reflect_cp866_string(&mut self.note)?;
+            reflector.reflect_cp866_zstring_ext(12, &mut self.code)?;
+            reflector.reflect_since(2, &mut self.revision, 0)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Values(Vec<Value>);
+
+    impl Reflectable for Values {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_array_of_tagged_composites(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn test_random_fill_is_deterministic() {
+        let mut first = RandomFillReflector::new(StdRng::seed_from_u64(7), RandomFillLimits::default());
+        let mut second = RandomFillReflector::new(StdRng::seed_from_u64(7), RandomFillLimits::default());
+        for _ in 0..16 {
+            let mut a: Record = first.fill().unwrap();
+            let mut b: Record = second.fill().unwrap();
+            assert_eq!(a.content_hash().unwrap(), b.content_hash().unwrap());
+        }
+
+        let limits = RandomFillLimits { max_length: 3, max_depth: 2 };
+        let mut reflector = RandomFillReflector::new(StdRng::seed_from_u64(1), limits);
+        for _ in 0..64 {
+            let record: Record = reflector.fill().unwrap();
+            assert!(record.samples.len() <= 3 && record.name.len() <= 3 && record.label.len() <= 3);
+            assert!(record.children.values().all(|child| child.children.is_empty() && child.samples.is_empty()));
+        }
+    }
+
+    #[test]
+    fn test_random_round_trips() {
+        let limits = RandomFillLimits::default();
+        check_random_round_trips::<Record, _>(StdRng::seed_from_u64(11), limits, 200).unwrap();
+        check_random_round_trips::<Values, _>(StdRng::seed_from_u64(12), limits, 200).unwrap();
+        check_random_round_trips::<Patch, _>(StdRng::seed_from_u64(14), limits, 200).unwrap();
+    }
+
+    fn check_canonical_reencoding<T: Reflectable>(seed: u64) {
+        let canonical = ReflectorOptions::canonical();
+        let mut reflector = RandomFillReflector::new(StdRng::seed_from_u64(seed), RandomFillLimits::default());
+        for _ in 0..100 {
+            let mut data: T = reflector.fill().unwrap();
+            for &endianness in &[Endianness::BigEndian, Endianness::LittleEndian] {
+                let mut encoded = Vec::new();
+                data.serialize_ext(&mut encoded, endianness, canonical).unwrap();
+                let mut decoded = T::deserialize_ext(&mut &encoded[..], endianness, canonical).unwrap();
+                let mut reencoded = Vec::new();
+                decoded.serialize_ext(&mut reencoded, endianness, canonical).unwrap();
+                assert_eq!(encoded, reencoded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_reencoding() {
+        check_canonical_reencoding::<Record>(21);
+        check_canonical_reencoding::<Values>(22);
+    }
+
+    #[test]
+    fn test_round_trip_detects_lossy_types() {
+        #[derive(Clone, Default)]
+        struct Lossy(f32);
+
+        impl Reflectable for Lossy {
+            fn reflect<TSerializationReflector: SerializationReflector>(
+                &mut self,
+                reflector: &mut TSerializationReflector
+            ) -> std::io::Result<()> {
+                if reflector.is_reading() {
+                    reflector.reflect_f16(&mut self.0)
+                } else {
+                    reflector.reflect_f32(&mut self.0)
+                }
+            }
+        }
+
+        assert!(check_round_trip(&Lossy(0.1)).is_err());
+    }
+}
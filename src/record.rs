@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use crate::{Reflectable, Endianness, check};
+
+pub struct RecordWriter<TStream: Write, T: Reflectable> {
+    stream: TStream,
+    endianness: Endianness,
+    sync_marker: Vec<u8>,
+    marker: PhantomData<T>
+}
+
+impl<TStream: Write, T: Reflectable> RecordWriter<TStream, T> {
+    pub fn new(stream: TStream, endianness: Endianness) -> Self {
+        Self::with_sync_marker(stream, endianness, &[])
+    }
+
+    pub fn with_sync_marker(stream: TStream, endianness: Endianness, sync_marker: &[u8]) -> Self {
+        Self { stream, endianness, sync_marker: sync_marker.to_vec(), marker: PhantomData }
+    }
+
+    pub fn write(&mut self, record: &mut T) -> std::io::Result<()> {
+        let mut bytes = self.sync_marker.clone();
+        record.serialize(&mut bytes, self.endianness)?;
+        self.stream.write_all(&bytes)?;
+        self.stream.flush()
+    }
+
+    pub fn into_inner(self) -> TStream {
+        self.stream
+    }
+}
+
+struct Replay<'a, TStream: Read> {
+    pending: &'a mut VecDeque<u8>,
+    stream: &'a mut TStream,
+    consumed: Vec<u8>
+}
+
+impl<'a, TStream: Read> Read for Replay<'a, TStream> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.pending.pop_front() {
+                Some(byte) => {
+                    buffer[filled] = byte;
+                    filled += 1;
+                }
+                None => {
+                    let size_read = self.stream.read(&mut buffer[filled..])?;
+                    if size_read == 0 {
+                        break;
+                    }
+                    filled += size_read;
+                }
+            }
+        }
+        self.consumed.extend_from_slice(&buffer[..filled]);
+        Ok(filled)
+    }
+}
+
+pub struct RecordReader<TStream: Read, T: Reflectable> {
+    stream: TStream,
+    endianness: Endianness,
+    sync_marker: Vec<u8>,
+    pending: VecDeque<u8>,
+    resync: bool,
+    finished: bool,
+    marker: PhantomData<T>
+}
+
+impl<TStream: Read, T: Reflectable> RecordReader<TStream, T> {
+    pub fn new(stream: TStream, endianness: Endianness) -> Self {
+        Self::with_sync_marker(stream, endianness, &[])
+    }
+
+    pub fn with_sync_marker(stream: TStream, endianness: Endianness, sync_marker: &[u8]) -> Self {
+        Self {
+            stream,
+            endianness,
+            sync_marker: sync_marker.to_vec(),
+            pending: VecDeque::new(),
+            resync: false,
+            finished: false,
+            marker: PhantomData
+        }
+    }
+
+    pub fn into_inner(self) -> TStream {
+        self.stream
+    }
+
+    fn next_byte(&mut self) -> std::io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0u8];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    fn skip_to_sync_marker(&mut self) -> std::io::Result<bool> {
+        let mut window = VecDeque::with_capacity(self.sync_marker.len());
+        while window.len() < self.sync_marker.len() || !window.iter().eq(self.sync_marker.iter()) {
+            match self.next_byte()? {
+                Some(byte) => {
+                    if window.len() == self.sync_marker.len() {
+                        window.pop_front();
+                    }
+                    window.push_back(byte);
+                }
+                None => return Ok(false)
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_sync_marker(&mut self, first: u8) -> std::io::Result<()> {
+        let mut found = vec![first];
+        while found.len() < self.sync_marker.len() {
+            match self.next_byte()? {
+                Some(byte) => found.push(byte),
+                None => break
+            }
+        }
+        if found != self.sync_marker {
+            for byte in found.into_iter().skip(1).rev() {
+                self.pending.push_front(byte);
+            }
+            self.resync = true;
+            return check(false, "sync marker not found");
+        }
+        Ok(())
+    }
+
+    fn read_record(&mut self) -> Option<std::io::Result<T>> {
+        if self.resync {
+            match self.skip_to_sync_marker() {
+                Ok(true) => self.resync = false,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e))
+            }
+        } else {
+            match self.next_byte() {
+                Ok(Some(byte)) if self.sync_marker.is_empty() => self.pending.push_front(byte),
+                Ok(Some(byte)) => if let Err(e) = self.read_sync_marker(byte) {
+                    return Some(Err(e));
+                },
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e))
+            }
+        }
+
+        let mut replay = Replay {
+            pending: &mut self.pending,
+            stream: &mut self.stream,
+            consumed: Vec::new()
+        };
+        let result = T::deserialize(&mut replay, self.endianness);
+        if result.is_err() {
+            if self.sync_marker.is_empty() {
+                self.finished = true;
+            } else {
+                let consumed = replay.consumed;
+                for byte in consumed.into_iter().rev() {
+                    self.pending.push_front(byte);
+                }
+                self.resync = true;
+            }
+        }
+        Some(result)
+    }
+}
+
+impl<TStream: Read, T: Reflectable> Iterator for RecordReader<TStream, T> {
+    type Item = std::io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let record = self.read_record();
+        if record.is_none() {
+            self.finished = true;
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RecordWriter, RecordReader, Reflectable, SerializationReflector, Endianness};
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct LogEntry {
+        timestamp: u32,
+        message: String
+    }
+
+    impl Reflectable for LogEntry {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u32(&mut self.timestamp)?;
+            reflector.reflect_string(&mut self.message)
+        }
+    }
+
+    fn entry(timestamp: u32, message: &str) -> LogEntry {
+        LogEntry { timestamp, message: message.to_string() }
+    }
+
+    #[test]
+    fn test_clean_eof_and_truncation() {
+        let mut writer = RecordWriter::new(Vec::new(), Endianness::LittleEndian);
+        writer.write(&mut entry(1, "start")).unwrap();
+        writer.write(&mut entry(2, "stop")).unwrap();
+        let bytes = writer.into_inner();
+
+        let records: Vec<LogEntry> = RecordReader::new(&bytes[..], Endianness::LittleEndian)
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(records, vec![entry(1, "start"), entry(2, "stop")]);
+
+        let truncated = &bytes[..bytes.len() - 1];
+        let mut reader = RecordReader::<_, LogEntry>::new(truncated, Endianness::LittleEndian);
+        assert_eq!(reader.next().unwrap().unwrap(), entry(1, "start"));
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_resync_after_corruption() {
+        let sync = &[0xFA, 0xCE];
+        let mut writer = RecordWriter::with_sync_marker(Vec::new(), Endianness::BigEndian, sync);
+        writer.write(&mut entry(1, "a")).unwrap();
+        writer.write(&mut entry(2, "bb")).unwrap();
+        writer.write(&mut entry(3, "ccc")).unwrap();
+        let mut bytes = writer.into_inner();
+        assert_eq!(&bytes[..10], &[0xFA, 0xCE, 0, 0, 0, 1, 1, 1, b'a', 0xFA]);
+
+        bytes[16] = 0xFF;
+        bytes.insert(10, 0x00);
+        let mut reader = RecordReader::<_, LogEntry>::with_sync_marker(&bytes[..], Endianness::BigEndian, sync);
+        assert_eq!(reader.next().unwrap().unwrap(), entry(1, "a"));
+        assert!(reader.next().unwrap().is_err());
+        assert_eq!(reader.next().unwrap().unwrap(), entry(3, "ccc"));
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,789 @@
+use std::any::type_name;
+use crate::{
+    SerializationReflector, Reflectable, TaggedReflectable, ReflectableMap, ReflectableSet,
+    ChecksumAlgorithm, CompressionCodec, SizePolicy, CharEncoding, BitOrder, Endianness, compression
+};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SizeEncoding {
+    Tagged,
+    Fixed(SizePolicy)
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub enum Layout {
+    #[default]
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F16,
+    F32,
+    F64,
+    Bool,
+    Char(CharEncoding),
+    String(SizeEncoding),
+    Cp866String,
+    Cp866ZString,
+    Cp866FixedZString(u64),
+    Array { size: SizeEncoding, element: Box<Layout> },
+    PackedBools { size: SizeEncoding, order: BitOrder },
+    Struct { name: String, fields: Vec<Layout> },
+    Tagged { name: String, tag: SizePolicy, variants: Vec<Variant> },
+    Recursive(String),
+    Magic(Vec<u8>),
+    Reserved { length: u64, byte: u8 },
+    Align(u64),
+    Frame(Vec<Layout>),
+    Checksummed { algorithm: ChecksumAlgorithm, fields: Vec<Layout> },
+    Compressed { codec: CompressionCodec, fields: Vec<Layout> },
+    Encrypted(Vec<Layout>)
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Variant {
+    pub tag: u64,
+    pub fields: Vec<Layout>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Schema {
+    pub endianness: Endianness,
+    pub root: Layout
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self { endianness: Endianness::LittleEndian, root: Layout::default() }
+    }
+}
+
+impl Schema {
+    pub fn extract<T: Reflectable>(endianness: Endianness) -> std::io::Result<Self> {
+        let mut reflector = SchemaReflector::new();
+        let root = reflector.record_struct::<T, _>(T::schema_name(), |r| T::default().reflect(r))?;
+        Ok(Self { endianness, root })
+    }
+}
+
+impl Reflectable for Schema {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_magic(b"BSSC")?;
+        reflect_code(reflector, &mut self.endianness, &[Endianness::BigEndian, Endianness::LittleEndian])?;
+        reflector.reflect_tagged_composite(&mut self.root)
+    }
+}
+
+impl Reflectable for Variant {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u64(&mut self.tag)?;
+        reflector.reflect_array_of_tagged_composites(&mut self.fields)
+    }
+}
+
+const SIZE_POLICIES: [SizePolicy; 4] = [SizePolicy::U8, SizePolicy::U16, SizePolicy::U32, SizePolicy::U64];
+
+const SIZE_ENCODINGS: [SizeEncoding; 5] = [
+    SizeEncoding::Tagged,
+    SizeEncoding::Fixed(SizePolicy::U8),
+    SizeEncoding::Fixed(SizePolicy::U16),
+    SizeEncoding::Fixed(SizePolicy::U32),
+    SizeEncoding::Fixed(SizePolicy::U64)
+];
+
+fn reflect_code<R: SerializationReflector, T: Copy + PartialEq>(
+    r: &mut R,
+    value: &mut T,
+    values: &[T]
+) -> std::io::Result<()> {
+    let mut code = values.iter().position(|v| v == value).unwrap_or(0) as u8;
+    r.reflect_u8(&mut code)?;
+    *value = *values.get(code as usize)
+        .ok_or_else(|| std::io::Error::other("unknown code in schema"))?;
+    Ok(())
+}
+
+fn reflect_codec<R: SerializationReflector>(r: &mut R, codec: &mut CompressionCodec) -> std::io::Result<()> {
+    let mut id = compression::codec_id(*codec);
+    r.reflect_u8(&mut id)?;
+    *codec = compression::codec_from_id(id)
+        .ok_or_else(|| std::io::Error::other("compression codec is not enabled"))?;
+    Ok(())
+}
+
+impl TaggedReflectable for Layout {
+    fn get_size_policy() -> SizePolicy {
+        SizePolicy::U8
+    }
+
+    fn get_tag(&self) -> u64 {
+        match self {
+            Layout::U8 => 0,
+            Layout::U16 => 1,
+            Layout::U32 => 2,
+            Layout::U64 => 3,
+            Layout::U128 => 4,
+            Layout::I8 => 5,
+            Layout::I16 => 6,
+            Layout::I32 => 7,
+            Layout::I64 => 8,
+            Layout::I128 => 9,
+            Layout::F16 => 10,
+            Layout::F32 => 11,
+            Layout::F64 => 12,
+            Layout::Bool => 13,
+            Layout::Char(_) => 14,
+            Layout::String(_) => 15,
+            Layout::Cp866String => 16,
+            Layout::Cp866ZString => 17,
+            Layout::Cp866FixedZString(_) => 18,
+            Layout::Array { .. } => 19,
+            Layout::PackedBools { .. } => 20,
+            Layout::Struct { .. } => 21,
+            Layout::Tagged { .. } => 22,
+            Layout::Recursive(_) => 23,
+            Layout::Magic(_) => 24,
+            Layout::Reserved { .. } => 25,
+            Layout::Align(_) => 26,
+            Layout::Frame(_) => 27,
+            Layout::Checksummed { .. } => 28,
+            Layout::Compressed { .. } => 29,
+            Layout::Encrypted(_) => 30
+        }
+    }
+
+    fn variant_tags() -> Vec<u64> {
+        (0..31).collect()
+    }
+
+    fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        tag: u64,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        if self.get_tag() != tag {
+            *self = match tag {
+                0 => Layout::U8,
+                1 => Layout::U16,
+                2 => Layout::U32,
+                3 => Layout::U64,
+                4 => Layout::U128,
+                5 => Layout::I8,
+                6 => Layout::I16,
+                7 => Layout::I32,
+                8 => Layout::I64,
+                9 => Layout::I128,
+                10 => Layout::F16,
+                11 => Layout::F32,
+                12 => Layout::F64,
+                13 => Layout::Bool,
+                14 => Layout::Char(CharEncoding::Utf32),
+                15 => Layout::String(SizeEncoding::Tagged),
+                16 => Layout::Cp866String,
+                17 => Layout::Cp866ZString,
+                18 => Layout::Cp866FixedZString(0),
+                19 => Layout::Array { size: SizeEncoding::Tagged, element: Box::default() },
+                20 => Layout::PackedBools { size: SizeEncoding::Tagged, order: BitOrder::MsbFirst },
+                21 => Layout::Struct { name: String::new(), fields: Vec::new() },
+                22 => Layout::Tagged { name: String::new(), tag: SizePolicy::U8, variants: Vec::new() },
+                23 => Layout::Recursive(String::new()),
+                24 => Layout::Magic(Vec::new()),
+                25 => Layout::Reserved { length: 0, byte: 0 },
+                26 => Layout::Align(1),
+                27 => Layout::Frame(Vec::new()),
+                28 => Layout::Checksummed { algorithm: ChecksumAlgorithm::Crc32, fields: Vec::new() },
+                29 => Layout::Compressed { codec: CompressionCodec::Stored, fields: Vec::new() },
+                30 => Layout::Encrypted(Vec::new()),
+                _ => return Err(std::io::Error::other("unknown layout tag"))
+            };
+        }
+        match self {
+            Layout::U8 | Layout::U16 | Layout::U32 | Layout::U64 | Layout::U128 |
+            Layout::I8 | Layout::I16 | Layout::I32 | Layout::I64 | Layout::I128 |
+            Layout::F16 | Layout::F32 | Layout::F64 | Layout::Bool |
+            Layout::Cp866String | Layout::Cp866ZString => Ok(()),
+            Layout::Char(encoding) =>
+                reflect_code(reflector, encoding, &[CharEncoding::Utf32, CharEncoding::Utf8]),
+            Layout::String(size) => reflect_code(reflector, size, &SIZE_ENCODINGS),
+            Layout::Cp866FixedZString(length) => reflector.reflect_u64(length),
+            Layout::Array { size, element } => {
+                reflect_code(reflector, size, &SIZE_ENCODINGS)?;
+                reflector.reflect_tagged_composite(&mut **element)
+            },
+            Layout::PackedBools { size, order } => {
+                reflect_code(reflector, size, &SIZE_ENCODINGS)?;
+                reflect_code(reflector, order, &[BitOrder::MsbFirst, BitOrder::LsbFirst])
+            },
+            Layout::Struct { name, fields } => {
+                reflector.reflect_string(name)?;
+                reflector.reflect_array_of_tagged_composites(fields)
+            },
+            Layout::Tagged { name, tag, variants } => {
+                reflector.reflect_string(name)?;
+                reflect_code(reflector, tag, &SIZE_POLICIES)?;
+                reflector.reflect_array_of_composites(variants)
+            },
+            Layout::Recursive(name) => reflector.reflect_string(name),
+            Layout::Magic(bytes) => reflector.reflect_u8_array(bytes),
+            Layout::Reserved { length, byte } => {
+                reflector.reflect_u64(length)?;
+                reflector.reflect_u8(byte)
+            },
+            Layout::Align(alignment) => reflector.reflect_u64(alignment),
+            Layout::Frame(fields) | Layout::Encrypted(fields) => reflector.reflect_array_of_tagged_composites(fields),
+            Layout::Checksummed { algorithm, fields } => {
+                reflect_code(
+                    reflector,
+                    algorithm,
+                    &[ChecksumAlgorithm::Crc32, ChecksumAlgorithm::Adler32, ChecksumAlgorithm::XxHash64]
+                )?;
+                reflector.reflect_array_of_tagged_composites(fields)
+            },
+            Layout::Compressed { codec, fields } => {
+                reflect_codec(reflector, codec)?;
+                reflector.reflect_array_of_tagged_composites(fields)
+            }
+        }
+    }
+}
+
+macro_rules! record_primitives {
+    ($($reflect:ident, $primitive:ty, $layout:ident;)*) => {$(
+        fn $reflect(&mut self, _data: &mut $primitive) -> std::io::Result<()> {
+            self.push(Layout::$layout);
+            Ok(())
+        }
+    )*};
+}
+
+pub struct SchemaReflector {
+    version: u32,
+    groups: Vec<Vec<Layout>>,
+    // Types being recorded, keyed by std::any::type_name, with the name their layout is stored under
+    types: Vec<(&'static str, String)>,
+    anonymous: usize
+}
+
+impl SchemaReflector {
+    pub fn new() -> Self {
+        Self {
+            version: u32::MAX,
+            groups: vec![Vec::new()],
+            types: Vec::new(),
+            anonymous: 0
+        }
+    }
+
+    pub fn into_layouts(mut self) -> Vec<Layout> {
+        self.groups.pop().unwrap_or_default()
+    }
+
+    fn push(&mut self, layout: Layout) {
+        self.groups.last_mut().unwrap().push(layout);
+    }
+
+    fn group<F>(&mut self, body: F) -> std::io::Result<Vec<Layout>>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        self.groups.push(Vec::new());
+        let result = body(self);
+        let layouts = self.groups.pop().unwrap();
+        result.map(|_| layouts)
+    }
+
+    fn record_array<F>(&mut self, size: SizeEncoding, element: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let mut layouts = self.group(element)?;
+        let element = if layouts.len() == 1 {
+            layouts.pop().unwrap()
+        } else {
+            Layout::Struct { name: String::new(), fields: layouts }
+        };
+        self.push(Layout::Array { size, element: Box::new(element) });
+        Ok(())
+    }
+
+    fn record_elements<T: Default, F>(
+        &mut self,
+        _data: &mut Vec<T>,
+        size_policy: Option<SizePolicy>,
+        mut element: F
+    ) -> std::io::Result<()>
+        where F: FnMut(&mut Self, &mut T) -> std::io::Result<()>
+    {
+        let size = size_policy.map_or(SizeEncoding::Tagged, SizeEncoding::Fixed);
+        self.record_array(size, |r| element(r, &mut T::default()))
+    }
+
+    // A recursive type needs a name to refer back to, so anonymous ones are numbered in the order
+    // their recursion is found
+    fn recursion<T>(&mut self) -> Option<Layout> {
+        let index = self.types.iter().position(|(key, _)| *key == type_name::<T>())?;
+        if self.types[index].1.is_empty() {
+            self.types[index].1 = format!("#{}", self.anonymous);
+            self.anonymous += 1;
+        }
+        Some(Layout::Recursive(self.types[index].1.clone()))
+    }
+
+    fn record_struct<T, F>(&mut self, name: String, body: F) -> std::io::Result<Layout>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        if let Some(recursive) = self.recursion::<T>() {
+            return Ok(recursive);
+        }
+        self.types.push((type_name::<T>(), name));
+        let fields = self.group(body);
+        let (_, name) = self.types.pop().unwrap();
+        Ok(Layout::Struct { name, fields: fields? })
+    }
+
+    fn record_tagged<R: TaggedReflectable>(&mut self) -> std::io::Result<Layout> {
+        if let Some(recursive) = self.recursion::<R>() {
+            return Ok(recursive);
+        }
+        let tags = R::variant_tags();
+        if tags.is_empty() {
+            return Err(std::io::Error::other("tagged type does not list its variant tags"));
+        }
+        self.types.push((type_name::<R>(), R::schema_name()));
+        let variants = tags.into_iter()
+            .map(|tag| {
+                let fields = self.group(|r| R::default().reflect_tagged(tag, r))?;
+                Ok(Variant { tag, fields })
+            })
+            .collect::<std::io::Result<Vec<Variant>>>();
+        let (_, name) = self.types.pop().unwrap();
+        Ok(Layout::Tagged { name, tag: R::get_size_policy(), variants: variants? })
+    }
+
+    fn record_entry<K: Reflectable, V: Reflectable>(&mut self) -> std::io::Result<()> {
+        let entry = self.record_struct::<(K, V), _>(String::new(), |r| {
+            K::default().reflect(r)?;
+            V::default().reflect(r)
+        })?;
+        self.push(entry);
+        Ok(())
+    }
+}
+
+impl Default for SchemaReflector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerializationReflector for SchemaReflector {
+    fn is_reading(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> u64 {
+        0
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    fn reflect_frame<F>(&mut self, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let fields = self.group(body)?;
+        self.push(Layout::Frame(fields));
+        Ok(())
+    }
+
+    fn reflect_checksummed<F>(&mut self, algorithm: ChecksumAlgorithm, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let fields = self.group(body)?;
+        self.push(Layout::Checksummed { algorithm, fields });
+        Ok(())
+    }
+
+    fn reflect_compressed<F>(&mut self, codec: CompressionCodec, body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let fields = self.group(body)?;
+        self.push(Layout::Compressed { codec, fields });
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    fn reflect_encrypted<F>(&mut self, _key: &[u8; 32], body: F) -> std::io::Result<()>
+        where F: FnOnce(&mut Self) -> std::io::Result<()>
+    {
+        let fields = self.group(body)?;
+        self.push(Layout::Encrypted(fields));
+        Ok(())
+    }
+
+    with_primitives!(record_primitives);
+
+    record_primitives! {
+        reflect_f16, f32, F16;
+        reflect_bool, bool, Bool;
+    }
+
+    fn reflect_cp866_string(&mut self, _string: &mut String) -> std::io::Result<()> {
+        self.push(Layout::Cp866String);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring(&mut self, _string: &mut String) -> std::io::Result<()> {
+        self.push(Layout::Cp866ZString);
+        Ok(())
+    }
+
+    fn reflect_cp866_zstring_ext(&mut self, length: usize, _string: &mut String) -> std::io::Result<()> {
+        self.push(Layout::Cp866FixedZString(length as u64));
+        Ok(())
+    }
+
+    fn reflect_char(&mut self, _data: &mut char, encoding: CharEncoding) -> std::io::Result<()> {
+        self.push(Layout::Char(encoding));
+        Ok(())
+    }
+
+    delegate_arrays!(record_elements);
+
+    fn reflect_char_array(&mut self, _data: &mut Vec<char>, encoding: CharEncoding) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Tagged, |r| r.reflect_char(&mut '\0', encoding))
+    }
+
+    fn reflect_char_array_ext(
+        &mut self,
+        _data: &mut Vec<char>,
+        encoding: CharEncoding,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Fixed(size_policy), |r| r.reflect_char(&mut '\0', encoding))
+    }
+
+    fn reflect_string(&mut self, _string: &mut String) -> std::io::Result<()> {
+        self.push(Layout::String(SizeEncoding::Tagged));
+        Ok(())
+    }
+
+    fn reflect_string_ext(&mut self, _string: &mut String, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.push(Layout::String(SizeEncoding::Fixed(size_policy)));
+        Ok(())
+    }
+
+    fn reflect_packed_bool_array(&mut self, _data: &mut Vec<bool>, bit_order: BitOrder) -> std::io::Result<()> {
+        self.push(Layout::PackedBools { size: SizeEncoding::Tagged, order: bit_order });
+        Ok(())
+    }
+
+    fn reflect_packed_bool_array_ext(
+        &mut self,
+        _data: &mut Vec<bool>,
+        bit_order: BitOrder,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.push(Layout::PackedBools { size: SizeEncoding::Fixed(size_policy), order: bit_order });
+        Ok(())
+    }
+
+    fn reflect_padding(&mut self, length: usize) -> std::io::Result<()> {
+        self.push(Layout::Reserved { length: length as u64, byte: 0 });
+        Ok(())
+    }
+
+    fn align(&mut self, alignment: u64) -> std::io::Result<()> {
+        self.push(Layout::Align(alignment));
+        Ok(())
+    }
+
+    fn reflect_reserved(&mut self, length: usize, expected_byte: u8) -> std::io::Result<()> {
+        self.push(Layout::Reserved { length: length as u64, byte: expected_byte });
+        Ok(())
+    }
+
+    fn reflect_magic(&mut self, magic: &[u8]) -> std::io::Result<()> {
+        self.push(Layout::Magic(magic.to_vec()));
+        Ok(())
+    }
+
+    fn reflect_composite<R: Reflectable>(&mut self, _composite: &mut R) -> std::io::Result<()> {
+        let layout = self.record_struct::<R, _>(R::schema_name(), |r| R::default().reflect(r))?;
+        self.push(layout);
+        Ok(())
+    }
+
+    fn reflect_composite_framed<R: Reflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_composite(composite))
+    }
+
+    fn reflect_array_of_composites<R: Reflectable>(&mut self, _data: &mut Vec<R>) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Tagged, |r| r.reflect_composite(&mut R::default()))
+    }
+
+    fn reflect_array_of_composites_ext<R: Reflectable>(
+        &mut self,
+        _data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Fixed(size_policy), |r| r.reflect_composite(&mut R::default()))
+    }
+
+    fn reflect_tagged_composite<R: TaggedReflectable>(&mut self, _composite: &mut R) -> std::io::Result<()> {
+        let layout = self.record_tagged::<R>()?;
+        self.push(layout);
+        Ok(())
+    }
+
+    fn reflect_tagged_composite_framed<R: TaggedReflectable>(&mut self, composite: &mut R) -> std::io::Result<()> {
+        self.reflect_frame(|r| r.reflect_tagged_composite(composite))
+    }
+
+    fn reflect_array_of_tagged_composites<R: TaggedReflectable>(&mut self, _data: &mut Vec<R>) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Tagged, |r| r.reflect_tagged_composite(&mut R::default()))
+    }
+
+    fn reflect_array_of_tagged_composites_ext<R: TaggedReflectable>(
+        &mut self,
+        _data: &mut Vec<R>,
+        size_policy: SizePolicy
+    ) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Fixed(size_policy), |r| r.reflect_tagged_composite(&mut R::default()))
+    }
+
+    fn reflect_map<M: ReflectableMap>(&mut self, _map: &mut M) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Tagged, |r| r.record_entry::<M::Key, M::Value>())
+    }
+
+    fn reflect_map_ext<M: ReflectableMap>(&mut self, _map: &mut M, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Fixed(size_policy), |r| r.record_entry::<M::Key, M::Value>())
+    }
+
+    fn reflect_set<S: ReflectableSet>(&mut self, _set: &mut S) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Tagged, |r| S::Item::default().reflect(r))
+    }
+
+    fn reflect_set_ext<S: ReflectableSet>(&mut self, _set: &mut S, size_policy: SizePolicy) -> std::io::Result<()> {
+        self.record_array(SizeEncoding::Fixed(size_policy), |r| S::Item::default().reflect(r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::{
+        Reflectable, TaggedReflectable, SerializationReflector, SizePolicy, CharEncoding, ChecksumAlgorithm,
+        Endianness, Schema, Layout, Variant, SizeEncoding
+    };
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    enum Command {
+        #[default]
+        Stop,
+        Move(i16, i16),
+        Say(String)
+    }
+
+    impl TaggedReflectable for Command {
+        fn get_size_policy() -> SizePolicy {
+            SizePolicy::U8
+        }
+
+        fn get_tag(&self) -> u64 {
+            match self {
+                Command::Stop => 0,
+                Command::Move(_, _) => 1,
+                Command::Say(_) => 5
+            }
+        }
+
+        fn variant_tags() -> Vec<u64> {
+            vec![0, 1, 5]
+        }
+
+        fn schema_name() -> String {
+            "Command".to_string()
+        }
+
+        fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            tag: u64,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            if self.get_tag() != tag {
+                *self = match tag {
+                    1 => Command::Move(0, 0),
+                    5 => Command::Say(String::new()),
+                    _ => Command::Stop
+                };
+            }
+            match self {
+                Command::Stop => Ok(()),
+                Command::Move(x, y) => {
+                    reflector.reflect_i16(x)?;
+                    reflector.reflect_i16(y)
+                },
+                Command::Say(text) => reflector.reflect_cp866_string(text)
+            }
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Node {
+        name: String,
+        children: Vec<Node>
+    }
+
+    impl Reflectable for Node {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string_ext(&mut self.name, SizePolicy::U8)?;
+            reflector.reflect_array_of_composites(&mut self.children)
+        }
+    }
+
+    #[derive(Clone, Default, Debug, PartialEq)]
+    struct Script {
+        glyph: char,
+        commands: Vec<Command>,
+        labels: BTreeMap<u16, String>,
+        tree: Node,
+        visible: bool
+    }
+
+    impl Reflectable for Script {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_magic(b"SCRP")?;
+            reflector.reflect_char(&mut self.glyph, CharEncoding::Utf8)?;
+            reflector.reflect_array_of_tagged_composites_ext(&mut self.commands, SizePolicy::U16)?;
+            reflector.reflect_checksummed(ChecksumAlgorithm::Crc32, |r| r.reflect_map(&mut self.labels))?;
+            reflector.reflect_composite_framed(&mut self.tree)?;
+            reflector.align(4)?;
+            reflector.reflect_bool(&mut self.visible)
+        }
+
+        fn schema_name() -> String {
+            "Script".to_string()
+        }
+    }
+
+    #[test]
+    fn test_schema_extraction() {
+        let schema = Schema::extract::<Script>(Endianness::BigEndian).unwrap();
+        let node = Layout::Struct {
+            name: "#0".to_string(),
+            fields: vec![
+                Layout::String(SizeEncoding::Fixed(SizePolicy::U8)),
+                Layout::Array { size: SizeEncoding::Tagged, element: Box::new(Layout::Recursive("#0".to_string())) }
+            ]
+        };
+        let expected = Layout::Struct {
+            name: "Script".to_string(),
+            fields: vec![
+                Layout::Magic(b"SCRP".to_vec()),
+                Layout::Char(CharEncoding::Utf8),
+                Layout::Array {
+                    size: SizeEncoding::Fixed(SizePolicy::U16),
+                    element: Box::new(Layout::Tagged {
+                        name: "Command".to_string(),
+                        tag: SizePolicy::U8,
+                        variants: vec![
+                            Variant { tag: 0, fields: vec![] },
+                            Variant { tag: 1, fields: vec![Layout::I16, Layout::I16] },
+                            Variant { tag: 5, fields: vec![Layout::Cp866String] }
+                        ]
+                    })
+                },
+                Layout::Checksummed {
+                    algorithm: ChecksumAlgorithm::Crc32,
+                    fields: vec![Layout::Array {
+                        size: SizeEncoding::Tagged,
+                        element: Box::new(Layout::Struct {
+                            name: String::new(),
+                            fields: vec![Layout::U16, Layout::String(SizeEncoding::Tagged)]
+                        })
+                    }]
+                },
+                Layout::Frame(vec![node]),
+                Layout::Align(4),
+                Layout::Bool
+            ]
+        };
+        assert_eq!(schema, Schema { endianness: Endianness::BigEndian, root: expected });
+    }
+
+    #[derive(Clone, Default)]
+    struct Unlisted(u8);
+
+    impl TaggedReflectable for Unlisted {
+        fn get_size_policy() -> SizePolicy {
+            SizePolicy::U8
+        }
+
+        fn get_tag(&self) -> u64 {
+            0
+        }
+
+        fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            _tag: u64,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u8(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn test_tagged_types_must_list_variants() {
+        #[derive(Clone, Default)]
+        struct Holder(Unlisted);
+
+        impl Reflectable for Holder {
+            fn reflect<TSerializationReflector: SerializationReflector>(
+                &mut self,
+                reflector: &mut TSerializationReflector
+            ) -> std::io::Result<()> {
+                reflector.reflect_tagged_composite(&mut self.0)
+            }
+        }
+
+        assert!(Schema::extract::<Holder>(Endianness::LittleEndian).is_err());
+    }
+
+    #[test]
+    fn test_schema_serialization() {
+        let mut schema = Schema::extract::<Script>(Endianness::LittleEndian).unwrap();
+        let mut bytes = Vec::new();
+        schema.serialize(&mut bytes, Endianness::LittleEndian).unwrap();
+        let schema1 = Schema::deserialize(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+        assert_eq!(schema, schema1);
+        assert!(!bytes.windows(2).any(|window| window == b"::"));
+
+        let mut schema_of_schema = Schema::extract::<Schema>(Endianness::LittleEndian).unwrap();
+        let mut bytes = Vec::new();
+        schema_of_schema.serialize(&mut bytes, Endianness::BigEndian).unwrap();
+        let schema_of_schema1 = Schema::deserialize(&mut &bytes[..], Endianness::BigEndian).unwrap();
+        assert_eq!(schema_of_schema, schema_of_schema1);
+    }
+}
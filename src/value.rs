@@ -0,0 +1,160 @@
+use crate::{TaggedReflectable, SerializationReflector, SizePolicy};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Array(Vec<Value>),
+    Struct(Vec<Value>),
+    Tagged(u64, Box<Value>)
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Struct(Vec::new())
+    }
+}
+
+impl Value {
+    pub(crate) fn same_as(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::F32(a), Value::F32(b)) => a.to_bits() == b.to_bits(),
+            (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            (Value::Array(a), Value::Array(b)) | (Value::Struct(a), Value::Struct(b)) =>
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.same_as(b)),
+            (Value::Tagged(tag_a, a), Value::Tagged(tag_b, b)) => tag_a == tag_b && a.same_as(b),
+            _ => self == other
+        }
+    }
+}
+
+impl TaggedReflectable for Value {
+    fn get_size_policy() -> SizePolicy {
+        SizePolicy::U8
+    }
+
+    fn get_tag(&self) -> u64 {
+        match self {
+            Value::U8(_) => 0,
+            Value::U16(_) => 1,
+            Value::U32(_) => 2,
+            Value::U64(_) => 3,
+            Value::U128(_) => 4,
+            Value::I8(_) => 5,
+            Value::I16(_) => 6,
+            Value::I32(_) => 7,
+            Value::I64(_) => 8,
+            Value::I128(_) => 9,
+            Value::F32(_) => 10,
+            Value::F64(_) => 11,
+            Value::String(_) => 12,
+            Value::Array(_) => 13,
+            Value::Struct(_) => 14,
+            Value::Tagged(_, _) => 15
+        }
+    }
+
+    fn variant_tags() -> Vec<u64> {
+        (0..16).collect()
+    }
+
+    fn reflect_tagged<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        tag: u64,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        if self.get_tag() != tag {
+            *self = match tag {
+                0 => Value::U8(0),
+                1 => Value::U16(0),
+                2 => Value::U32(0),
+                3 => Value::U64(0),
+                4 => Value::U128(0),
+                5 => Value::I8(0),
+                6 => Value::I16(0),
+                7 => Value::I32(0),
+                8 => Value::I64(0),
+                9 => Value::I128(0),
+                10 => Value::F32(0.0),
+                11 => Value::F64(0.0),
+                12 => Value::String(String::new()),
+                13 => Value::Array(Vec::new()),
+                14 => Value::Struct(Vec::new()),
+                15 => Value::Tagged(0, Box::default()),
+                _ => return Err(std::io::Error::other("unknown value tag"))
+            };
+        }
+        match self {
+            Value::U8(v) => reflector.reflect_u8(v),
+            Value::U16(v) => reflector.reflect_u16(v),
+            Value::U32(v) => reflector.reflect_u32(v),
+            Value::U64(v) => reflector.reflect_u64(v),
+            Value::U128(v) => reflector.reflect_u128(v),
+            Value::I8(v) => reflector.reflect_i8(v),
+            Value::I16(v) => reflector.reflect_i16(v),
+            Value::I32(v) => reflector.reflect_i32(v),
+            Value::I64(v) => reflector.reflect_i64(v),
+            Value::I128(v) => reflector.reflect_i128(v),
+            Value::F32(v) => reflector.reflect_f32(v),
+            Value::F64(v) => reflector.reflect_f64(v),
+            Value::String(v) => reflector.reflect_string(v),
+            Value::Array(items) | Value::Struct(items) => reflector.reflect_array_of_tagged_composites(items),
+            Value::Tagged(variant, body) => {
+                reflector.reflect_u64(variant)?;
+                reflector.reflect_tagged_composite(&mut **body)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::U8(v) => write!(f, "{}u8", v),
+            Value::U16(v) => write!(f, "{}u16", v),
+            Value::U32(v) => write!(f, "{}u32", v),
+            Value::U64(v) => write!(f, "{}u64", v),
+            Value::U128(v) => write!(f, "{}u128", v),
+            Value::I8(v) => write!(f, "{}i8", v),
+            Value::I16(v) => write!(f, "{}i16", v),
+            Value::I32(v) => write!(f, "{}i32", v),
+            Value::I64(v) => write!(f, "{}i64", v),
+            Value::I128(v) => write!(f, "{}i128", v),
+            Value::F32(v) => write!(f, "{:?}f32", v),
+            Value::F64(v) => write!(f, "{:?}f64", v),
+            Value::String(v) => write!(f, "{:?}", v),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Value::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                write!(f, "}}")
+            },
+            Value::Tagged(tag, body) => write!(f, "#{} {}", tag, body)
+        }
+    }
+}
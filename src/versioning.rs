@@ -0,0 +1,255 @@
+use crate::{Reflectable, SerializationReflector, ReflectError};
+
+pub trait VersionedReflectable: Default+Clone {
+    const LATEST_VERSION: u32;
+    fn reflect_versioned<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        version: u32,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()>;
+    fn migrate(&mut self, _from_version: u32) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Versioned<T: VersionedReflectable> {
+    pub version: u32,
+    pub data: T
+}
+
+// Starting at the latest version keeps schema extraction on the current layout, with no migration
+impl<T: VersionedReflectable> Default for Versioned<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: VersionedReflectable> Versioned<T> {
+    pub fn new(data: T) -> Self {
+        Self { version: T::LATEST_VERSION, data }
+    }
+}
+
+impl<T: VersionedReflectable> Reflectable for Versioned<T> {
+    fn reflect<TSerializationReflector: SerializationReflector>(
+        &mut self,
+        reflector: &mut TSerializationReflector
+    ) -> std::io::Result<()> {
+        reflector.reflect_u32(&mut self.version)?;
+        if self.version > T::LATEST_VERSION {
+            return Err(ReflectError::UnsupportedVersion {
+                version: self.version,
+                latest: T::LATEST_VERSION
+            }.into());
+        }
+        let outer_version = reflector.version();
+        reflector.set_version(self.version);
+        let result = self.data.reflect_versioned(self.version, reflector);
+        reflector.set_version(outer_version);
+        result?;
+        if reflector.is_reading() && self.version < T::LATEST_VERSION {
+            self.data.migrate(self.version)?;
+            self.version = T::LATEST_VERSION;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Reflectable, SerializationReflector, VersionedReflectable, Versioned, Endianness, ReflectError, Schema,
+        DynamicReader
+    };
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct MapHeader {
+        width: u16,
+        height: u16,
+        tileset: String,
+        legacy_flags: u8
+    }
+
+    impl VersionedReflectable for MapHeader {
+        const LATEST_VERSION: u32 = 3;
+
+        fn reflect_versioned<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            version: u32,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u16(&mut self.width)?;
+            reflector.reflect_u16(&mut self.height)?;
+            if version >= 2 {
+                reflector.reflect_string(&mut self.tileset)?;
+            }
+            if version < 3 {
+                reflector.reflect_u8(&mut self.legacy_flags)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct MapFile {
+        header: Versioned<MapHeader>
+    }
+
+    impl Reflectable for MapFile {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_magic(b"MAP1")?;
+            reflector.reflect_composite(&mut self.header)
+        }
+    }
+
+    #[test]
+    fn test_version_dependent_fields() {
+        let v1 = &[b'M', b'A', b'P', b'1', 0, 0, 0, 1, 0, 4, 0, 2, 7];
+        let header = MapFile::deserialize(&mut &v1[..], Endianness::BigEndian).unwrap().header;
+        assert_eq!(header.version, 3);
+        assert_eq!(header.data, MapHeader { width: 4, height: 2, tileset: String::new(), legacy_flags: 7 });
+
+        let v2 = &[b'M', b'A', b'P', b'1', 0, 0, 0, 2, 0, 4, 0, 2, 1, 2, b'd', b'n', 7];
+        let header = MapFile::deserialize(&mut &v2[..], Endianness::BigEndian).unwrap().header;
+        assert_eq!(header.data.tileset, "dn");
+        assert_eq!(header.data.legacy_flags, 7);
+
+        let mut latest = MapFile {
+            header: Versioned::new(MapHeader { width: 4, height: 2, tileset: "dn".to_string(), legacy_flags: 7 })
+        };
+        let mut stream_vec = Vec::new();
+        latest.serialize(&mut stream_vec, Endianness::BigEndian).unwrap();
+        assert_eq!(stream_vec, vec![b'M', b'A', b'P', b'1', 0, 0, 0, 3, 0, 4, 0, 2, 1, 2, b'd', b'n']);
+    }
+
+    #[test]
+    fn test_schema_of_latest_version() {
+        let schema = Schema::extract::<MapFile>(Endianness::BigEndian).unwrap();
+        let v3 = &[b'M', b'A', b'P', b'1', 0, 0, 0, 3, 0, 4, 0, 2, 1, 2, b'd', b'n'];
+        let mut stream = &v3[..];
+        DynamicReader::new(&mut stream, &schema, Default::default()).read().unwrap();
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn test_bad_magic_and_future_version() {
+        let bad_magic = &[b'M', b'A', b'P', b'2', 0, 0, 0, 1, 0, 4, 0, 2, 7];
+        let error = MapFile::deserialize(&mut &bad_magic[..], Endianness::BigEndian).unwrap_err();
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::BadMagic { expected: b"MAP1".to_vec(), found: b"MAP2".to_vec() })
+        );
+
+        let future = &[b'M', b'A', b'P', b'1', 0, 0, 0, 4];
+        let error = MapFile::deserialize(&mut &future[..], Endianness::BigEndian).unwrap_err();
+        assert_eq!(
+            ReflectError::from_io_error(&error),
+            Some(&ReflectError::UnsupportedVersion { version: 4, latest: 3 })
+        );
+    }
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct Inventory {
+        gold: u32,
+        slots: Vec<u16>
+    }
+
+    impl Reflectable for Inventory {
+        fn reflect<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_u32(&mut self.gold)?;
+            if reflector.version() >= 3 {
+                reflector.reflect_u16_array(&mut self.slots)?;
+            } else if reflector.is_reading() {
+                self.slots = vec![0; 4];
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Default, Clone, PartialEq, Debug)]
+    struct SaveGame {
+        name: String,
+        hp: u16,
+        max_hp: u16,
+        legacy_score: u32,
+        inventory: Inventory,
+        playtime_minutes: u32
+    }
+
+    impl VersionedReflectable for SaveGame {
+        const LATEST_VERSION: u32 = 4;
+
+        fn reflect_versioned<TSerializationReflector: SerializationReflector>(
+            &mut self,
+            _version: u32,
+            reflector: &mut TSerializationReflector
+        ) -> std::io::Result<()> {
+            reflector.reflect_string(&mut self.name)?;
+            reflector.reflect_u16(&mut self.hp)?;
+            reflector.reflect_since(2, &mut self.max_hp, 100)?;
+            reflector.reflect_until(3, &mut self.legacy_score, 0)?;
+            reflector.reflect_composite(&mut self.inventory)?;
+            reflector.reflect_since(4, &mut self.playtime_minutes, 0)
+        }
+
+        fn migrate(&mut self, from_version: u32) -> std::io::Result<()> {
+            if from_version < 3 {
+                self.inventory.gold += self.legacy_score / 10;
+                self.legacy_score = 0;
+            }
+            Ok(())
+        }
+    }
+
+    fn expected_save(max_hp: u16, gold: u32, slots: Vec<u16>, playtime_minutes: u32) -> Versioned<SaveGame> {
+        Versioned {
+            version: 4,
+            data: SaveGame {
+                name: "ann".to_string(),
+                hp: 42,
+                max_hp,
+                legacy_score: 0,
+                inventory: Inventory { gold, slots },
+                playtime_minutes
+            }
+        }
+    }
+
+    #[test]
+    fn test_historical_fixtures() {
+        let fixtures: &[(&[u8], Versioned<SaveGame>)] = &[
+            (
+                &[1, 0, 0, 0, 1, 3, b'a', b'n', b'n', 42, 0, 250, 0, 0, 0, 5, 0, 0, 0],
+                expected_save(100, 30, vec![0; 4], 0)
+            ),
+            (
+                &[2, 0, 0, 0, 1, 3, b'a', b'n', b'n', 42, 0, 120, 0, 250, 0, 0, 0, 5, 0, 0, 0],
+                expected_save(120, 30, vec![0; 4], 0)
+            ),
+            (
+                &[3, 0, 0, 0, 1, 3, b'a', b'n', b'n', 42, 0, 120, 0, 5, 0, 0, 0, 1, 2, 7, 0, 9, 0],
+                expected_save(120, 5, vec![7, 9], 0)
+            ),
+            (
+                &[4, 0, 0, 0, 1, 3, b'a', b'n', b'n', 42, 0, 120, 0, 5, 0, 0, 0, 1, 2, 7, 0, 9, 0, 90, 0, 0, 0],
+                expected_save(120, 5, vec![7, 9], 90)
+            )
+        ];
+        for (bytes, expected) in fixtures {
+            let loaded = Versioned::<SaveGame>::deserialize(&mut &bytes[..], Endianness::LittleEndian).unwrap();
+            assert_eq!(&loaded, expected);
+        }
+
+        let mut latest = fixtures[3].1.clone();
+        let mut stream_vec = Vec::new();
+        latest.serialize(&mut stream_vec, Endianness::LittleEndian).unwrap();
+        assert_eq!(&stream_vec[..], fixtures[3].0);
+    }
+}
\ No newline at end of file